@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use gm4_core::animation::{self, Animation, Player, SystemClock};
+use gm4_core::device::Device;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Minimal time between two updates sent to the mouse, in milliseconds
+    #[structopt(long, default_value = "50")]
+    interval: u64,
+
+    /// Stop after this many seconds
+    #[structopt(long, parse(try_from_str = parse_seconds))]
+    duration: Option<Duration>,
+
+    #[structopt(subcommand)]
+    kind: Kind,
+}
+
+#[derive(StructOpt)]
+enum Kind {
+    /// Cycle through all hues
+    Rainbow {
+        /// Length of one cycle in seconds
+        #[structopt(long, default_value = "5", parse(try_from_str = parse_seconds))]
+        period: Duration,
+    },
+    /// Loop over a list of colors
+    Gradient {
+        /// Length of one cycle in seconds
+        #[structopt(long, default_value = "5", parse(try_from_str = parse_seconds))]
+        period: Duration,
+        #[structopt(parse(try_from_str = crate::parse_rgb), required = true)]
+        colors: Vec<[u8; 3]>,
+    },
+    /// Blend between keyframes given as `seconds=#rrggbb`, in any order
+    Sequence {
+        #[structopt(long)]
+        repeat: bool,
+        #[structopt(parse(try_from_str = parse_keyframe), required = true)]
+        frames: Vec<animation::Keyframe>,
+    },
+    /// Flash a color, then restore the previous config
+    Flash {
+        #[structopt(parse(try_from_str = crate::parse_rgb))]
+        color: [u8; 3],
+        #[structopt(long, default_value = "3")]
+        count: u32,
+        /// On and off time in milliseconds
+        #[structopt(long, default_value = "250")]
+        blink: u64,
+    },
+}
+
+/// Seconds as a `Duration`, negative, NaN and infinite values are refused
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("Invalid time: {}", s))
+}
+
+fn parse_keyframe(s: &str) -> Result<animation::Keyframe, String> {
+    let mut split = s.splitn(2, '=');
+    let at = split.next().unwrap_or_default();
+    let color = split
+        .next()
+        .ok_or_else(|| format!("Expected seconds=#rrggbb, got: {}", s))?;
+
+    Ok(animation::Keyframe {
+        at: parse_seconds(at)?,
+        color: crate::parse_rgb(color)?,
+    })
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let mut device = crate::open_device()?;
+    let config = crate::read_config(&mut device)?;
+    let original = config.clone();
+
+    let animation: Box<dyn Animation> = match opt.kind {
+        Kind::Rainbow { period } => Box::new(animation::Rainbow { period }),
+        Kind::Gradient { period, colors } => Box::new(animation::Gradient {
+            stops: colors,
+            period,
+        }),
+        Kind::Sequence { repeat, mut frames } => {
+            // `Keyframes` expects them sorted by time
+            frames.sort_by_key(|f| f.at);
+            Box::new(animation::Keyframes { frames, repeat })
+        }
+        Kind::Flash {
+            color,
+            count,
+            blink,
+        } => Box::new(animation::Flash {
+            color,
+            on: Duration::from_millis(blink),
+            off: Duration::from_millis(blink),
+            count,
+        }),
+    };
+
+    let mut player = Player::new(device, SystemClock::default(), config)
+        .with_min_interval(Duration::from_millis(opt.interval))
+        .with_stop(crate::stop_on_ctrl_c()?);
+    let res = player.play(animation.as_ref(), opt.duration);
+
+    // Leave the mouse as it was before the animation
+    let (mut device, _, _) = player.into_inner();
+    Device::send(&mut device, (&original).into())?;
+
+    Ok(res?)
+}
@@ -0,0 +1,130 @@
+use std::io::{self, BufReader, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use gm4_core::animation::{Player, SystemClock};
+use gm4_core::device::Device;
+use gm4_core::reactive::audio::{self, AudioMode, AudioSettings, PcmFormat};
+use gm4_core::reactive::{self, AudioSource, ReactiveError};
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Play a 16 bit PCM WAV file instead of listening to the default sink
+    #[structopt(long, conflicts_with = "stdin")]
+    wav: Option<PathBuf>,
+
+    /// Read raw signed 16 bit little endian PCM from stdin
+    #[structopt(long)]
+    stdin: bool,
+
+    /// Sample rate of the sink monitor or stdin
+    #[structopt(long, default_value = "44100", parse(try_from_str = parse_rate))]
+    rate: u32,
+
+    /// Channels of the sink monitor or stdin
+    #[structopt(long, default_value = "2", parse(try_from_str = parse_channels))]
+    channels: u16,
+
+    /// level, beat or spectrum
+    #[structopt(long, default_value = "level")]
+    mode: AudioMode,
+
+    /// Color for the level and beat modes
+    #[structopt(long, default_value = "#ffffff", parse(try_from_str = crate::parse_rgb))]
+    color: [u8; 3],
+
+    /// How much of the previous value is kept every 20ms, from 0 to 0.99
+    #[structopt(long, default_value = "0.6")]
+    smoothing: f64,
+
+    /// Multiplier for the loudness, raise it for quiet sources
+    #[structopt(long, default_value = "4")]
+    gain: f64,
+
+    /// Minimal time between two updates sent to the mouse, in milliseconds
+    #[structopt(long, default_value = "50")]
+    interval: u64,
+}
+
+fn parse_rate(s: &str) -> Result<u32, String> {
+    let sample_rate = s.parse().map_err(|err| format!("{}: {}", s, err))?;
+    let format = PcmFormat {
+        sample_rate,
+        ..PcmFormat::default()
+    };
+    format.validate().map(|_| sample_rate)
+}
+
+fn parse_channels(s: &str) -> Result<u16, String> {
+    let channels = s.parse().map_err(|err| format!("{}: {}", s, err))?;
+    let format = PcmFormat {
+        channels,
+        ..PcmFormat::default()
+    };
+    format.validate().map(|_| channels)
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let settings = AudioSettings {
+        mode: opt.mode,
+        color: opt.color,
+        smoothing: opt.smoothing,
+        gain: opt.gain,
+        ..AudioSettings::default()
+    };
+    let format = PcmFormat {
+        sample_rate: opt.rate,
+        channels: opt.channels,
+    };
+
+    let mut monitor = None;
+    // Live input paces itself, a file is played back in real time
+    let (input, format, realtime): (Box<dyn Read>, _, _) = if let Some(path) = &opt.wav {
+        let mut file = BufReader::new(
+            std::fs::File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?,
+        );
+        let format = audio::read_wav_header(&mut file)?;
+        (Box::new(file), format, true)
+    } else if opt.stdin {
+        (Box::new(io::stdin()), format, false)
+    } else {
+        let mut child = audio::spawn_monitor(format)
+            .map_err(|err| format!("Could not start parec: {}", err))?;
+        let stdout = child.stdout.take().unwrap();
+        monitor = Some(child);
+        (Box::new(stdout), format, false)
+    };
+
+    let mut source = AudioSource::new(input, format, settings);
+    let poll = if realtime {
+        source.window_duration()
+    } else {
+        Duration::from_secs(0)
+    };
+
+    let mut device = crate::open_device()?;
+    let config = crate::read_config(&mut device)?;
+    let original = config.clone();
+
+    // Ctrl-C also ends parec, which ends the input, so the read in `follow` returns
+    let mut player = Player::new(device, SystemClock::default(), config)
+        .with_min_interval(Duration::from_millis(opt.interval))
+        .with_stop(crate::stop_on_ctrl_c()?);
+    let res = reactive::follow(&mut player, &mut source, poll, None);
+
+    if let Some(mut child) = monitor {
+        child.kill().ok();
+        child.wait().ok();
+    }
+
+    // Leave the mouse as it was before, also after Ctrl-C
+    let (mut device, _, _) = player.into_inner();
+    Device::send(&mut device, (&original).into())?;
+
+    match res {
+        Err(ReactiveError::Source(err)) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(()),
+        res => Ok(res?),
+    }
+}
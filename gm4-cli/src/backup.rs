@@ -0,0 +1,86 @@
+use std::io::{self, BufRead, Write};
+
+use structopt::StructOpt;
+
+use gm4_core::backup::{self, Backups};
+use gm4_core::device::Device;
+
+#[derive(StructOpt)]
+pub struct RestoreOpt {
+    /// File name in the backup directory or path of a snapshot, the config the mouse
+    /// had when it was first connected by default
+    snapshot: Option<String>,
+
+    /// Print the available snapshots instead
+    #[structopt(long, conflicts_with = "snapshot")]
+    list: bool,
+}
+
+#[derive(StructOpt)]
+pub struct FactoryResetOpt {
+    /// Don't ask before replacing the config of the mouse
+    #[structopt(short, long)]
+    yes: bool,
+}
+
+fn backups(device: &dyn Device) -> crate::Result<Backups> {
+    Ok(Backups::open(&backup::mouse_id(
+        device.serial().as_deref(),
+    ))?)
+}
+
+pub fn backup() -> crate::Result<()> {
+    let mut device = crate::open_device()?;
+    let config_data = device.read()?;
+
+    let path = backups(&device)?.save(&config_data)?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+pub fn restore(opt: RestoreOpt) -> crate::Result<()> {
+    let mut device = crate::open_device()?;
+    let backups = backups(&device)?;
+
+    if opt.list {
+        for path in backups.list()? {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    let path = match &opt.snapshot {
+        Some(name) => backups.resolve(name),
+        None => backups.original(),
+    };
+    let config_data = backup::load(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    device.send(&config_data)?;
+    crate::record("gm4-cli restore", &config_data);
+    Ok(())
+}
+
+/// Asks on stderr, anything but `y` or `yes` is a no
+fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+pub fn factory_reset(opt: FactoryResetOpt) -> crate::Result<()> {
+    // A dry run changes nothing, so there's nothing to confirm
+    if !opt.yes
+        && !gm4_core::backend::dry_run()
+        && !confirm("Replace the config of the mouse with the factory one?")?
+    {
+        return Err("Factory reset cancelled".into());
+    }
+
+    let mut device = crate::open_device()?;
+    device.send(&backup::factory())?;
+    crate::record("gm4-cli factory-reset", &backup::factory());
+    Ok(())
+}
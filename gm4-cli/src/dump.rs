@@ -0,0 +1,32 @@
+use std::io::IsTerminal;
+
+use structopt::StructOpt;
+
+use gm4_core::backup;
+use gm4_core::protocol::dump;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Name every known byte and mark the unknown ones
+    #[structopt(long)]
+    annotate: bool,
+
+    /// Dump a snapshot, as saved by `backup`, instead of the mouse
+    #[structopt(long)]
+    file: Option<String>,
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let config_data = match &opt.file {
+        Some(path) => backup::load(path).map_err(|err| format!("{}: {}", path, err))?,
+        None => crate::open_device()?.read()?,
+    };
+
+    if opt.annotate {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        print!("{}", dump::annotated(&config_data, color));
+    } else {
+        print!("{}", dump::hexdump(&config_data));
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use structopt::StructOpt;
+
+use gm4_core::protocol::led::{self, blink, breathe, neon, steady};
+
+#[derive(StructOpt)]
+pub struct Opt {
+    #[structopt(subcommand)]
+    mode: Mode,
+}
+
+/// Values are given in their unit, e.g. `85%`, `2.5s`, `3hz` or `5x`
+#[derive(StructOpt)]
+enum Mode {
+    /// Constant light
+    Steady {
+        /// 5% to 85% in steps of 10, or 100%, 85% by default
+        #[structopt(long)]
+        brightness: Option<steady::Brightnes>,
+    },
+    /// Fade in and out
+    Breathe {
+        /// Length of one breath, 4s to 8s, 4s by default
+        #[structopt(long)]
+        speed: Option<breathe::Speed>,
+    },
+    /// Blink a few times, then play an effect
+    Blink {
+        /// 1hz to 10hz, 1hz by default
+        #[structopt(long)]
+        frequency: Option<blink::Frequency>,
+        /// 1x to 10x, 3x by default
+        #[structopt(long)]
+        times: Option<blink::Times>,
+        #[structopt(flatten)]
+        effect: EffectOpt,
+    },
+    /// Stay steady for a while, then play an effect
+    SteadyEffect {
+        /// 0.5s to 5s in steps of 0.5s, 1s by default
+        #[structopt(long)]
+        time: Option<steady::EffectTime>,
+        #[structopt(flatten)]
+        effect: EffectOpt,
+    },
+}
+
+/// The effect played after blinking or the steady phase, breathing at 4s by default
+#[derive(StructOpt)]
+struct EffectOpt {
+    /// Breathe, with the length of one breath
+    #[structopt(long, conflicts_with_all = &["neon", "steady"])]
+    breathe: Option<breathe::Speed>,
+    /// Cycle through the colors, 0.5s to 7.5s in steps of 0.5s
+    #[structopt(long, conflicts_with = "steady")]
+    neon: Option<neon::Speed>,
+    /// Constant light with this brightness
+    #[structopt(long)]
+    steady: Option<steady::Brightnes>,
+}
+
+impl EffectOpt {
+    fn effect(&self) -> led::Effect {
+        match (self.breathe, self.neon, self.steady) {
+            (_, Some(speed), _) => led::Effect::Neon(speed),
+            (_, _, Some(brightnes)) => led::Effect::Steady(brightnes),
+            (speed, _, _) => led::Effect::Respiration(speed.unwrap_or_default()),
+        }
+    }
+}
+
+impl Mode {
+    fn config(&self) -> led::Config {
+        match self {
+            Mode::Steady { brightness } => led::Config::Steady(brightness.unwrap_or_default()),
+            Mode::Breathe { speed } => led::Config::Breathe(speed.unwrap_or_default()),
+            Mode::Blink {
+                frequency,
+                times,
+                effect,
+            } => led::Config::BlinkEffect(
+                frequency.unwrap_or_default(),
+                times.unwrap_or_default(),
+                effect.effect(),
+            ),
+            Mode::SteadyEffect { time, effect } => {
+                led::Config::SteadyEffect(time.unwrap_or_default(), effect.effect())
+            }
+        }
+    }
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let mut device = crate::open_device()?;
+    let mut config = crate::read_config(&mut device)?;
+
+    config.set_led_effect(opt.mode.config());
+
+    let config_data = (&config).into();
+    device.send(config_data)?;
+    crate::record("gm4-cli effect", config_data);
+    Ok(())
+}
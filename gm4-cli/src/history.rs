@@ -0,0 +1,58 @@
+use structopt::StructOpt;
+
+use gm4_core::device::Device;
+use gm4_core::history::{self, Journal};
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Only show the last N entries
+    #[structopt(long)]
+    last: Option<usize>,
+}
+
+#[derive(StructOpt)]
+pub struct RollbackOpt {
+    /// Entry to apply again, as listed by `history`
+    id: u64,
+}
+
+pub fn list(opt: Opt) -> crate::Result<()> {
+    let entries = Journal::open_default()?.entries()?;
+    let skip = opt
+        .last
+        .map_or(0, |last| entries.len().saturating_sub(last));
+
+    for (n, entry) in entries.iter().enumerate().skip(skip) {
+        let changes = match n.checked_sub(1) {
+            None => "first entry".to_owned(),
+            Some(prev) => {
+                let changes = history::changes(&entries[prev].config_data, &entry.config_data);
+                if changes.is_empty() {
+                    "no changes".to_owned()
+                } else {
+                    changes.join(", ")
+                }
+            }
+        };
+
+        println!(
+            "{:>5}  {}  {:<20} {}",
+            entry.id,
+            history::format_time(entry.at),
+            entry.source,
+            changes
+        );
+    }
+    Ok(())
+}
+
+pub fn rollback(opt: RollbackOpt) -> crate::Result<()> {
+    let entry = Journal::open_default()?
+        .get(opt.id)?
+        .ok_or_else(|| format!("No history entry {}", opt.id))?;
+
+    let mut device = crate::open_device()?;
+    device.send(&entry.config_data)?;
+    crate::record("gm4-cli rollback", &entry.config_data);
+    Ok(())
+}
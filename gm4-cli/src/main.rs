@@ -1,3 +1,125 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use structopt::StructOpt;
+
+use gm4_core::device::Device;
+
+mod animate;
+mod audio;
+mod backup;
+mod dump;
+mod effect;
+mod history;
+mod reactive;
+mod script;
+mod simulate;
+
+#[derive(StructOpt)]
+#[structopt(name = "gm4-cli", about = "Modecom MC-GM4 configuration tool")]
+struct Opt {
+    /// Print the reports that would be sent, with a diff against the mouse, instead of
+    /// sending them
+    #[structopt(long, global = true)]
+    dry_run: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Play a host side LED animation
+    Animate(animate::Opt),
+    /// Drive the LED color from sound, the default sink, a WAV file or stdin
+    Audio(audio::Opt),
+    /// Save the config stored in the mouse to ~/.local/share/gm4
+    Backup,
+    /// Send the config from a backup, the one the mouse had when first connected by default
+    Restore(backup::RestoreOpt),
+    /// Send the config the mouse comes with from the factory, asks first unless `--yes`
+    /// is given
+    FactoryReset(backup::FactoryResetOpt),
+    /// Print the raw config report in the layout of gm4-protocol-doc
+    Dump(dump::Opt),
+    /// Set the LED effect, e.g. `effect blink --frequency 3hz --times 5x --neon 2.5s`
+    Effect(effect::Opt),
+    /// List the configs applied so far, from every tool
+    History(history::Opt),
+    /// Apply a config from the history again
+    Rollback(history::RollbackOpt),
+    /// Drive the LED color from the state of the system
+    Reactive(reactive::Opt),
+    /// Run a Rhai script, see the `gm4_core::script` docs for its API
+    Script(script::Opt),
+    /// Print a text preview of the LED effect stored in the mouse
+    Simulate(simulate::Opt),
+}
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Connects through the backend named by `GM4_BACKEND`, hidraw or libusb by default
+pub fn open_device() -> Result<Box<dyn Device + Send>> {
+    gm4_core::backend::open_from_env()
+        .map_err(|err| format!("Could not connect to the mouse: {}", err).into())
+}
+
+/// Adds an applied config to the history, a failure only gets a warning
+pub fn record(source: &str, config_data: &gm4_core::protocol::ConfigData) {
+    if gm4_core::backend::dry_run() {
+        return;
+    }
+    if let Err(err) = gm4_core::history::record(source, config_data) {
+        eprintln!("Could not write the history: {}", err);
+    }
+}
+
+/// Flag set by Ctrl-C, so endless loops can end and put the original config back
+pub fn stop_on_ctrl_c() -> Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed))
+        .map_err(|err| format!("Could not handle Ctrl-C: {}", err))?;
+    Ok(stop)
+}
+
+pub fn read_config(device: &mut impl Device) -> Result<gm4_core::config::Config> {
+    let raw_data = device.read()?;
+    gm4_core::config::Config::try_new(raw_data)
+        .map_err(|err| format!("Unsupported config in the mouse: {}", err).into())
+}
+
+/// Parse `#rrggbb`, `rrggbb` or `r,g,b`, see `config::Rgb`
+pub fn parse_rgb(s: &str) -> std::result::Result<[u8; 3], String> {
+    s.parse::<gm4_core::config::Rgb>()
+        .map(|rgb| rgb.0)
+        .map_err(|err| err.to_string())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let opt = Opt::from_args();
+    if opt.dry_run {
+        // Picked up by `backend::open`, before any thread is started
+        std::env::set_var(gm4_core::backend::DRY_RUN_ENV, "1");
+    }
+
+    let res = match opt.command {
+        Command::Animate(opt) => animate::run(opt),
+        Command::Audio(opt) => audio::run(opt),
+        Command::Backup => backup::backup(),
+        Command::Restore(opt) => backup::restore(opt),
+        Command::FactoryReset(opt) => backup::factory_reset(opt),
+        Command::Dump(opt) => dump::run(opt),
+        Command::Effect(opt) => effect::run(opt),
+        Command::History(opt) => history::list(opt),
+        Command::Rollback(opt) => history::rollback(opt),
+        Command::Reactive(opt) => reactive::run(opt),
+        Command::Script(opt) => script::run(opt),
+        Command::Simulate(opt) => simulate::run(opt),
+    };
+
+    if let Err(err) = res {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use gm4_core::animation::{Player, SystemClock};
+use gm4_core::device::Device;
+use gm4_core::reactive::{self, Scale, Source};
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// How often the source is sampled, in milliseconds
+    #[structopt(long, default_value = "1000")]
+    poll: u64,
+
+    /// Color for the low end of the range
+    #[structopt(long, default_value = "#00ff00", parse(try_from_str = crate::parse_rgb))]
+    low: [u8; 3],
+
+    /// Color for the high end of the range
+    #[structopt(long, default_value = "#ff0000", parse(try_from_str = crate::parse_rgb))]
+    high: [u8; 3],
+
+    /// Value mapped to the low color, defaults depend on the source
+    #[structopt(long)]
+    min: Option<f64>,
+
+    /// Value mapped to the high color, defaults depend on the source
+    #[structopt(long)]
+    max: Option<f64>,
+
+    #[structopt(subcommand)]
+    source: SourceKind,
+}
+
+#[derive(StructOpt)]
+enum SourceKind {
+    /// CPU usage, 0 to 1
+    CpuLoad,
+    /// CPU temperature in °C, 40 to 90 by default
+    CpuTemp {
+        #[structopt(long, default_value = "/sys/class/thermal/thermal_zone0/temp")]
+        path: PathBuf,
+    },
+    /// Number or #rrggbb color from the last line of a file or FIFO
+    File { path: PathBuf },
+    /// Number of waiting dunst notifications, 0 to 5 by default
+    Notifications,
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let (default_min, default_max) = match opt.source {
+        SourceKind::CpuLoad | SourceKind::File { .. } => (0.0, 1.0),
+        SourceKind::CpuTemp { .. } => (40.0, 90.0),
+        SourceKind::Notifications => (0.0, 5.0),
+    };
+
+    let scale = Scale {
+        min: opt.min.unwrap_or(default_min),
+        max: opt.max.unwrap_or(default_max),
+        low: opt.low,
+        high: opt.high,
+    };
+
+    let mut source: Box<dyn Source> = match opt.source {
+        SourceKind::CpuLoad => Box::new(reactive::CpuLoad::new(scale)),
+        SourceKind::CpuTemp { path } => Box::new(reactive::CpuTemp::with_path(path, scale)),
+        SourceKind::File { path } => Box::new(reactive::FileValue::new(path, scale)),
+        SourceKind::Notifications => Box::new(reactive::CommandValue::notifications(scale)),
+    };
+
+    let mut device = crate::open_device()?;
+    let config = crate::read_config(&mut device)?;
+    let original = config.clone();
+
+    let mut player =
+        Player::new(device, SystemClock::default(), config).with_stop(crate::stop_on_ctrl_c()?);
+    let res = reactive::follow(
+        &mut player,
+        source.as_mut(),
+        Duration::from_millis(opt.poll),
+        None,
+    );
+
+    // Leave the mouse as it was before, also after Ctrl-C
+    let (mut device, _, _) = player.into_inner();
+    Device::send(&mut device, (&original).into())?;
+
+    Ok(res?)
+}
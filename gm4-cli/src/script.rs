@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use gm4_core::animation::SystemClock;
+use gm4_core::backend;
+use gm4_core::script::Script;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Rhai script to run
+    path: PathBuf,
+
+    /// File or directory the script may read, /sys and a few files in /proc like
+    /// /proc/loadavg are always allowed
+    #[structopt(long)]
+    allow_read: Vec<PathBuf>,
+
+    /// How often to check whether the mouse was plugged in or out, in milliseconds
+    #[structopt(long, default_value = "1000")]
+    poll: u64,
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let source = std::fs::read_to_string(&opt.path)
+        .map_err(|err| format!("{}: {}", opt.path.display(), err))?;
+
+    let mut script = Script::new(&source, Box::new(SystemClock::default()))?;
+    script.allow_system_info();
+    for path in &opt.allow_read {
+        script.allow_read(path);
+    }
+
+    script.attach(crate::open_device()?)?;
+    script.run()?;
+
+    if !script.has_hotplug_hooks() {
+        return Ok(());
+    }
+
+    let mut connected = true;
+    loop {
+        std::thread::sleep(Duration::from_millis(opt.poll));
+
+        match (connected, backend::is_plugged_in()) {
+            (true, false) => {
+                connected = false;
+                script.disconnected()?;
+            }
+            (false, true) => {
+                // It may take a moment until the device can be opened
+                if let Ok(device) = backend::open_from_env() {
+                    connected = true;
+                    script.connected(device)?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use structopt::StructOpt;
+
+use gm4_core::simulation;
+
+#[derive(StructOpt)]
+pub struct Opt {
+    /// Profile whose color is used
+    #[structopt(long, default_value = "0")]
+    profile: usize,
+
+    /// Length of the preview in seconds
+    #[structopt(long, default_value = "8")]
+    duration: f64,
+
+    /// Frames per second
+    #[structopt(long, default_value = "10")]
+    fps: u32,
+}
+
+pub fn run(opt: Opt) -> crate::Result<()> {
+    let mut device = crate::open_device()?;
+    let config = crate::read_config(&mut device)?;
+
+    let profile = config
+        .profiles()
+        .get(opt.profile)
+        .ok_or_else(|| format!("There is no profile {}", opt.profile))?;
+
+    println!("{:?}", config.led_config());
+
+    let frames = (opt.duration * opt.fps as f64).ceil() as u32;
+    for n in 0..frames {
+        let t = Duration::from_secs_f64(n as f64 / opt.fps as f64);
+        let frame = simulation::frame_at(config.led_config(), profile.rgb, t);
+        let [r, g, b] = frame.rgb();
+
+        println!(
+            "{:6.2}s \x1b[38;2;{};{};{}m████████\x1b[0m #{:02x}{:02x}{:02x} {:3.0}%",
+            t.as_secs_f64(),
+            r,
+            g,
+            b,
+            r,
+            g,
+            b,
+            frame.level * 100.0
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,405 @@
+//! Host side LED animations.
+//!
+//! The firmware only knows a handful of effects, everything else is done by
+//! rewriting `profiles_rgb` from the host, frame by frame.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::device::{Device, DeviceError};
+
+pub mod clock;
+
+pub use clock::{Clock, ManualClock, SystemClock};
+
+/// Default minimal time between two reports sent to the mouse
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+pub trait Animation {
+    /// Color at time `t` since the start of the animation,
+    /// `None` once the animation is over.
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]>;
+}
+
+/// Cycles through the whole hue circle once per `period`
+#[derive(Debug, Clone, Copy)]
+pub struct Rainbow {
+    pub period: Duration,
+}
+
+impl Animation for Rainbow {
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]> {
+        let hue = progress(t, self.period) * 360.0;
+        Some(hsv_to_rgb(hue, 1.0, 1.0))
+    }
+}
+
+/// Loops over `stops`, blending between neighbours, once per `period`
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub stops: Vec<[u8; 3]>,
+    pub period: Duration,
+}
+
+impl Animation for Gradient {
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]> {
+        let len = self.stops.len();
+        if len == 0 {
+            return None;
+        }
+
+        let pos = progress(t, self.period) * len as f64;
+        let id = pos.floor() as usize % len;
+        let next = (id + 1) % len;
+
+        Some(lerp_rgb(self.stops[id], self.stops[next], pos.fract()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub at: Duration,
+    pub color: [u8; 3],
+}
+
+/// Linear blend between keyframes, which have to be sorted by time
+#[derive(Debug, Clone)]
+pub struct Keyframes {
+    pub frames: Vec<Keyframe>,
+    pub repeat: bool,
+}
+
+impl Animation for Keyframes {
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]> {
+        let last = self.frames.last()?;
+
+        let t = if self.repeat && last.at > Duration::from_secs(0) {
+            Duration::from_secs_f64(t.as_secs_f64() % last.at.as_secs_f64())
+        } else if t > last.at {
+            return None;
+        } else {
+            t
+        };
+
+        let next = self
+            .frames
+            .iter()
+            .position(|f| f.at >= t)
+            .unwrap_or(self.frames.len() - 1);
+
+        if next == 0 {
+            return Some(self.frames[0].color);
+        }
+
+        let a = self.frames[next - 1];
+        let b = self.frames[next];
+        let span = (b.at - a.at).as_secs_f64();
+        let k = if span > 0.0 {
+            (t - a.at).as_secs_f64() / span
+        } else {
+            1.0
+        };
+
+        Some(lerp_rgb(a.color, b.color, k))
+    }
+}
+
+/// Notification flash: `count` times `color` for `on`, black for `off`
+#[derive(Debug, Clone, Copy)]
+pub struct Flash {
+    pub color: [u8; 3],
+    pub on: Duration,
+    pub off: Duration,
+    pub count: u32,
+}
+
+impl Animation for Flash {
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]> {
+        let cycle = self.on + self.off;
+        if cycle == Duration::from_secs(0) || t >= cycle * self.count {
+            return None;
+        }
+
+        let in_cycle = t.as_secs_f64() % cycle.as_secs_f64();
+        if in_cycle < self.on.as_secs_f64() {
+            Some(self.color)
+        } else {
+            Some([0, 0, 0])
+        }
+    }
+}
+
+/// Streams colors to the device, never faster than `min_interval`.
+pub struct Player<D: Device, C: Clock> {
+    device: D,
+    clock: C,
+    config: Config,
+    min_interval: Duration,
+    last_send: Option<Duration>,
+    last_color: Option<[u8; 3]>,
+    /// Ends `play` and `reactive::follow` once set, e.g. from a Ctrl-C handler
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl<D: Device, C: Clock> Player<D, C> {
+    pub fn new(device: D, clock: C, config: Config) -> Self {
+        Self {
+            device,
+            clock,
+            config,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            last_send: None,
+            last_color: None,
+            stop: None,
+        }
+    }
+
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Whether the stop flag given to `with_stop` was set
+    pub fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    pub fn device(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn into_inner(self) -> (D, C, Config) {
+        (self.device, self.clock, self.config)
+    }
+
+    /// Set color of every profile.
+    ///
+    /// Returns `false` when the update was dropped, either because nothing changed
+    /// or because the last report was sent less than `min_interval` ago.
+    pub fn set_color(&mut self, rgb: [u8; 3]) -> Result<bool, DeviceError> {
+        if self.last_color == Some(rgb) {
+            return Ok(false);
+        }
+
+        let now = self.clock.now();
+        if let Some(last) = self.last_send {
+            if now < last + self.min_interval {
+                return Ok(false);
+            }
+        }
+
+        for id in 0..self.config.profiles().len() {
+            self.config.set_profile_rgb(id, rgb);
+        }
+        self.device.send((&self.config).into())?;
+
+        self.last_send = Some(now);
+        self.last_color = Some(rgb);
+        Ok(true)
+    }
+
+    /// Play `animation` until it ends, until `limit` passes or until stopped
+    pub fn play(
+        &mut self,
+        animation: &dyn Animation,
+        limit: Option<Duration>,
+    ) -> Result<(), DeviceError> {
+        let start = self.clock.now();
+
+        loop {
+            let t = self.clock.now() - start;
+            if self.stopped() || limit.map(|l| t >= l).unwrap_or(false) {
+                break;
+            }
+
+            match animation.color_at(t) {
+                Some(rgb) => {
+                    self.set_color(rgb)?;
+                }
+                None => break,
+            }
+
+            self.clock.sleep(self.min_interval);
+        }
+
+        Ok(())
+    }
+}
+
+/// Position inside of the current period, in `0.0..1.0`
+fn progress(t: Duration, period: Duration) -> f64 {
+    let period = period.as_secs_f64();
+    if period <= 0.0 {
+        return 0.0;
+    }
+
+    (t.as_secs_f64() % period) / period
+}
+
+pub fn lerp_rgb(a: [u8; 3], b: [u8; 3], k: f64) -> [u8; 3] {
+    let k = k.clamp(0.0, 1.0);
+    let mut out = [0; 3];
+    for (o, (a, b)) in out.iter_mut().zip(a.iter().zip(b.iter())) {
+        *o = (*a as f64 + (*b as f64 - *a as f64) * k).round() as u8;
+    }
+    out
+}
+
+/// `h` in degrees, `s` and `v` in `0.0..=1.0`
+pub fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8; 3] {
+    let c = v * s;
+    let h = (h % 360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    let m = v - c;
+    [
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockDevice;
+    use crate::protocol::ConfigData;
+
+    fn player(device: &MockDevice) -> Player<MockDevice, ManualClock> {
+        Player::new(
+            device.clone(),
+            ManualClock::default(),
+            Config::new(ConfigData::default()),
+        )
+        .with_min_interval(Duration::from_millis(100))
+    }
+
+    #[test]
+    fn rainbow() {
+        let r = Rainbow {
+            period: Duration::from_secs(3),
+        };
+
+        assert_eq!(Some([255, 0, 0]), r.color_at(Duration::from_secs(0)));
+        assert_eq!(Some([0, 255, 0]), r.color_at(Duration::from_secs(1)));
+        assert_eq!(Some([0, 0, 255]), r.color_at(Duration::from_secs(2)));
+        assert_eq!(Some([255, 0, 0]), r.color_at(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn keyframes() {
+        let k = Keyframes {
+            frames: vec![
+                Keyframe {
+                    at: Duration::from_secs(0),
+                    color: [0, 0, 0],
+                },
+                Keyframe {
+                    at: Duration::from_secs(2),
+                    color: [200, 100, 0],
+                },
+            ],
+            repeat: false,
+        };
+
+        assert_eq!(Some([0, 0, 0]), k.color_at(Duration::from_secs(0)));
+        assert_eq!(Some([100, 50, 0]), k.color_at(Duration::from_secs(1)));
+        assert_eq!(Some([200, 100, 0]), k.color_at(Duration::from_secs(2)));
+        assert_eq!(None, k.color_at(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn flash() {
+        let device = MockDevice::default();
+        let mut player = player(&device);
+
+        let flash = Flash {
+            color: [255, 0, 0],
+            on: Duration::from_millis(200),
+            off: Duration::from_millis(200),
+            count: 2,
+        };
+        player.play(&flash, None).unwrap();
+
+        let colors: Vec<[u8; 3]> = device.sent().iter().map(|d| d.profiles_rgb()[0]).collect();
+        assert_eq!(vec![[255, 0, 0], [0, 0, 0], [255, 0, 0], [0, 0, 0]], colors);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let device = MockDevice::default();
+        let clock = ManualClock::default();
+        let mut player = Player::new(
+            device.clone(),
+            clock.clone(),
+            Config::new(ConfigData::default()),
+        )
+        .with_min_interval(Duration::from_millis(100));
+
+        assert!(player.set_color([1, 2, 3]).unwrap());
+        assert!(!player.set_color([4, 5, 6]).unwrap());
+
+        clock.advance(Duration::from_millis(100));
+        assert!(!player.set_color([1, 2, 3]).unwrap());
+        assert!(player.set_color([4, 5, 6]).unwrap());
+
+        assert_eq!(2, device.sent().len());
+        assert_eq!([[4, 5, 6]; 5], device.config_data().profiles_rgb());
+    }
+
+    #[test]
+    fn rainbow_limit() {
+        let device = MockDevice::default();
+        let mut player = player(&device);
+
+        let rainbow = Rainbow {
+            period: Duration::from_secs(5),
+        };
+        player.play(&rainbow, Some(Duration::from_secs(1))).unwrap();
+
+        assert_eq!(10, device.sent().len());
+    }
+
+    #[test]
+    fn stop() {
+        let device = MockDevice::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut player = player(&device).with_stop(stop.clone());
+        assert!(!player.stopped());
+
+        // An endless animation only ends through the flag
+        stop.store(true, Ordering::Relaxed);
+        let rainbow = Rainbow {
+            period: Duration::from_secs(5),
+        };
+        player.play(&rainbow, None).unwrap();
+
+        assert!(device.sent().is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! tokio facade over the blocking backends.
+//!
+//! Every transfer runs on the blocking thread pool. The device sits behind an async
+//! mutex, so concurrent callers take turns in the order they asked, and a caller that
+//! gives up waiting can't leave a transfer half done.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::backend::{self, Backend};
+use crate::device::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+fn join_error(err: tokio::task::JoinError) -> DeviceError {
+    DeviceError::Io(io::Error::other(format!("Device task failed: {}", err)))
+}
+
+/// Cloneable handle to the mouse, clones share the device
+#[derive(Clone)]
+pub struct AsyncDevice {
+    device: Arc<Mutex<Box<dyn Device + Send>>>,
+}
+
+impl AsyncDevice {
+    pub fn new(device: Box<dyn Device + Send>) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    /// `backend::open` off the async threads
+    pub async fn open(backend: Backend) -> Result<Self, DeviceError> {
+        let device = tokio::task::spawn_blocking(move || backend::open(backend))
+            .await
+            .map_err(join_error)??;
+        Ok(Self::new(device))
+    }
+
+    /// Runs `f` with exclusive access to the device on the blocking thread pool
+    pub async fn with<T, F>(&self, f: F) -> Result<T, DeviceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Device) -> Result<T, DeviceError> + Send + 'static,
+    {
+        // The guard moves into the blocking task, the device stays locked until the
+        // transfer is over even when the caller is dropped
+        let mut device = self.device.clone().lock_owned().await;
+        tokio::task::spawn_blocking(move || f(&mut **device))
+            .await
+            .map_err(join_error)?
+    }
+
+    pub async fn read(&self) -> Result<ConfigData, DeviceError> {
+        self.with(|device| device.read()).await
+    }
+
+    pub async fn send(&self, config_data: ConfigData) -> Result<(), DeviceError> {
+        self.with(move |device| device.send(&config_data)).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotplug {
+    Arrived,
+    Left,
+}
+
+/// Checks every `interval` whether the mouse is plugged in.
+///
+/// The first event is the state at the start, after that only changes are sent.
+/// Polling stops when the receiver is dropped. Needs a runtime with the time driver.
+pub fn hotplug(interval: Duration) -> mpsc::Receiver<Hotplug> {
+    let (tx, rx) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        let mut plugged_in = None;
+        loop {
+            let now = match tokio::task::spawn_blocking(backend::is_plugged_in).await {
+                Ok(now) => now,
+                Err(_) => return,
+            };
+
+            if plugged_in != Some(now) {
+                plugged_in = Some(now);
+                let event = if now { Hotplug::Arrived } else { Hotplug::Left };
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = tx.closed() => return,
+            }
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockDevice;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Fails when two transfers overlap
+    struct Exclusive {
+        inner: MockDevice,
+        busy: Arc<AtomicBool>,
+    }
+
+    impl Exclusive {
+        fn transfer<T>(&mut self, f: impl FnOnce(&mut MockDevice) -> T) -> T {
+            assert!(
+                !self.busy.swap(true, Ordering::SeqCst),
+                "Concurrent transfer"
+            );
+            std::thread::sleep(Duration::from_millis(2));
+            let res = f(&mut self.inner);
+            self.busy.store(false, Ordering::SeqCst);
+            res
+        }
+    }
+
+    impl Device for Exclusive {
+        fn read(&mut self) -> Result<ConfigData, DeviceError> {
+            self.transfer(|device| device.read())
+        }
+
+        fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+            self.transfer(|device| device.send(config_data))
+        }
+    }
+
+    #[test]
+    fn serialized_transfers() {
+        let mock = MockDevice::default();
+        let device = AsyncDevice::new(Box::new(Exclusive {
+            inner: mock.clone(),
+            busy: Arc::default(),
+        }));
+
+        // Transfers run on the blocking pool, so they could overlap even here
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let tasks: Vec<_> = (0..8)
+                .map(|n| {
+                    let device = device.clone();
+                    tokio::spawn(async move {
+                        if n & 1 == 0 {
+                            device.read().await.map(|_| ())
+                        } else {
+                            device.send(ConfigData::default()).await
+                        }
+                    })
+                })
+                .collect();
+
+            for task in tasks {
+                task.await.unwrap().unwrap();
+            }
+        });
+
+        assert_eq!(4, mock.reads());
+        assert_eq!(4, mock.sent().len());
+    }
+}
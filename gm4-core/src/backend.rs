@@ -0,0 +1,149 @@
+//! Picks the transport to the mouse at runtime.
+
+use std::io;
+use std::str::FromStr;
+
+use crate::backup::{self, Backups, FirstConnect};
+use crate::device::{Device, DeviceError, DryRun, LoggedDevice, TransferLog};
+
+/// Overrides the backend `open_from_env` uses: `auto`, `hidraw` or `usb`
+pub const BACKEND_ENV: &str = "GM4_BACKEND";
+/// When set to anything but `0`, `open` prints reports instead of sending them
+pub const DRY_RUN_ENV: &str = "GM4_DRY_RUN";
+/// When set, `open` logs every report transfer to the file it names, see `device::log`
+pub const LOG_ENV: &str = "GM4_USB_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    /// hidraw when it is available, libusb otherwise
+    #[default]
+    Auto,
+    /// `/dev/hidrawN`, keeps the kernel driver bound
+    Hidraw,
+    /// libusb, detaches the kernel driver for every transfer
+    Usb,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Backend::Auto),
+            "hidraw" => Ok(Backend::Hidraw),
+            "usb" => Ok(Backend::Usb),
+            _ => Err(format!("Unknown backend: {}", s)),
+        }
+    }
+}
+
+#[cfg(any(
+    not(all(feature = "hidraw", target_os = "linux")),
+    not(feature = "usb")
+))]
+fn unsupported(name: &str) -> DeviceError {
+    DeviceError::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Built without {} support", name),
+    ))
+}
+
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+fn open_hidraw() -> Result<Box<dyn Device + Send>, DeviceError> {
+    Ok(Box::new(crate::hidraw::HidrawDevice::new()?))
+}
+
+#[cfg(not(all(feature = "hidraw", target_os = "linux")))]
+fn open_hidraw() -> Result<Box<dyn Device + Send>, DeviceError> {
+    Err(unsupported("hidraw"))
+}
+
+#[cfg(feature = "usb")]
+fn open_usb() -> Result<Box<dyn Device + Send>, DeviceError> {
+    Ok(Box::new(crate::usb::MouseDevice::new()?))
+}
+
+#[cfg(not(feature = "usb"))]
+fn open_usb() -> Result<Box<dyn Device + Send>, DeviceError> {
+    Err(unsupported("libusb"))
+}
+
+/// Whether `GM4_DRY_RUN` is on
+pub fn dry_run() -> bool {
+    matches!(std::env::var_os(DRY_RUN_ENV), Some(value) if value != "0")
+}
+
+/// Connects to the mouse, `Backend::Auto` falls back to libusb when there is no hidraw
+/// node for it
+///
+/// The report the mouse had when it was first seen is saved, see `backup::FirstConnect`.
+/// With `GM4_DRY_RUN` set nothing is sent, see `device::DryRun`. With `GM4_USB_LOG` set
+/// the transfers are logged, see `device::LoggedDevice`.
+pub fn open(backend: Backend) -> Result<Box<dyn Device + Send>, DeviceError> {
+    let device = match backend {
+        Backend::Hidraw => open_hidraw(),
+        Backend::Usb => open_usb(),
+        // Other hidraw errors, like a node without access, are reported instead of
+        // being hidden behind a libusb error
+        Backend::Auto => match open_hidraw() {
+            Err(DeviceError::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::Unsupported
+                ) =>
+            {
+                open_usb()
+            }
+            res => res,
+        },
+    }?;
+
+    // A log that can't be created must not keep the mouse from working
+    let device = match std::env::var_os(LOG_ENV).map(|path| (TransferLog::create(&path), path)) {
+        Some((Ok(log), _)) => Box::new(LoggedDevice::new(device, log)),
+        Some((Err(err), path)) => {
+            eprintln!("Not logging transfers to {:?}: {}", path, err);
+            device
+        }
+        None => device,
+    };
+
+    let backups = Backups::open(&backup::mouse_id(device.serial().as_deref())).ok();
+    let device = FirstConnect::new(device, backups);
+
+    if dry_run() {
+        Ok(Box::new(DryRun::new(device, Box::new(std::io::stdout()))))
+    } else {
+        Ok(Box::new(device))
+    }
+}
+
+/// Whether the mouse is plugged in, without opening it
+pub fn is_plugged_in() -> bool {
+    #[cfg(all(feature = "hidraw", target_os = "linux"))]
+    {
+        if crate::hidraw::find_node("/sys").is_ok() {
+            return true;
+        }
+    }
+
+    #[cfg(feature = "usb")]
+    {
+        if crate::usb::is_plugged_in() {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// `open` with the backend named by `GM4_BACKEND`, `Backend::Auto` when it is unset
+pub fn open_from_env() -> Result<Box<dyn Device + Send>, DeviceError> {
+    let backend = match std::env::var(BACKEND_ENV) {
+        Ok(name) => name
+            .parse()
+            .map_err(|err| DeviceError::Io(io::Error::new(io::ErrorKind::InvalidInput, err)))?,
+        Err(_) => Backend::Auto,
+    };
+    open(backend)
+}
@@ -0,0 +1,303 @@
+//! Snapshots of the config report, so experiments with unknown bytes can be undone.
+//!
+//! Every mouse gets a directory in `$XDG_DATA_HOME/gm4` (`~/.local/share/gm4`), named by
+//! its USB id and serial number. `original.bin` is the report the mouse had when it was
+//! first seen, `backup-<unix time>.bin` are taken on request. Both are the raw 154 bytes.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+const ORIGINAL: &str = "original.bin";
+
+/// What `ConfigData::default` holds, the report of a mouse fresh from the factory
+pub fn factory() -> ConfigData {
+    ConfigData::default()
+}
+
+/// Name of the backup directory of a mouse, mice without a serial number share one.
+/// The serial comes from the device, anything but `[A-Za-z0-9_-]` is replaced so it
+/// can't point outside the data directory.
+pub fn mouse_id(serial: Option<&str>) -> String {
+    match serial {
+        Some(serial) if !serial.is_empty() => {
+            let serial: String = serial
+                .chars()
+                .map(|c| match c {
+                    'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' => c,
+                    _ => '_',
+                })
+                .collect();
+            format!("258a-1007-{}", serial)
+        }
+        _ => "258a-1007".to_owned(),
+    }
+}
+
+/// Where a snapshot goes in `Backups::list`, the original, then backups by time and
+/// suffix, then other files
+fn list_order(path: &Path) -> (u8, u64, u64) {
+    let name = path
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    if path.file_name() == Some(ORIGINAL.as_ref()) {
+        return (0, 0, 0);
+    }
+
+    let mut parts = name.strip_prefix("backup-").unwrap_or("").splitn(2, '-');
+    let time = parts.next().and_then(|time| time.parse().ok());
+    let suffix = parts.next().map_or(Some(0), |suffix| suffix.parse().ok());
+    match (time, suffix) {
+        (Some(time), Some(suffix)) => (1, time, suffix),
+        _ => (2, 0, 0),
+    }
+}
+
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")),
+    }
+}
+
+/// Reads a snapshot, it has to be a whole report
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<ConfigData> {
+    let bytes = fs::read(path)?;
+    let mut raw = [0; 154];
+    if bytes.len() != raw.len() || bytes[0] != 0x04 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Not a config report snapshot",
+        ));
+    }
+    raw.copy_from_slice(&bytes);
+    Ok(ConfigData::from_bytes(raw))
+}
+
+/// Snapshots of one mouse
+#[derive(Debug, Clone)]
+pub struct Backups {
+    dir: PathBuf,
+}
+
+impl Backups {
+    /// Backups of the mouse `id` in the user's data directory
+    pub fn open(id: &str) -> io::Result<Self> {
+        let dir = data_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "Neither XDG_DATA_HOME nor HOME is set",
+            )
+        })?;
+        Ok(Self::at(dir.join("gm4").join(id)))
+    }
+
+    pub fn at<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Snapshot taken the first time the mouse was seen
+    pub fn original(&self) -> PathBuf {
+        self.dir.join(ORIGINAL)
+    }
+
+    /// Stores `config_data` as the original, unless there already is one
+    pub fn save_original(&self, config_data: &ConfigData) -> io::Result<bool> {
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.original());
+        match file {
+            Ok(mut file) => {
+                file.write_all(config_data.as_bytes())?;
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Stores a new timestamped snapshot
+    pub fn save(&self, config_data: &ConfigData) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Two backups within a second get a suffix instead of replacing each other
+        let mut path = self.dir.join(format!("backup-{}.bin", now));
+        let mut n = 1;
+        while path.exists() {
+            path = self.dir.join(format!("backup-{}-{}.bin", now, n));
+            n += 1;
+        }
+
+        fs::write(&path, config_data.as_bytes())?;
+        Ok(path)
+    }
+
+    /// Every snapshot, the original first and the others oldest first
+    pub fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let mut out: Vec<PathBuf> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension() == Some("bin".as_ref()))
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        out.sort_by_key(|path| (list_order(path), path.clone()));
+        Ok(out)
+    }
+
+    /// Snapshot path from a file name in the backup directory or any other path
+    pub fn resolve(&self, name: &str) -> PathBuf {
+        let in_dir = self.dir.join(name);
+        if !name.contains('/') && in_dir.exists() {
+            in_dir
+        } else {
+            PathBuf::from(name)
+        }
+    }
+}
+
+/// Saves the report of the mouse as `Backups::original` before anything is sent to it.
+///
+/// `backend::open` wraps every device in it. Failing to save the snapshot doesn't stop
+/// the device from working.
+pub struct FirstConnect<D> {
+    device: D,
+    backups: Option<Backups>,
+}
+
+impl<D: Device> FirstConnect<D> {
+    /// `None` turns the snapshot off
+    pub fn new(device: D, backups: Option<Backups>) -> Self {
+        Self { device, backups }
+    }
+
+    fn snapshot(&mut self, config_data: &ConfigData) {
+        if let Some(backups) = self.backups.take() {
+            backups.save_original(config_data).ok();
+        }
+    }
+}
+
+impl<D: Device> Device for FirstConnect<D> {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let config_data = self.device.read()?;
+        self.snapshot(&config_data);
+        Ok(config_data)
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        if self.backups.is_some() {
+            let original = self.device.read()?;
+            self.snapshot(&original);
+        }
+        self.device.send(config_data)
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.device.serial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockDevice;
+
+    #[test]
+    fn first_connect_snapshot() {
+        let dir = std::env::temp_dir().join(format!("gm4-backup-{}", std::process::id()));
+        let backups = Backups::at(&dir);
+
+        let mut original = *factory().as_bytes();
+        original[10] = 0x42;
+        let mock = MockDevice::new(ConfigData::from_bytes(original));
+
+        // The first send saves what was there before it
+        let mut device = FirstConnect::new(mock.clone(), Some(backups.clone()));
+        device.send(&factory()).unwrap();
+        assert_eq!(&original, load(backups.original()).unwrap().as_bytes());
+
+        // Later connections keep the first snapshot
+        let mut device = FirstConnect::new(mock, Some(backups.clone()));
+        device.read().unwrap();
+        assert_eq!(&original, load(backups.original()).unwrap().as_bytes());
+
+        let backup = backups.save(&factory()).unwrap();
+        assert_eq!(
+            vec![backups.original(), backup.clone()],
+            backups.list().unwrap()
+        );
+        assert_eq!(
+            backup,
+            backups.resolve(backup.file_name().unwrap().to_str().unwrap())
+        );
+
+        fs::write(dir.join("broken.bin"), [0x04; 10]).unwrap();
+        assert!(load(dir.join("broken.bin")).is_err());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn list_by_time() {
+        let dir = std::env::temp_dir().join(format!("gm4-backup-list-{}", std::process::id()));
+        let backups = Backups::at(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let names = [
+            "backup-10-2.bin",
+            "a.bin",
+            "backup-9.bin",
+            "backup-10-10.bin",
+            "original.bin",
+            "backup-10.bin",
+        ];
+        for name in names.iter() {
+            fs::write(dir.join(name), []).unwrap();
+        }
+
+        let list: Vec<_> = backups
+            .list()
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            vec![
+                "original.bin",
+                "backup-9.bin",
+                "backup-10.bin",
+                "backup-10-2.bin",
+                "backup-10-10.bin",
+                "a.bin",
+            ],
+            list
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn serial_id() {
+        assert_eq!("258a-1007", mouse_id(None));
+        assert_eq!("258a-1007-AB_12-x", mouse_id(Some("AB_12-x")));
+        assert_eq!("258a-1007-______etc", mouse_id(Some("../../etc")));
+        assert_eq!("258a-1007-a_b", mouse_id(Some("a\u{e9}b")));
+    }
+}
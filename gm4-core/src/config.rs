@@ -1,4 +1,59 @@
-use super::protocol::{led, ConfigData};
+use alloc::format;
+use core::fmt;
+use core::str::FromStr;
+
+use super::protocol::{error::ProtocolError, led, ConfigData};
+
+/// DPI of every sensor step, `Profile::dpi` is an index into this list
+pub const DPI_LIST: [u16; 16] = [
+    800, 750, 1000, 1200, 1600, 2000, 2400, 3000, 3200, 3500, 4000, 4500, 5000, 5500, 6000, 7200,
+];
+
+/// Index of `dpi` in `DPI_LIST`
+pub fn dpi_id(dpi: u16) -> Option<u8> {
+    DPI_LIST.iter().position(|&d| d == dpi).map(|id| id as u8)
+}
+
+/// Color of a profile, written as `#rrggbb`. Parses `#rrggbb`, `rrggbb` and `r,g,b`.
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct Rgb(pub [u8; 3]);
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError::InvalidValue(format!("color {}", s));
+        let s = s.trim();
+
+        let mut rgb = [0; 3];
+        if s.contains(',') {
+            let mut parts = s.split(',');
+            for c in rgb.iter_mut() {
+                let part = parts.next().ok_or_else(invalid)?;
+                *c = part.trim().parse().map_err(|_| invalid())?;
+            }
+            if parts.next().is_some() {
+                return Err(invalid());
+            }
+        } else {
+            let hex = s.strip_prefix('#').unwrap_or(s);
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            for (i, c) in rgb.iter_mut().enumerate() {
+                *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+            }
+        }
+        Ok(Rgb(rgb))
+    }
+}
 
 #[derive(Default, Copy, Clone, Debug)]
 pub struct Profile {
@@ -7,6 +62,30 @@ pub struct Profile {
     pub rgb: [u8; 3],
 }
 
+impl Profile {
+    /// `None` when the mouse reports a step that is not in `DPI_LIST`
+    pub fn dpi_value(&self) -> Option<u16> {
+        DPI_LIST.get(self.dpi as usize).copied()
+    }
+}
+
+/// Profiles stored in a report, unknown DPI steps are kept as they are
+pub fn profiles(raw_data: &ConfigData) -> [Profile; 5] {
+    let mut profiles = [Profile::default(); 5];
+
+    for (id, (rgb, dpi)) in raw_data
+        .profiles_rgb()
+        .iter()
+        .zip(&raw_data.profiles_dpi())
+        .enumerate()
+    {
+        profiles[id].active = dpi.0;
+        profiles[id].dpi = dpi.1;
+        profiles[id].rgb = *rgb;
+    }
+    profiles
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     raw_data: ConfigData,
@@ -23,18 +102,7 @@ impl Default for Config {
 
 impl Config {
     pub fn new(raw_data: ConfigData) -> Self {
-        let mut profiles = [Profile::default(); 5];
-
-        for (id, (rgb, dpi)) in raw_data
-            .profiles_rgb()
-            .iter()
-            .zip(&raw_data.profiles_dpi())
-            .enumerate()
-        {
-            profiles[id].active = dpi.0;
-            profiles[id].dpi = dpi.1;
-            profiles[id].rgb = *rgb;
-        }
+        let profiles = profiles(&raw_data);
 
         let led_config = led::Config::from_raw(
             raw_data.led_mode,
@@ -51,6 +119,31 @@ impl Config {
         }
     }
 
+    /// Like `new`, but an unknown LED mode or DPI step is an error instead of a panic
+    /// or a profile without a DPI value
+    pub fn try_new(raw_data: ConfigData) -> Result<Self, ProtocolError> {
+        let profiles = profiles(&raw_data);
+        if let Some(profile) = profiles.iter().find(|p| p.dpi_value().is_none()) {
+            return Err(ProtocolError::InvalidValue(format!(
+                "DPI step {}",
+                profile.dpi
+            )));
+        }
+
+        let led_config = led::Config::from_raw(
+            raw_data.led_mode,
+            raw_data.led_arg1,
+            raw_data.led_arg2,
+            raw_data.led_arg3,
+        )?;
+
+        Ok(Self {
+            raw_data,
+            profiles,
+            led_config,
+        })
+    }
+
     pub fn profiles(&self) -> &[Profile; 5] {
         &self.profiles
     }
@@ -59,6 +152,15 @@ impl Config {
         &self.led_config
     }
 
+    /// Raw report rate byte, its unit is not known yet
+    pub fn report_rate(&self) -> u8 {
+        self.raw_data.report_rate
+    }
+
+    pub fn set_report_rate(&mut self, report_rate: u8) {
+        self.raw_data.report_rate = report_rate;
+    }
+
     pub fn set_profile_dpi(&mut self, id: usize, dpi: u8) {
         self.profiles[id].dpi = dpi;
         self.raw_data.set_profile_dpi(id, dpi);
@@ -97,3 +199,19 @@ impl<'a> From<&'a Config> for &'a ConfigData {
         &c.raw_data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb() {
+        for s in ["#0a0b0c", "0A0B0C", " 10, 11,12 "].iter() {
+            assert_eq!(Ok(Rgb([10, 11, 12])), s.parse().map_err(|_| s));
+        }
+        for s in ["#0a0b0", "#ééé", "1,2", "1,2,3,4", "1,2,256", ""].iter() {
+            assert!(s.parse::<Rgb>().is_err(), "{}", s);
+        }
+        assert_eq!("#ff0010", Rgb([0xff, 0x00, 0x10]).to_string());
+    }
+}
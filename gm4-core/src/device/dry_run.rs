@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use super::{Device, DeviceError};
+use crate::history;
+use crate::protocol::{dump, ConfigData};
+
+/// Prints what would be sent instead of sending it.
+///
+/// Reads go to the wrapped device until the first send, after that they return the
+/// config that would be in the mouse, so read-modify-write code sees its own changes.
+pub struct DryRun<D> {
+    device: D,
+    out: Box<dyn Write + Send>,
+    state: Option<ConfigData>,
+}
+
+impl<D: Device> DryRun<D> {
+    pub fn new(device: D, out: Box<dyn Write + Send>) -> Self {
+        Self {
+            device,
+            out,
+            state: None,
+        }
+    }
+
+    fn report(&mut self, old: &ConfigData, new: &ConfigData) -> io::Result<()> {
+        let out = &mut self.out;
+        writeln!(out, "Dry run, not sent:")?;
+        write!(out, "{}", dump::describe(new))?;
+        writeln!(out)?;
+        // No colors, `out` may be a pipe or a file
+        write!(out, "{}", dump::annotated(new, false))?;
+        writeln!(out)?;
+
+        let diff = dump::byte_diff(old, new);
+        if diff.is_empty() {
+            writeln!(out, "Same as the mouse")?;
+        } else {
+            writeln!(out, "Changes against the mouse:")?;
+            for change in history::changes(old, new) {
+                writeln!(out, "  {}", change)?;
+            }
+            for (offset, old, new) in diff {
+                let field = dump::field_at(offset).unwrap_or("?");
+                writeln!(
+                    out,
+                    "  {:#04x} {:<24} {:02x} -> {:02x}",
+                    offset, field, old, new
+                )?;
+            }
+        }
+        writeln!(out)?;
+        out.flush()
+    }
+}
+
+impl<D: Device> Device for DryRun<D> {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        match &self.state {
+            Some(state) => Ok(state.clone()),
+            None => self.device.read(),
+        }
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let old = self.read()?;
+        self.report(&old, config_data)?;
+        self.state = Some(config_data.clone());
+        Ok(())
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.device.serial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockDevice;
+    use std::sync::{Arc, Mutex};
+
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn nothing_sent() {
+        let mock = MockDevice::default();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut device = DryRun::new(mock.clone(), Box::new(Shared(out.clone())));
+
+        let mut config = crate::config::Config::default();
+        config.set_profile_rgb(1, [0x12, 0x34, 0x56]);
+        device.send((&config).into()).unwrap();
+
+        assert!(mock.sent().is_empty());
+        // Later reads see the config that wasn't sent
+        assert_eq!(0x12, device.read().unwrap().profiles_rgb[1][0]);
+
+        let out = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(out.contains("profile 2: "));
+        assert!(out.contains("#123456"));
+        assert!(out.contains("0x67 profiles_rgb"));
+        // The annotated packet
+        assert!(out.contains("profiles_rgb[1] = #123456"));
+        assert!(out.contains("\n       ^^ ?? ?? ?? ?? ?? ?? ?? ^^ ??"));
+    }
+}
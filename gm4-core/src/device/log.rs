@@ -0,0 +1,343 @@
+//! Text log of USB control transfers, one per line:
+//!
+//! ```text
+//! 0.012 in type=0xa1 request=0x01 value=0x0304 index=1 took=0.8ms result=ok data=0400...
+//! ```
+//!
+//! The first column is seconds since the log was opened, `result` is `ok` or the error
+//! message, `data` is the hex encoded buffer. Lines starting with `#` are comments.
+//!
+//! `LoggedDevice` records the reports of any backend, each as the HID feature report
+//! transfer that carries it over USB.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+/// `(request_type, request, value, index)` of the control transfer reading the report
+pub const GET_REPORT: (u8, u8, u16, u16) = (0xa1, 0x01, 0x0304, 1);
+/// `(request_type, request, value, index)` of the control transfer sending the report
+pub const SET_REPORT: (u8, u8, u16, u16) = (0x21, 0x09, 0x0304, 1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    /// Since the log was opened
+    pub at: Duration,
+    pub direction: Direction,
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    /// Bytes received or sent, empty for a failed read
+    pub data: Vec<u8>,
+    /// Error message of a failed transfer
+    pub result: Result<(), String>,
+    pub took: Duration,
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::In => "in",
+            Direction::Out => "out",
+        };
+        write!(
+            f,
+            "{:.3} {} type={:#04x} request={:#04x} value={:#06x} index={} took={:.1}ms result=",
+            self.at.as_secs_f64(),
+            direction,
+            self.request_type,
+            self.request,
+            self.value,
+            self.index,
+            self.took.as_secs_f64() * 1000.0,
+        )?;
+
+        match &self.result {
+            Ok(()) => write!(f, "ok")?,
+            // Keeps the message a single field
+            Err(err) => write!(f, "{}", err.replace(' ', "_"))?,
+        }
+
+        write!(f, " data=")?;
+        for b in &self.data {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid transfer log line: {}", line),
+    )
+}
+
+pub(crate) fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect()
+}
+
+fn parse_int<T: TryFrom<u32>>(value: &str) -> Option<T> {
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+    T::try_from(value).ok()
+}
+
+impl FromStr for Transfer {
+    type Err = io::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let bad = || invalid(line);
+
+        let at = fields
+            .next()
+            .and_then(|at| at.parse::<f64>().ok())
+            .filter(|at| *at >= 0.0)
+            .ok_or_else(bad)?;
+        let direction = match fields.next() {
+            Some("in") => Direction::In,
+            Some("out") => Direction::Out,
+            _ => return Err(bad()),
+        };
+
+        let mut transfer = Transfer {
+            at: Duration::from_millis((at * 1000.0).round() as u64),
+            direction,
+            request_type: 0,
+            request: 0,
+            value: 0,
+            index: 0,
+            data: Vec::new(),
+            result: Ok(()),
+            took: Duration::default(),
+        };
+
+        for field in fields {
+            let (key, value) = field.split_once('=').ok_or_else(bad)?;
+            match key {
+                "type" => transfer.request_type = parse_int(value).ok_or_else(bad)?,
+                "request" => transfer.request = parse_int(value).ok_or_else(bad)?,
+                "value" => transfer.value = parse_int(value).ok_or_else(bad)?,
+                "index" => transfer.index = parse_int(value).ok_or_else(bad)?,
+                "took" => {
+                    let ms = value
+                        .strip_suffix("ms")
+                        .and_then(|ms| ms.parse::<f64>().ok())
+                        .filter(|ms| *ms >= 0.0)
+                        .ok_or_else(bad)?;
+                    transfer.took = Duration::from_micros((ms * 1000.0).round() as u64);
+                }
+                "result" if value == "ok" => transfer.result = Ok(()),
+                "result" => transfer.result = Err(value.replace('_', " ")),
+                "data" => transfer.data = parse_hex(value).ok_or_else(bad)?,
+                // Unknown keys are left for newer versions of the format
+                _ => {}
+            }
+        }
+
+        Ok(transfer)
+    }
+}
+
+/// Reads every transfer of a log
+pub fn read_log<R: BufRead>(reader: R) -> io::Result<Vec<Transfer>> {
+    let mut out = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        out.push(line.parse()?);
+    }
+    Ok(out)
+}
+
+/// Writes transfers as they happen
+pub struct TransferLog {
+    out: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl TransferLog {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out,
+            start: Instant::now(),
+        }
+    }
+
+    /// Creates or truncates `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut log = Self::new(Box::new(BufWriter::new(File::create(path)?)));
+        writeln!(log.out, "# gm4 USB transfer log")?;
+        Ok(log)
+    }
+
+    /// Time since the log was opened, for `Transfer::at`
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Appends `transfer`, flushed right away so a crash doesn't lose it
+    pub fn record(&mut self, transfer: &Transfer) -> io::Result<()> {
+        writeln!(self.out, "{}", transfer)?;
+        self.out.flush()
+    }
+}
+
+/// Logs every report read from or sent to `device`
+pub struct LoggedDevice<D> {
+    device: D,
+    log: TransferLog,
+}
+
+impl<D: Device> LoggedDevice<D> {
+    pub fn new(device: D, log: TransferLog) -> Self {
+        Self { device, log }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    fn record(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        result: Result<(), &DeviceError>,
+        start: Instant,
+    ) {
+        let (request_type, request, value, index) = match direction {
+            Direction::In => GET_REPORT,
+            Direction::Out => SET_REPORT,
+        };
+        let took = start.elapsed();
+        let transfer = Transfer {
+            at: self.log.elapsed().saturating_sub(took),
+            direction,
+            request_type,
+            request,
+            value,
+            index,
+            data: data.to_vec(),
+            result: result.map_err(|err| err.to_string()),
+            took,
+        };
+
+        // Losing the log is better than failing the transfer
+        self.log.record(&transfer).ok();
+    }
+}
+
+impl<D: Device> Device for LoggedDevice<D> {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let start = Instant::now();
+        let res = self.device.read();
+        match &res {
+            Ok(config_data) => self.record(Direction::In, config_data.as_bytes(), Ok(()), start),
+            Err(err) => self.record(Direction::In, &[], Err(err), start),
+        }
+        res
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let start = Instant::now();
+        let res = self.device.send(config_data);
+        self.record(
+            Direction::Out,
+            config_data.as_bytes(),
+            res.as_ref().map(|_| ()),
+            start,
+        );
+        res
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.device.serial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_roundtrip() {
+        let transfer = Transfer {
+            at: Duration::from_millis(1500),
+            direction: Direction::Out,
+            request_type: 0x21,
+            request: 0x09,
+            value: 0x0304,
+            index: 1,
+            data: vec![0x04, 0x00, 0xff],
+            result: Err("Pipe error".to_owned()),
+            took: Duration::from_micros(800),
+        };
+
+        let line = transfer.to_string();
+        assert_eq!(
+            "1.500 out type=0x21 request=0x09 value=0x0304 index=1 took=0.8ms result=Pipe_error data=0400ff",
+            line
+        );
+        assert_eq!(transfer, line.parse().unwrap());
+
+        let log = format!("# comment\n\n{}\n{}\n", line, line.replace("out", "in"));
+        let transfers = read_log(log.as_bytes()).unwrap();
+        assert_eq!(2, transfers.len());
+        assert_eq!(Direction::In, transfers[1].direction);
+
+        assert!("1.0 sideways".parse::<Transfer>().is_err());
+        assert!("1.0 in data=abc".parse::<Transfer>().is_err());
+    }
+
+    #[test]
+    fn logged_device_replays() {
+        use crate::device::{MockDevice, ReplayDevice};
+
+        let path = std::env::temp_dir().join(format!("gm4-log-{}.log", std::process::id()));
+        let mut device = LoggedDevice::new(
+            MockDevice::new(ConfigData::default()),
+            TransferLog::create(&path).unwrap(),
+        );
+        let mut config_data = device.read().unwrap();
+        config_data.report_rate = 0x42;
+        device.send(&config_data).unwrap();
+        drop(device);
+
+        let mut replay = ReplayDevice::open(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            ConfigData::default().as_bytes(),
+            replay.read().unwrap().as_bytes()
+        );
+        replay.send(&config_data).unwrap();
+        assert!(replay.finished());
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+#[derive(Debug, Default)]
+struct State {
+    config_data: ConfigData,
+    sent: Vec<ConfigData>,
+    reads: usize,
+    next_error: Option<DeviceError>,
+}
+
+/// In-memory stand-in for the mouse.
+///
+/// Clones share the same state, so a test can keep one handle
+/// while another one is moved into the code under test.
+#[derive(Debug, Clone, Default)]
+pub struct MockDevice {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockDevice {
+    pub fn new(config_data: ConfigData) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                config_data,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Config currently "stored" in the mouse
+    pub fn config_data(&self) -> ConfigData {
+        self.state().config_data.clone()
+    }
+
+    /// Every report sent so far, oldest first
+    pub fn sent(&self) -> Vec<ConfigData> {
+        self.state().sent.clone()
+    }
+
+    pub fn reads(&self) -> usize {
+        self.state().reads
+    }
+
+    /// Make the next read or send fail with `err`
+    pub fn fail_next(&self, err: DeviceError) {
+        self.state().next_error = Some(err);
+    }
+}
+
+impl Device for MockDevice {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let mut state = self.state();
+        if let Some(err) = state.next_error.take() {
+            return Err(err);
+        }
+
+        state.reads += 1;
+        Ok(state.config_data.clone())
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let mut state = self.state();
+        if let Some(err) = state.next_error.take() {
+            return Err(err);
+        }
+
+        state.config_data = config_data.clone();
+        state.sent.push(config_data.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use crate::protocol::ConfigData;
+
+pub mod dry_run;
+pub mod log;
+pub mod mock;
+pub mod replay;
+pub mod worker;
+
+pub use dry_run::DryRun;
+pub use log::{LoggedDevice, TransferLog};
+pub use mock::MockDevice;
+pub use replay::ReplayDevice;
+pub use worker::Worker;
+
+#[derive(Debug)]
+pub enum DeviceError {
+    #[cfg(feature = "usb")]
+    Usb(rusb::Error),
+    Io(std::io::Error),
+    #[cfg(any(feature = "usb", feature = "hidraw"))]
+    Lock(crate::lock::LockError),
+}
+
+impl std::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "usb")]
+            DeviceError::Usb(err) => write!(f, "{}", err),
+            DeviceError::Io(err) => write!(f, "{}", err),
+            #[cfg(any(feature = "usb", feature = "hidraw"))]
+            DeviceError::Lock(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+#[cfg(feature = "usb")]
+impl From<rusb::Error> for DeviceError {
+    fn from(err: rusb::Error) -> Self {
+        DeviceError::Usb(err)
+    }
+}
+
+#[cfg(any(feature = "usb", feature = "hidraw"))]
+impl From<crate::lock::LockError> for DeviceError {
+    fn from(err: crate::lock::LockError) -> Self {
+        DeviceError::Lock(err)
+    }
+}
+
+impl From<std::io::Error> for DeviceError {
+    fn from(err: std::io::Error) -> Self {
+        DeviceError::Io(err)
+    }
+}
+
+/// A report that can't be decoded
+impl From<crate::protocol::error::ProtocolError> for DeviceError {
+    fn from(err: crate::protocol::error::ProtocolError) -> Self {
+        DeviceError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Anything that can read and write the 154 byte config report.
+///
+/// Implemented by the real `usb::MouseDevice` and by `MockDevice`, so host side logic
+/// (animations, reactive lighting) can be tested without the hardware.
+pub trait Device {
+    fn read(&mut self) -> Result<ConfigData, DeviceError>;
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError>;
+
+    /// USB serial number, tells several mice apart
+    fn serial(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<D: Device + ?Sized> Device for Box<D> {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        (**self).read()
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        (**self).send(config_data)
+    }
+
+    fn serial(&self) -> Option<String> {
+        (**self).serial()
+    }
+}
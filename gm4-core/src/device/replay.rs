@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use super::log::{read_log, Direction, Transfer};
+use super::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+fn mismatch(msg: String) -> DeviceError {
+    DeviceError::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Plays a recorded USB session back.
+///
+/// Reads return the recorded reports, sends have to match the recorded ones byte for
+/// byte. Recorded failures fail again, with their message.
+pub struct ReplayDevice {
+    transfers: Vec<Transfer>,
+    next: usize,
+}
+
+impl ReplayDevice {
+    pub fn new(transfers: Vec<Transfer>) -> Self {
+        Self { transfers, next: 0 }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(read_log(BufReader::new(file))?))
+    }
+
+    /// Whether every recorded transfer was replayed
+    pub fn finished(&self) -> bool {
+        self.next == self.transfers.len()
+    }
+
+    fn next(&mut self, direction: Direction) -> Result<&Transfer, DeviceError> {
+        let id = self.next;
+        let transfer = self
+            .transfers
+            .get(id)
+            .ok_or_else(|| mismatch(format!("transfer {} is past the end of the recording", id)))?;
+
+        if transfer.direction != direction {
+            return Err(mismatch(format!(
+                "transfer {} is {:?}, the recording has {:?}",
+                id, direction, transfer.direction
+            )));
+        }
+        self.next += 1;
+
+        match &transfer.result {
+            Ok(()) => Ok(transfer),
+            Err(err) => Err(DeviceError::Io(io::Error::other(err.clone()))),
+        }
+    }
+}
+
+impl Device for ReplayDevice {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let transfer = self.next(Direction::In)?;
+
+        let mut bytes = [0; 154];
+        if transfer.data.len() != bytes.len() {
+            return Err(mismatch(format!(
+                "recorded report has {} bytes",
+                transfer.data.len()
+            )));
+        }
+        bytes.copy_from_slice(&transfer.data);
+        Ok(ConfigData::from_bytes(bytes))
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let id = self.next;
+        let transfer = self.next(Direction::Out)?;
+
+        let sent = &config_data.as_bytes()[..];
+        if let Some(pos) = (0..sent.len()).find(|&i| transfer.data.get(i) != Some(&sent[i])) {
+            return Err(mismatch(format!(
+                "transfer {} differs from the recording at byte {}",
+                id, pos
+            )));
+        }
+        // A longer recording only matches the start of it
+        if transfer.data.len() != sent.len() {
+            return Err(mismatch(format!(
+                "recorded transfer {} has {} bytes",
+                id,
+                transfer.data.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::time::Duration;
+
+    fn transfer(direction: Direction, data: &ConfigData, result: Result<(), String>) -> Transfer {
+        Transfer {
+            at: Duration::default(),
+            direction,
+            request_type: 0xa1,
+            request: 0x01,
+            value: 0x0304,
+            index: 1,
+            data: data.as_bytes().to_vec(),
+            result,
+            took: Duration::default(),
+        }
+    }
+
+    #[test]
+    fn replay() {
+        let mut changed = Config::default();
+        changed.set_profile_rgb(0, [1, 2, 3]);
+        let changed: &ConfigData = (&changed).into();
+
+        let log = [
+            transfer(Direction::In, &ConfigData::default(), Ok(())),
+            transfer(Direction::Out, changed, Ok(())),
+            transfer(Direction::Out, changed, Err("Pipe error".to_owned())),
+        ]
+        .iter()
+        .map(|t| format!("{}\n", t))
+        .collect::<String>();
+
+        let mut device = ReplayDevice::new(read_log(log.as_bytes()).unwrap());
+        let mut config = Config::new(device.read().unwrap());
+        config.set_profile_rgb(0, [1, 2, 3]);
+        device.send((&config).into()).unwrap();
+
+        let err = device.send((&config).into()).unwrap_err();
+        assert_eq!("Pipe error", err.to_string());
+        assert!(device.finished());
+        assert!(device.read().is_err());
+
+        // Sending something else than what was recorded
+        let mut device = ReplayDevice::new(read_log(log.as_bytes()).unwrap());
+        device.read().unwrap();
+        assert!(device.send(&ConfigData::default()).is_err());
+        assert!(!device.finished());
+
+        // A recording that starts with the report but goes on
+        let mut longer = transfer(Direction::Out, changed, Ok(()));
+        longer.data.push(0);
+        let mut device = ReplayDevice::new(vec![longer]);
+        assert!(device.send(changed).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Runs device transfers on their own thread, so a UI stays responsive while the
+//! mouse is slow or unplugged.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{Device, DeviceError};
+use crate::protocol::ConfigData;
+
+pub enum Command {
+    Read,
+    /// Only the last of several queued `Apply` commands is sent
+    Apply(ConfigData),
+    /// Read the config every interval and report changes made by other tools,
+    /// `None` stops watching
+    Watch(Option<Duration>),
+    /// Stop the thread once the commands sent before are done
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    Connect,
+    Read,
+    Apply,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// With the serial number of the mouse
+    Connected(Option<String>),
+    Disconnected,
+    /// `Task` started, `Done` or `Error` follows
+    Busy(Task),
+    Done(Task),
+    Error(Task, DeviceError),
+    /// Result of `Command::Read`
+    Read(ConfigData),
+    /// Config a `Command::Apply` sent, before its `Done`
+    Applied(ConfigData),
+    /// The watched config differs from what was last read or applied
+    Changed(ConfigData),
+}
+
+type Open = Box<dyn FnMut() -> Result<Box<dyn Device + Send>, DeviceError> + Send>;
+
+struct State {
+    open: Open,
+    on_event: Box<dyn FnMut(Event) + Send>,
+    device: Option<Box<dyn Device + Send>>,
+    /// Last config read from or sent to the mouse
+    last: Option<ConfigData>,
+}
+
+impl State {
+    fn emit(&mut self, event: Event) {
+        (self.on_event)(event)
+    }
+
+    /// Opens the device when needed, `quiet` skips the events of a failed attempt
+    fn connect(&mut self, quiet: bool) -> bool {
+        if self.device.is_some() {
+            return true;
+        }
+
+        if !quiet {
+            self.emit(Event::Busy(Task::Connect));
+        }
+        match (self.open)() {
+            Ok(device) => {
+                let serial = device.serial();
+                self.device = Some(device);
+                self.emit(Event::Connected(serial));
+                true
+            }
+            Err(err) => {
+                if !quiet {
+                    self.emit(Event::Error(Task::Connect, err));
+                }
+                false
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.device = None;
+        self.last = None;
+        self.emit(Event::Disconnected);
+    }
+
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let res = self.device.as_mut().unwrap().read();
+        if res.is_err() {
+            self.disconnect();
+        }
+        res
+    }
+
+    /// Sends `apply` when there is one, reads otherwise
+    fn run(&mut self, task: Task, apply: Option<ConfigData>) {
+        if !self.connect(false) {
+            return;
+        }
+
+        self.emit(Event::Busy(task));
+        let res = match apply {
+            Some(config_data) => {
+                let res = self.device.as_mut().unwrap().send(&config_data);
+                match res {
+                    Ok(()) => {
+                        self.last = Some(config_data.clone());
+                        self.emit(Event::Applied(config_data));
+                    }
+                    Err(_) => self.disconnect(),
+                }
+                res
+            }
+            None => self.read().map(|config_data| {
+                self.last = Some(config_data.clone());
+                self.emit(Event::Read(config_data));
+            }),
+        };
+
+        match res {
+            Ok(()) => self.emit(Event::Done(task)),
+            Err(err) => self.emit(Event::Error(task, err)),
+        }
+    }
+
+    fn poll(&mut self) {
+        // A missing mouse is the normal state here, don't report every attempt
+        if !self.connect(true) {
+            return;
+        }
+
+        if let Ok(config_data) = self.read() {
+            let changed = match &self.last {
+                Some(last) => last.as_bytes() != config_data.as_bytes(),
+                None => true,
+            };
+            if changed {
+                self.last = Some(config_data.clone());
+                self.emit(Event::Changed(config_data));
+            }
+        }
+    }
+}
+
+/// Handle to the device thread, dropping it stops the thread once the running
+/// transfer is done. `shutdown` also waits for the queued commands.
+pub struct Worker {
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<Option<ConfigData>>>,
+}
+
+impl Worker {
+    /// `open` is called on the worker thread whenever there is no device,
+    /// `on_event` is called there too.
+    pub fn spawn<O, E>(open: O, on_event: E) -> Self
+    where
+        O: FnMut() -> Result<Box<dyn Device + Send>, DeviceError> + Send + 'static,
+        E: FnMut(Event) + Send + 'static,
+    {
+        let (commands, rx) = mpsc::channel();
+        let state = State {
+            open: Box::new(open),
+            on_event: Box::new(on_event),
+            device: None,
+            last: None,
+        };
+        let thread = thread::spawn(move || run(state, rx));
+
+        Self {
+            commands,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        // The thread only stops when this handle is dropped
+        self.commands.send(command).ok();
+    }
+
+    pub fn read(&self) {
+        self.send(Command::Read);
+    }
+
+    pub fn apply(&self, config_data: ConfigData) {
+        self.send(Command::Apply(config_data));
+    }
+
+    pub fn watch(&self, interval: Option<Duration>) {
+        self.send(Command::Watch(interval));
+    }
+
+    /// Runs the commands sent so far, so a config applied right before quitting still
+    /// reaches the mouse, and waits for the thread to stop.
+    ///
+    /// Returns the config last read from or sent to the mouse, the events of the last
+    /// commands may not have been handled yet.
+    pub fn shutdown(&mut self) -> Option<ConfigData> {
+        self.send(Command::Shutdown);
+        self.thread.take()?.join().ok().flatten()
+    }
+}
+
+fn run(mut state: State, rx: Receiver<Command>) -> Option<ConfigData> {
+    let mut pending = VecDeque::new();
+    let mut watch = None;
+
+    loop {
+        let command = match pending.pop_front() {
+            Some(command) => command,
+            None => match watch {
+                Some(interval) => match rx.recv_timeout(interval) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => {
+                        state.poll();
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return None,
+                },
+                None => match rx.recv() {
+                    Ok(command) => command,
+                    Err(_) => return None,
+                },
+            },
+        };
+
+        match command {
+            Command::Read => state.run(Task::Read, None),
+            Command::Apply(mut config_data) => {
+                // Skip the states a slider went through while the mouse was busy
+                while let Ok(next) = rx.try_recv() {
+                    match next {
+                        Command::Apply(next) => config_data = next,
+                        next => {
+                            pending.push_back(next);
+                            break;
+                        }
+                    }
+                }
+                state.run(Task::Apply, Some(config_data));
+            }
+            Command::Watch(interval) => watch = interval,
+            Command::Shutdown => return state.last,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::MockDevice;
+
+    fn report(byte: u8) -> ConfigData {
+        let mut raw = *ConfigData::default().as_bytes();
+        raw[10] = byte;
+        ConfigData::from_bytes(raw)
+    }
+
+    #[test]
+    fn commands_and_events() {
+        let mock = MockDevice::new(report(1));
+        let device = mock.clone();
+        let (events, rx) = mpsc::channel();
+        let worker = Worker::spawn(
+            move || Ok(Box::new(device.clone()) as Box<dyn Device + Send>),
+            move |event| {
+                events.send(event).ok();
+            },
+        );
+
+        worker.read();
+        worker.apply(report(2));
+        worker.apply(report(3));
+        worker.read();
+
+        let mut read = Vec::new();
+        while read.len() < 2 {
+            match rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                Event::Read(config_data) => read.push(config_data.as_bytes()[10]),
+                Event::Error(task, err) => panic!("{:?} failed: {}", task, err),
+                _ => {}
+            }
+        }
+        assert_eq!(vec![1, 3], read);
+        // The second apply may or may not have been merged into the first
+        assert_eq!(3, mock.sent().last().unwrap().as_bytes()[10]);
+
+        // A change made behind the worker's back
+        mock.clone().send(&report(4)).unwrap();
+        worker.watch(Some(Duration::from_millis(1)));
+        loop {
+            if let Event::Changed(config_data) = rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                assert_eq!(4, config_data.as_bytes()[10]);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn shutdown_flushes() {
+        let mock = MockDevice::new(report(1));
+        let device = mock.clone();
+        let mut worker = Worker::spawn(
+            move || Ok(Box::new(device.clone()) as Box<dyn Device + Send>),
+            |_| {},
+        );
+
+        worker.apply(report(2));
+        assert_eq!(2, worker.shutdown().unwrap().as_bytes()[10]);
+        assert_eq!(2, mock.sent().last().unwrap().as_bytes()[10]);
+    }
+
+    #[test]
+    fn connect_error() {
+        let (events, rx) = mpsc::channel();
+        let worker = Worker::spawn(
+            || Err(DeviceError::Io(std::io::ErrorKind::NotFound.into())),
+            move |event| {
+                events.send(event).ok();
+            },
+        );
+
+        worker.apply(ConfigData::default());
+        assert!(matches!(rx.recv().unwrap(), Event::Busy(Task::Connect)));
+        assert!(matches!(
+            rx.recv().unwrap(),
+            Event::Error(Task::Connect, DeviceError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,295 @@
+//! Config report transport over `/dev/hidrawN`.
+//!
+//! `usb::MouseDevice` has to detach usbhid from the mouse for every transfer, so the
+//! pointer freezes while it is configured. hidraw sends the same feature report through
+//! `HIDIOCSFEATURE`/`HIDIOCGFEATURE` with the kernel driver left bound.
+
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::device::{Device, DeviceError};
+use crate::lock::{DeviceLock, LockPolicy};
+use crate::protocol::ConfigData;
+
+/// `HID_ID` line of the uevent, bus 0003 is USB
+const HID_ID: &str = "0003:0000258A:00001007";
+/// The config report is on the second interface, `wIndex` of the control transfer
+const INTERFACE: u8 = 1;
+/// Feature report 4, `wValue` 0x0304 of the control transfer
+const REPORT_ID: u8 = 0x04;
+const REPORT_LEN: usize = 154;
+
+/// The two ioctls the transport needs, replaceable to test without a device node
+pub trait Ioctl {
+    /// `buf[0]` is the report id, returns the length of the report
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+    fn set_feature(&mut self, buf: &[u8]) -> io::Result<usize>;
+}
+
+// _IOC(_IOC_READ | _IOC_WRITE, 'H', nr, len) from <linux/hidraw.h>
+fn hidioc(nr: u32, len: usize) -> u32 {
+    (3 << 30) | ((len as u32) << 16) | ((b'H' as u32) << 8) | nr
+}
+
+/// An open `/dev/hidrawN`
+pub struct HidrawFile(File);
+
+impl HidrawFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self(file))
+    }
+
+    fn ioctl(&mut self, nr: u32, buf: *mut u8, len: usize) -> io::Result<usize> {
+        // SAFETY: The kernel reads or writes at most `len` bytes of `buf`
+        let res = unsafe { libc::ioctl(self.0.as_raw_fd(), hidioc(nr, len) as _, buf) };
+        if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res as usize)
+        }
+    }
+}
+
+impl Ioctl for HidrawFile {
+    fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.ioctl(0x07, buf.as_mut_ptr(), buf.len())
+    }
+
+    fn set_feature(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // HIDIOCSFEATURE only reads the buffer
+        self.ioctl(0x06, buf.as_ptr() as *mut u8, buf.len())
+    }
+}
+
+/// Finds the hidraw node of the config interface in a sysfs tree mounted at `sys`
+pub fn find_node<P: AsRef<Path>>(sys: P) -> io::Result<PathBuf> {
+    let class = sys.as_ref().join("class/hidraw");
+
+    for entry in fs::read_dir(&class)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        let uevent = match fs::read_to_string(path.join("device/uevent")) {
+            Ok(uevent) => uevent,
+            Err(_) => continue,
+        };
+        if !uevent
+            .lines()
+            .any(|line| line.eq_ignore_ascii_case(&format!("HID_ID={}", HID_ID)))
+        {
+            continue;
+        }
+
+        // `device` links to the HID device, its parent is the USB interface
+        let interface = fs::read_to_string(path.join("device/../bInterfaceNumber"))
+            .ok()
+            .and_then(|n| u8::from_str_radix(n.trim(), 16).ok());
+        if interface == Some(INTERFACE) {
+            return Ok(Path::new("/dev").join(entry.file_name()));
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "No hidraw node for the mouse",
+    ))
+}
+
+/// USB bus and address of the device behind hidraw node `name`, the key of its `DeviceLock`
+pub fn usb_address<P: AsRef<Path>>(sys: P, name: &OsStr) -> Option<(u8, u8)> {
+    // HID device, USB interface, USB device
+    let device = sys
+        .as_ref()
+        .join("class/hidraw")
+        .join(name)
+        .join("device/../..");
+    let read = |file: &str| {
+        fs::read_to_string(device.join(file))
+            .ok()
+            .and_then(|n| n.trim().parse().ok())
+    };
+    Some((read("busnum")?, read("devnum")?))
+}
+
+/// USB serial number of the device behind hidraw node `name`
+fn usb_serial<P: AsRef<Path>>(sys: P, name: &OsStr) -> Option<String> {
+    let path = sys
+        .as_ref()
+        .join("class/hidraw")
+        .join(name)
+        .join("device/../../serial");
+    fs::read_to_string(path)
+        .ok()
+        .map(|serial| serial.trim().to_owned())
+}
+
+pub struct HidrawDevice<I = HidrawFile> {
+    ioctl: I,
+    /// Bus and address for the device lock, `None` skips locking
+    address: Option<(u8, u8)>,
+    lock_policy: LockPolicy,
+    serial: Option<String>,
+}
+
+impl HidrawDevice {
+    /// Opens the hidraw node of a plugged in mouse
+    pub fn new() -> io::Result<Self> {
+        let node = find_node("/sys")?;
+        let mut device = Self::with_ioctl(HidrawFile::open(&node)?);
+        if let Some(name) = node.file_name() {
+            device.address = usb_address("/sys", name);
+            device.serial = usb_serial("/sys", name);
+        }
+        Ok(device)
+    }
+}
+
+impl<I: Ioctl> HidrawDevice<I> {
+    pub fn with_ioctl(ioctl: I) -> Self {
+        Self {
+            ioctl,
+            address: None,
+            lock_policy: LockPolicy::from_env(),
+            serial: None,
+        }
+    }
+
+    /// What to do when another process is using the mouse, `GM4_LOCK` by default
+    pub fn set_lock_policy(&mut self, policy: LockPolicy) {
+        self.lock_policy = policy;
+    }
+
+    fn lock(&self) -> Result<Option<DeviceLock>, DeviceError> {
+        match self.address {
+            Some((bus, address)) => Ok(Some(DeviceLock::acquire(bus, address, self.lock_policy)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn short_report(len: usize) -> DeviceError {
+    DeviceError::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Feature report of {} bytes, expected {}", len, REPORT_LEN),
+    ))
+}
+
+impl<I: Ioctl> Device for HidrawDevice<I> {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let _lock = self.lock()?;
+        let mut buf = [0; REPORT_LEN];
+        buf[0] = REPORT_ID;
+
+        let len = self.ioctl.get_feature(&mut buf)?;
+        if len != REPORT_LEN {
+            return Err(short_report(len));
+        }
+
+        Ok(ConfigData::from_bytes(buf))
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let _lock = self.lock()?;
+        let mut buf = [0; REPORT_LEN];
+        buf.copy_from_slice(config_data.as_bytes());
+        buf[0] = REPORT_ID;
+
+        let len = self.ioctl.set_feature(&buf)?;
+        if len != REPORT_LEN {
+            return Err(short_report(len));
+        }
+
+        Ok(())
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.serial.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeIoctl {
+        report: [u8; REPORT_LEN],
+        len: usize,
+    }
+
+    impl Ioctl for FakeIoctl {
+        fn get_feature(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            assert_eq!(REPORT_ID, buf[0]);
+            buf.copy_from_slice(&self.report);
+            Ok(self.len)
+        }
+
+        fn set_feature(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.report.copy_from_slice(buf);
+            Ok(self.len)
+        }
+    }
+
+    #[test]
+    fn feature_report() {
+        let mut device = HidrawDevice::with_ioctl(FakeIoctl {
+            report: [0; REPORT_LEN],
+            len: REPORT_LEN,
+        });
+
+        let mut config = crate::config::Config::new(ConfigData::default());
+        config.set_report_rate(2);
+        let sent: &ConfigData = (&config).into();
+        device.send(sent).unwrap();
+        assert_eq!(REPORT_ID, device.ioctl.report[0]);
+
+        let read = device.read().unwrap();
+        assert_eq!(sent.as_bytes(), read.as_bytes());
+
+        device.ioctl.len = 64;
+        assert!(device.read().is_err());
+    }
+
+    #[test]
+    fn sysfs_lookup() {
+        use std::os::unix::fs::symlink;
+
+        let sys = std::env::temp_dir().join(format!("gm4-hidraw-{}", std::process::id()));
+        fs::create_dir_all(sys.join("class/hidraw")).unwrap();
+        // The kernel layout: the class entry links to the node below the HID device, its
+        // `device` links back up to it, so `..` goes to the USB interface and then the
+        // USB device
+        let node = |name: &str, hid_id: &str, usb: &str, devnum: u8, interface: u8| {
+            let usb_dir = sys.join("devices/usb3").join(usb);
+            let interface_dir = usb_dir.join(format!("{}:1.{}", usb, interface));
+            let hid_dir = interface_dir.join(format!("{}.0001", hid_id));
+            let node_dir = hid_dir.join("hidraw").join(name);
+            fs::create_dir_all(&node_dir).unwrap();
+
+            fs::write(usb_dir.join("busnum"), "3\n").unwrap();
+            fs::write(usb_dir.join("devnum"), format!("{}\n", devnum)).unwrap();
+            fs::write(usb_dir.join("serial"), format!("SN{}\n", devnum)).unwrap();
+            let number = format!("{:02x}\n", interface);
+            fs::write(interface_dir.join("bInterfaceNumber"), number).unwrap();
+            fs::write(hid_dir.join("uevent"), format!("HID_ID={}\n", hid_id)).unwrap();
+
+            symlink("../..", node_dir.join("device")).unwrap();
+            symlink(&node_dir, sys.join("class/hidraw").join(name)).unwrap();
+        };
+        node("hidraw0", "0003:0000046D:0000C52B", "3-1", 5, 1);
+        node("hidraw1", "0003:0000258A:00001007", "3-2", 17, 0);
+        node("hidraw2", "0003:0000258a:00001007", "3-2", 17, 1);
+
+        let found = find_node(&sys);
+        let address = usb_address(&sys, OsStr::new("hidraw2"));
+        let serial = usb_serial(&sys, OsStr::new("hidraw2"));
+        fs::remove_dir_all(&sys).ok();
+
+        assert_eq!(Path::new("/dev/hidraw2"), found.unwrap());
+        assert_eq!(Some((3, 17)), address);
+        assert_eq!(Some("SN17".to_owned()), serial);
+    }
+}
@@ -0,0 +1,402 @@
+//! Journal of every config applied to the mouse, one entry per line:
+//!
+//! ```text
+//! 12 1760000000 gm4-cli_restore 0400...
+//! ```
+//!
+//! id, unix time, source (spaces replaced by `_`) and the hex encoded report.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::device::log::parse_hex;
+use crate::protocol::dump::{dpi, effect};
+use crate::protocol::ConfigData;
+
+/// How much history to keep, e.g. `500`, `30d` or `500,30d`; `all` keeps everything
+pub const KEEP_ENV: &str = "GM4_HISTORY_KEEP";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    pub max_entries: Option<usize>,
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_entries: Some(1000),
+            max_age: None,
+        }
+    }
+}
+
+impl FromStr for Retention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut out = Retention {
+            max_entries: None,
+            max_age: None,
+        };
+        if s == "all" {
+            return Ok(out);
+        }
+
+        for part in s.split(',') {
+            let part = part.trim();
+            let bad = || format!("Invalid history retention: {}", s);
+            match part.strip_suffix('d') {
+                Some(days) => {
+                    let days: u64 = days.parse().map_err(|_| bad())?;
+                    out.max_age = Some(Duration::from_secs(days * 24 * 60 * 60));
+                }
+                None => out.max_entries = Some(part.parse().map_err(|_| bad())?),
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Retention {
+    /// Retention named by `GM4_HISTORY_KEEP`, the default when it is unset or invalid
+    pub fn from_env() -> Self {
+        std::env::var(KEEP_ENV)
+            .ok()
+            .and_then(|keep| keep.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u64,
+    /// Unix time in seconds
+    pub at: u64,
+    /// Program, and what in it, that applied the config
+    pub source: String,
+    pub config_data: ConfigData,
+}
+
+impl Entry {
+    fn to_line(&self) -> String {
+        let mut line = format!("{} {} {} ", self.id, self.at, self.source.replace(' ', "_"));
+        for b in self.config_data.as_bytes().iter() {
+            line.push_str(&format!("{:02x}", b));
+        }
+        line
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let id = fields.next()?.parse().ok()?;
+        let at = fields.next()?.parse().ok()?;
+        let source = fields.next()?.replace('_', " ");
+
+        let bytes = parse_hex(fields.next()?)?;
+        let mut raw = [0; 154];
+        if bytes.len() != raw.len() {
+            return None;
+        }
+        raw.copy_from_slice(&bytes);
+
+        Some(Self {
+            id,
+            at,
+            source,
+            config_data: ConfigData::from_bytes(raw),
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub struct Journal {
+    path: PathBuf,
+    retention: Retention,
+}
+
+impl Journal {
+    /// `history.log` next to the backups, with the retention from `GM4_HISTORY_KEEP`
+    pub fn open_default() -> io::Result<Self> {
+        let dir = crate::backup::data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+        Ok(Self::at(dir.join("gm4/history.log"), Retention::from_env()))
+    }
+
+    pub fn at<P: Into<PathBuf>>(path: P, retention: Retention) -> Self {
+        Self {
+            path: path.into(),
+            retention,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every entry, oldest first, lines that don't parse are skipped
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut out = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Some(entry) = Entry::parse(&line?) {
+                out.push(entry);
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn get(&self, id: u64) -> io::Result<Option<Entry>> {
+        Ok(self.entries()?.into_iter().find(|entry| entry.id == id))
+    }
+
+    /// `flock` on a file next to the journal, held until the returned file is dropped.
+    ///
+    /// The journal itself is replaced when it is pruned, so it can't carry the lock.
+    fn lock(&self) -> io::Result<File> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.path.with_extension("lock"))?;
+        // SAFETY: Only takes a file descriptor, which `file` keeps open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(file)
+    }
+
+    /// Adds an entry and drops the ones the retention no longer covers.
+    ///
+    /// Safe to call from several processes at once, the GUI, CLI and daemon share the journal.
+    pub fn append(&self, source: &str, config_data: &ConfigData) -> io::Result<Entry> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = self.lock()?;
+
+        let mut entries = self.entries()?;
+        let entry = Entry {
+            id: entries.last().map_or(1, |last| last.id + 1),
+            at: now(),
+            source: source.to_owned(),
+            config_data: config_data.clone(),
+        };
+
+        entries.push(entry.clone());
+        if self.prune(&mut entries, entry.at) {
+            let tmp = self.path.with_extension("tmp");
+            let mut file = File::create(&tmp)?;
+            for entry in &entries {
+                writeln!(file, "{}", entry.to_line())?;
+            }
+            file.sync_all()?;
+            fs::rename(tmp, &self.path)?;
+        } else {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            writeln!(file, "{}", entry.to_line())?;
+        }
+
+        Ok(entry)
+    }
+
+    /// Whether entries were removed
+    fn prune(&self, entries: &mut Vec<Entry>, now: u64) -> bool {
+        let len = entries.len();
+
+        if let Some(max_age) = self.retention.max_age {
+            let oldest = now.saturating_sub(max_age.as_secs());
+            entries.retain(|entry| entry.at >= oldest);
+        }
+
+        if let Some(max) = self.retention.max_entries {
+            // Trimmed in batches, so a full journal isn't rewritten on every append
+            if entries.len() > max + max / 10 {
+                entries.drain(..entries.len() - max);
+            }
+        }
+
+        entries.len() != len
+    }
+}
+
+/// Appends to the default journal
+pub fn record(source: &str, config_data: &ConfigData) -> io::Result<Entry> {
+    Journal::open_default()?.append(source, config_data)
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// What changed from `old` to `new`, field by field, reports that don't decode are fine
+pub fn changes(old: &ConfigData, new: &ConfigData) -> Vec<String> {
+    let mut out = Vec::new();
+
+    for (id, (old, new)) in config::profiles(old)
+        .iter()
+        .zip(&config::profiles(new))
+        .enumerate()
+    {
+        let id = id + 1;
+        if old.active != new.active {
+            let state = if new.active { "on" } else { "off" };
+            out.push(format!("profile {} {}", id, state));
+        }
+        if old.dpi != new.dpi {
+            out.push(format!("profile {} dpi {} -> {}", id, dpi(old), dpi(new)));
+        }
+        if old.rgb != new.rgb {
+            out.push(format!(
+                "profile {} color {} -> {}",
+                id,
+                hex(old.rgb),
+                hex(new.rgb)
+            ));
+        }
+    }
+
+    let (old_effect, new_effect) = (effect(old), effect(new));
+    if old_effect != new_effect {
+        out.push(format!("effect {} -> {}", old_effect, new_effect));
+    }
+    if old.report_rate != new.report_rate {
+        out.push(format!(
+            "report rate {} -> {}",
+            old.report_rate, new.report_rate
+        ));
+    }
+
+    if out.is_empty() && old.as_bytes() != new.as_bytes() {
+        let bytes = old
+            .as_bytes()
+            .iter()
+            .zip(new.as_bytes().iter())
+            .filter(|(a, b)| a != b)
+            .count();
+        out.push(format!("{} unknown bytes", bytes));
+    }
+    out
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC
+pub fn format_time(unix: u64) -> String {
+    let (days, secs) = ((unix / 86400) as i64, unix % 86400);
+
+    // Civil date from days since 1970-01-01, Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal() {
+        let path = std::env::temp_dir().join(format!("gm4-history-{}.log", std::process::id()));
+        let journal = Journal::at(
+            &path,
+            Retention {
+                max_entries: Some(10),
+                max_age: None,
+            },
+        );
+
+        let mut config = config::Config::default();
+        for n in 0..11 {
+            config.set_profile_rgb(0, [n, 0, 0]);
+            journal.append("gm4-cli test", (&config).into()).unwrap();
+        }
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(
+            (1..=11).collect::<Vec<u64>>(),
+            entries.iter().map(|e| e.id).collect::<Vec<_>>()
+        );
+        assert_eq!("gm4-cli test", entries[0].source);
+
+        // The 12th entry goes over the slack and trims back to 10
+        journal.append("gm4-cli test", (&config).into()).unwrap();
+        let entries = journal.entries().unwrap();
+        assert_eq!(10, entries.len());
+        assert_eq!(12, entries.last().unwrap().id);
+
+        let old = &journal.get(5).unwrap().unwrap().config_data;
+        assert_eq!(
+            vec!["profile 1 color #040000 -> #0a0000".to_owned()],
+            changes(old, &entries[entries.len() - 2].config_data)
+        );
+
+        // Appends from several processes (or open files) don't lose entries
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let journal = Journal::at(&path, Retention::default());
+                std::thread::spawn(move || {
+                    for _ in 0..5 {
+                        journal
+                            .append("gm4-cli test", &ConfigData::default())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let ids: Vec<u64> = journal.entries().unwrap().iter().map(|e| e.id).collect();
+        assert_eq!((3..=32).collect::<Vec<u64>>(), ids);
+        fs::remove_file(&path).ok();
+        fs::remove_file(path.with_extension("lock")).ok();
+
+        // Bytes that don't decode
+        let mut raw = *ConfigData::default().as_bytes();
+        raw[74] = 0x80;
+        raw[93] = 0x99;
+        let odd = ConfigData::from_bytes(raw);
+        let changes = changes(&ConfigData::default(), &odd);
+        assert!(changes.contains(&"profile 1 dpi 800 -> unknown".to_owned()));
+        assert!(changes.contains(&"effect Breathe(S4) -> unknown 99 00 00 12".to_owned()));
+
+        assert_eq!("2025-10-09 08:53", format_time(1_760_000_000));
+        assert_eq!(
+            Some(Duration::from_secs(30 * 86400)),
+            "100,30d".parse::<Retention>().unwrap().max_age
+        );
+    }
+}
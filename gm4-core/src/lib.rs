@@ -1,3 +1,35 @@
+//! `protocol` and `config` only need `alloc`, build with `default-features = false`
+//! to use them without libusb or `std` (e.g. on `wasm32-unknown-unknown`).
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "async")]
+pub mod async_device;
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+pub mod backup;
 pub mod config;
+#[cfg(feature = "std")]
+pub mod device;
+#[cfg(all(feature = "hidraw", target_os = "linux"))]
+pub mod hidraw;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(any(feature = "usb", feature = "hidraw"))]
+pub mod lock;
 pub mod protocol;
+#[cfg(feature = "std")]
+pub mod reactive;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod simulation;
+#[cfg(feature = "usb")]
 pub mod usb;
@@ -0,0 +1,317 @@
+//! Advisory lock that keeps two processes from talking to the mouse at once.
+//!
+//! The lock is an `flock` on `gm4-<bus>-<address>.lock` in `$XDG_RUNTIME_DIR`, or in
+//! `/run/lock` or the temp dir when that isn't set, see `lock_dir`. The holder writes its
+//! pid and name into the file so others can tell who they are waiting for.
+//!
+//! A lock directory that can't be written to means no lock rather than no mouse, the
+//! device is then used unlocked after a warning.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Overrides the default `LockPolicy`: `fail`, `wait` or `wait:<milliseconds>`
+pub const LOCK_ENV: &str = "GM4_LOCK";
+
+/// Overrides the directory of the lock files
+pub const LOCK_DIR_ENV: &str = "GM4_LOCK_DIR";
+
+/// Shared by every user, used when `$XDG_RUNTIME_DIR` isn't set
+pub const LOCK_DIR: &str = "/run/lock";
+
+const RETRY: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockPolicy {
+    /// Give up right away when another process holds the lock
+    Fail,
+    /// Wait for the lock, `None` waits forever
+    Wait(Option<Duration>),
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        LockPolicy::Wait(Some(Duration::from_secs(10)))
+    }
+}
+
+impl FromStr for LockPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(LockPolicy::Fail),
+            "wait" => Ok(LockPolicy::Wait(None)),
+            _ => s
+                .strip_prefix("wait:")
+                .and_then(|ms| ms.parse().ok())
+                .map(|ms| LockPolicy::Wait(Some(Duration::from_millis(ms))))
+                .ok_or_else(|| format!("Invalid lock policy: {}", s)),
+        }
+    }
+}
+
+impl LockPolicy {
+    /// Policy named by `GM4_LOCK`, the default when it is unset or invalid
+    pub fn from_env() -> Self {
+        std::env::var(LOCK_ENV)
+            .ok()
+            .and_then(|policy| policy.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Process holding the lock
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holder {
+    pub pid: u32,
+    pub name: String,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another process has the lock, `None` when it didn't say who it is yet
+    Held(Option<Holder>),
+    Io(io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Held(Some(holder)) => write!(f, "The mouse is in use by {}", holder),
+            LockError::Held(None) => write!(f, "The mouse is in use by another process"),
+            LockError::Io(err) => write!(f, "Could not lock the mouse: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl From<io::Error> for LockError {
+    fn from(err: io::Error) -> Self {
+        LockError::Io(err)
+    }
+}
+
+fn process_name() -> String {
+    std::fs::read_to_string("/proc/self/comm")
+        .map(|name| name.trim().to_owned())
+        .ok()
+        .or_else(|| std::env::args().next())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn writable(dir: &Path) -> bool {
+    let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    // SAFETY: `path` is a valid C string for the duration of the call
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// `GM4_LOCK_DIR`, then `$XDG_RUNTIME_DIR`, then `/run/lock` when this user may write
+/// to it (it is root only on some distributions) and the temp dir last
+pub fn lock_dir() -> PathBuf {
+    [LOCK_DIR_ENV, "XDG_RUNTIME_DIR"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .next()
+        .or_else(|| Some(PathBuf::from(LOCK_DIR)).filter(|dir| writable(dir)))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Held until dropped
+#[derive(Debug)]
+pub struct DeviceLock {
+    /// `None` when the lock file could not be created
+    _file: Option<File>,
+}
+
+impl DeviceLock {
+    /// Lock file of the device at `bus`/`address`
+    pub fn path(bus: u8, address: u8) -> PathBuf {
+        lock_dir().join(format!("gm4-{:03}-{:03}.lock", bus, address))
+    }
+
+    pub fn acquire(bus: u8, address: u8, policy: LockPolicy) -> Result<Self, LockError> {
+        Self::acquire_at(Self::path(bus, address), policy)
+    }
+
+    /// Symlinks are refused, the directory may be writable by everyone
+    pub fn acquire_at<P: AsRef<Path>>(path: P, policy: LockPolicy) -> Result<Self, LockError> {
+        let path = path.as_ref();
+        let open = |write: bool| {
+            OpenOptions::new()
+                .read(true)
+                .write(write)
+                .create(write)
+                .truncate(false)
+                // Other users have to be able to open the file the first process created
+                .mode(0o666)
+                .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+                .open(path)
+        };
+        let unlocked = |err: io::Error| {
+            eprintln!(
+                "Using the mouse without a lock, {} can't be created: {}",
+                path.display(),
+                err
+            );
+            Ok(Self { _file: None })
+        };
+        let no_access = |err: &io::Error| {
+            matches!(
+                err.kind(),
+                io::ErrorKind::PermissionDenied
+                    | io::ErrorKind::NotFound
+                    | io::ErrorKind::ReadOnlyFilesystem
+            )
+        };
+
+        // A file created by another user may only be readable, which is enough to lock it
+        let (mut file, writable) = match open(true) {
+            Ok(file) => {
+                // The umask may have taken away the write bits others need. Only the
+                // owner may change them, so a failure means someone else created it.
+                file.set_permissions(std::fs::Permissions::from_mode(0o666))
+                    .ok();
+                (file, true)
+            }
+            Err(err) if no_access(&err) => match open(false) {
+                Ok(file) => (file, false),
+                Err(_) => return unlocked(err),
+            },
+            Err(err) => {
+                return Err(
+                    io::Error::new(err.kind(), format!("{}: {}", path.display(), err)).into(),
+                )
+            }
+        };
+
+        let start = Instant::now();
+        loop {
+            // SAFETY: Only takes a file descriptor, which `file` keeps open
+            let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+            if res == 0 {
+                break;
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(err.into());
+            }
+
+            let timed_out = match policy {
+                LockPolicy::Fail => true,
+                LockPolicy::Wait(Some(timeout)) => start.elapsed() >= timeout,
+                LockPolicy::Wait(None) => false,
+            };
+            if timed_out {
+                return Err(LockError::Held(Self::holder(&mut file)));
+            }
+            std::thread::sleep(RETRY);
+        }
+
+        if writable {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            writeln!(file, "{} {}", std::process::id(), process_name())?;
+        }
+
+        Ok(Self { _file: Some(file) })
+    }
+
+    fn holder(file: &mut File) -> Option<Holder> {
+        let mut content = String::new();
+        file.seek(SeekFrom::Start(0)).ok()?;
+        file.read_to_string(&mut content).ok()?;
+
+        let (pid, name) = content.trim().split_once(' ')?;
+        Some(Holder {
+            pid: pid.parse().ok()?,
+            name: name.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_lock() {
+        let path = std::env::temp_dir().join(format!("gm4-lock-{}.lock", std::process::id()));
+
+        let lock = DeviceLock::acquire_at(&path, LockPolicy::Fail).unwrap();
+
+        // flock is per open file, so a second open in the same process conflicts too
+        match DeviceLock::acquire_at(&path, LockPolicy::Fail) {
+            Err(LockError::Held(Some(holder))) => assert_eq!(std::process::id(), holder.pid),
+            res => panic!("Expected a held lock, got {:?}", res.map(|_| ())),
+        }
+        let waited = Instant::now();
+        assert!(
+            DeviceLock::acquire_at(&path, LockPolicy::Wait(Some(Duration::from_millis(100))))
+                .is_err()
+        );
+        assert!(waited.elapsed() >= Duration::from_millis(100));
+
+        drop(lock);
+        assert!(DeviceLock::acquire_at(&path, LockPolicy::Fail).is_ok());
+
+        // A planted symlink is not followed, so its target isn't truncated
+        let link = path.with_extension("link");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        assert!(DeviceLock::acquire_at(&link, LockPolicy::Fail).is_err());
+        assert!(!std::fs::read_to_string(&path).unwrap().is_empty());
+        std::fs::remove_file(&link).ok();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(
+            LockPolicy::Wait(Some(Duration::from_millis(500))),
+            "wait:500".parse().unwrap()
+        );
+        assert!("sometimes".parse::<LockPolicy>().is_err());
+    }
+
+    #[test]
+    fn lock_dir_override() {
+        let dir = std::env::temp_dir().join(format!("gm4-lock-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var(LOCK_DIR_ENV, &dir);
+
+        assert_eq!(dir.join("gm4-001-002.lock"), DeviceLock::path(1, 2));
+        let lock = DeviceLock::acquire(1, 2, LockPolicy::Fail).unwrap();
+        let mode = std::fs::metadata(DeviceLock::path(1, 2))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(0o666, mode & 0o777);
+        assert!(DeviceLock::acquire(1, 2, LockPolicy::Fail).is_err());
+        drop(lock);
+
+        // A directory that can't be used means no lock, not an error
+        std::env::set_var(LOCK_DIR_ENV, dir.join("missing"));
+        let _first = DeviceLock::acquire(1, 2, LockPolicy::Fail).unwrap();
+        assert!(DeviceLock::acquire(1, 2, LockPolicy::Fail).is_ok());
+
+        std::env::remove_var(LOCK_DIR_ENV);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,380 @@
+//! Human readable views of the raw report.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
+
+use super::led::{self, blink, breathe, neon, steady};
+use super::ConfigData;
+use crate::config::{self, Profile, DPI_LIST};
+
+/// Where the report starts in the usbmon captures of `gm4-protocol-doc`
+pub const CAPTURE_OFFSET: usize = 0x40;
+
+/// Name, offset and length of every `ConfigData` field
+pub const FIELDS: [(&str, usize, usize); 14] = [
+    ("sec_1", 0, 8),
+    ("report_rate", 8, 1),
+    ("sec_2", 9, 62),
+    ("active_profiles_list_len", 71, 1),
+    ("sec_3", 72, 2),
+    ("profiles_dpi", 74, 5),
+    ("sec_5", 79, 14),
+    ("led_mode", 93, 1),
+    ("led_arg1", 94, 1),
+    ("led_arg2", 95, 1),
+    ("led_arg3", 96, 1),
+    ("sec_9", 97, 3),
+    ("profiles_rgb", 100, 15),
+    ("data", 115, 39),
+];
+
+/// Name of the field `offset` belongs to
+pub fn field_at(offset: usize) -> Option<&'static str> {
+    FIELDS
+        .iter()
+        .find(|(_, start, len)| (*start..start + len).contains(&offset))
+        .map(|(name, _, _)| *name)
+}
+
+/// Every field with its offset and bytes, one per line
+pub fn fields(config_data: &ConfigData) -> String {
+    let bytes = config_data.as_bytes();
+    let mut out = String::new();
+
+    for (name, start, len) in FIELDS.iter() {
+        write!(out, "{:#04x} {:<24}", start, name).unwrap();
+        for b in &bytes[*start..start + len] {
+            write!(out, " {:02x}", b).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// DPI of a profile, `unknown` for a step that is not in `DPI_LIST`
+pub fn dpi(profile: &Profile) -> String {
+    match profile.dpi_value() {
+        Some(dpi) => format!("{}", dpi),
+        None => "unknown".into(),
+    }
+}
+
+/// LED effect of the report, the raw bytes when they are not a known effect
+pub fn effect(config_data: &ConfigData) -> String {
+    let raw = (
+        config_data.led_mode,
+        config_data.led_arg1,
+        config_data.led_arg2,
+        config_data.led_arg3,
+    );
+    match led::Config::from_raw(raw.0, raw.1, raw.2, raw.3) {
+        Ok(config) => format!("{:?}", config),
+        Err(_) => format!(
+            "unknown {:02x} {:02x} {:02x} {:02x}",
+            raw.0, raw.1, raw.2, raw.3
+        ),
+    }
+}
+
+/// Profiles, effect and report rate the report decodes to, any report can be described
+pub fn describe(config_data: &ConfigData) -> String {
+    let mut out = String::new();
+
+    for (id, profile) in config::profiles(config_data).iter().enumerate() {
+        let [r, g, b] = profile.rgb;
+        writeln!(
+            out,
+            "profile {}: {}, {} DPI, #{:02x}{:02x}{:02x}",
+            id + 1,
+            if profile.active { "on" } else { "off" },
+            dpi(profile),
+            r,
+            g,
+            b
+        )
+        .unwrap();
+    }
+    writeln!(out, "effect: {}", effect(config_data)).unwrap();
+    writeln!(out, "report rate: {}", config_data.report_rate).unwrap();
+    out
+}
+
+/// Offset, old and new value of every byte that differs
+pub fn byte_diff(old: &ConfigData, new: &ConfigData) -> Vec<(usize, u8, u8)> {
+    old.as_bytes()
+        .iter()
+        .zip(new.as_bytes().iter())
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(offset, (a, b))| (offset, *a, *b))
+        .collect()
+}
+
+/// Known bytes of the report and what they decode to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub offset: usize,
+    pub len: usize,
+    pub label: String,
+}
+
+fn decoded<T: Display, E>(value: Result<T, E>, raw: u8) -> String {
+    match value {
+        Ok(value) => format!("{}", value),
+        Err(_) => format!("{:#04x}, invalid", raw),
+    }
+}
+
+/// The LED mode a `led_mode` byte selects, with default values
+fn led_mode(led_mode: u8) -> Option<led::Config> {
+    let effects = [
+        led::Effect::Respiration(Default::default()),
+        led::Effect::Neon(Default::default()),
+        led::Effect::Steady(Default::default()),
+    ];
+    let modes = [
+        led::Config::Steady(Default::default()),
+        led::Config::Breathe(Default::default()),
+    ];
+
+    modes
+        .iter()
+        .copied()
+        .chain(
+            effects
+                .iter()
+                .map(|&e| led::Config::BlinkEffect(Default::default(), Default::default(), e)),
+        )
+        .chain(
+            effects
+                .iter()
+                .map(|&e| led::Config::SteadyEffect(Default::default(), e)),
+        )
+        .find(|config| config.to_raw().0 == led_mode)
+}
+
+fn effect_name(effect: &led::Effect) -> &'static str {
+    match effect {
+        led::Effect::Respiration(_) => "Breathe",
+        led::Effect::Neon(_) => "Neon",
+        led::Effect::Steady(_) => "Steady",
+    }
+}
+
+/// `Blink+Neon` style name of a `led_mode` byte
+pub fn led_mode_name(led_mode: u8) -> Option<String> {
+    Some(match self::led_mode(led_mode)? {
+        led::Config::Steady(_) => "Steady".into(),
+        led::Config::Breathe(_) => "Breathe".into(),
+        led::Config::BlinkEffect(_, _, effect) => format!("Blink+{}", effect_name(&effect)),
+        led::Config::SteadyEffect(_, effect) => format!("Steady+{}", effect_name(&effect)),
+    })
+}
+
+/// Every known field, in report order, anything not covered is unknown
+pub fn annotations(config_data: &ConfigData) -> Vec<Annotation> {
+    let mut out = Vec::new();
+    let mut push =
+        |offset: usize, len: usize, label: String| out.push(Annotation { offset, len, label });
+
+    push(0, 1, format!("report_id = {:#04x}", config_data.sec_1[0]));
+    push(
+        8,
+        1,
+        format!("report_rate = {:#04x}", config_data.report_rate),
+    );
+    push(
+        71,
+        1,
+        format!(
+            "active_profiles_list_len = {:#04x}",
+            config_data.active_profiles_list_len
+        ),
+    );
+
+    for (id, raw) in config_data.profiles_dpi.iter().enumerate() {
+        // The step is one indexed, the top bit turns the profile off
+        let dpi = (raw & 0x7f)
+            .checked_sub(1)
+            .and_then(|step| DPI_LIST.get(step as usize));
+        let label = match dpi {
+            Some(dpi) => format!("profiles_dpi[{}] = {} DPI", id, dpi),
+            None => format!("profiles_dpi[{}] = {:#04x}, invalid", id, raw),
+        };
+        let state = if raw & 0x80 == 0 { "" } else { " inactive" };
+        push(74 + id, 1, format!("{}{}", label, state));
+    }
+
+    let mode = config_data.led_mode;
+    push(
+        93,
+        1,
+        match led_mode_name(mode) {
+            Some(name) => format!("led_mode = {}", name),
+            None => format!("led_mode = {:#04x}, unknown", mode),
+        },
+    );
+    let (arg1, arg2, arg3) = (
+        config_data.led_arg1,
+        config_data.led_arg2,
+        config_data.led_arg3,
+    );
+    // The arguments of an unknown mode can't be told apart
+    let effect = match led_mode(mode) {
+        Some(led::Config::Steady(b)) => Some(led::Effect::Steady(b)),
+        Some(led::Config::Breathe(s)) => Some(led::Effect::Respiration(s)),
+        Some(led::Config::BlinkEffect(_, _, effect)) => {
+            let frequency = decoded(blink::Frequency::from_raw(arg1), arg1);
+            push(94, 1, format!("led_arg1 = {} blink frequency", frequency));
+            let times = decoded(blink::Times::from_raw(arg2), arg2);
+            push(95, 1, format!("led_arg2 = {} blinks", times));
+            Some(effect)
+        }
+        Some(led::Config::SteadyEffect(_, effect)) => {
+            let time = decoded(steady::EffectTime::from_raw(arg1), arg1);
+            push(94, 1, format!("led_arg1 = {} steady", time));
+            Some(effect)
+        }
+        None => None,
+    };
+    let arg3 = effect.map(|effect| match effect {
+        led::Effect::Respiration(_) => format!(
+            "{} breathe period",
+            decoded(breathe::Speed::from_raw(arg3), arg3)
+        ),
+        led::Effect::Neon(_) => {
+            format!("{} neon period", decoded(neon::Speed::from_raw(arg3), arg3))
+        }
+        led::Effect::Steady(_) => format!(
+            "{} brightness",
+            decoded(steady::Brightnes::from_raw(arg3), arg3)
+        ),
+    });
+    if let Some(arg3) = arg3 {
+        push(96, 1, format!("led_arg3 = {}", arg3));
+    }
+
+    for (id, [r, g, b]) in config_data.profiles_rgb.iter().enumerate() {
+        push(
+            100 + id * 3,
+            3,
+            format!("profiles_rgb[{}] = #{:02x}{:02x}{:02x}", id, r, g, b),
+        );
+    }
+    out
+}
+
+/// The report in rows of 16 bytes, offsets as in the `gm4-protocol-doc` captures
+pub fn hexdump(config_data: &ConfigData) -> String {
+    let mut out = String::new();
+    for (row, chunk) in config_data.as_bytes().chunks(16).enumerate() {
+        write!(out, "{:04x}  ", CAPTURE_OFFSET + row * 16).unwrap();
+        for b in chunk {
+            write!(out, " {:02x}", b).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `hexdump` with a line under every row marking the known bytes with `^^` and the
+/// unknown ones with `??`, followed by the decoded known fields of the row.
+///
+/// `color` highlights known bytes and dims unknown zeros with ANSI escapes.
+pub fn annotated(config_data: &ConfigData, color: bool) -> String {
+    let annotations = annotations(config_data);
+    let known = |offset: usize| {
+        annotations
+            .iter()
+            .any(|a| (a.offset..a.offset + a.len).contains(&offset))
+    };
+
+    let mut out = String::new();
+    for (row, chunk) in config_data.as_bytes().chunks(16).enumerate() {
+        let start = row * 16;
+        write!(out, "{:04x}  ", CAPTURE_OFFSET + start).unwrap();
+        for (offset, b) in (start..).zip(chunk) {
+            match (color, known(offset)) {
+                (true, true) => write!(out, " \x1b[1;32m{:02x}\x1b[0m", b),
+                (true, false) if *b == 0 => write!(out, " \x1b[2m{:02x}\x1b[0m", b),
+                _ => write!(out, " {:02x}", b),
+            }
+            .unwrap();
+        }
+        out.push('\n');
+
+        out.push_str("      ");
+        for offset in start..start + chunk.len() {
+            out.push_str(if known(offset) { " ^^" } else { " ??" });
+        }
+        out.push('\n');
+
+        for a in annotations
+            .iter()
+            .filter(|a| (start..start + 16).contains(&a.offset))
+        {
+            writeln!(out, "       {:04x} {}", CAPTURE_OFFSET + a.offset, a.label).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_layout() {
+        // The table has to cover the struct exactly
+        let mut next = 0;
+        for (_, start, len) in FIELDS.iter() {
+            assert_eq!(next, *start);
+            next = start + len;
+        }
+        assert_eq!(154, next);
+        assert_eq!(Some("report_rate"), field_at(8));
+        assert_eq!(Some("led_mode"), field_at(93));
+
+        let old = ConfigData::default();
+        let mut new = old.clone();
+        new.report_rate = 2;
+        assert_eq!(vec![(8, old.report_rate, 2)], byte_diff(&old, &new));
+        assert!(fields(&new).contains("0x08 report_rate              02"));
+
+        // Bytes the enums don't know are described too
+        let mut raw = *old.as_bytes();
+        raw[74] = 0x80;
+        raw[93] = 0x99;
+        let odd = describe(&ConfigData::from_bytes(raw));
+        assert!(odd.contains("profile 1: off, unknown DPI"));
+        assert!(odd.contains("effect: unknown 99 00 00 12"));
+    }
+
+    #[test]
+    fn annotated_hexdump() {
+        let mut config = config::Config::default();
+        config.set_profile_active(2, false);
+        config.set_profile_dpi(2, 4);
+        let config_data: &ConfigData = (&config).into();
+
+        let dump = hexdump(config_data);
+        assert_eq!(10, dump.lines().count());
+        assert!(dump.starts_with("0040   04 00 00 00 00 00 00 00 3c 00"));
+
+        let annotated = annotated(config_data, false);
+        assert!(annotated.contains("0080   00 00 00 00 00 00 00 24 80"));
+        assert!(annotated.contains("       0040 report_id = 0x04\n"));
+        assert!(annotated.contains("008c profiles_dpi[2] = 1600 DPI inactive"));
+        assert!(annotated.contains("009d led_mode = Breathe"));
+        // report_id, then seven unknown bytes and report_rate
+        assert!(annotated.contains("\n       ^^ ?? ?? ?? ?? ?? ?? ?? ^^ ??"));
+
+        assert_eq!(Some("Blink+Neon".into()), led_mode_name(0x44));
+        assert_eq!(Some("Steady+Breathe".into()), led_mode_name(0x82));
+        assert_eq!(None, led_mode_name(0x24));
+        assert_eq!(Some("Steady".into()), led_mode_name(0x28));
+        assert_eq!(None, led_mode_name(0x99));
+    }
+}
@@ -1,12 +1,19 @@
+use alloc::string::String;
+
 #[derive(Debug)]
 pub enum ProtocolError {
     InvalidRawInput,
+    InvalidValue(String),
 }
 
-impl std::fmt::Display for ProtocolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+impl core::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ProtocolError::InvalidValue(v) => write!(f, "Invalid value: {}", v),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for ProtocolError {}
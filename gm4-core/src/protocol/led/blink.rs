@@ -1,7 +1,13 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::ToString;
+
+use super::parse_whole_unit;
 use crate::protocol::error::ProtocolError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Frequency {
+    #[default]
     Hz1 = 1,
     Hz2 = 2,
     Hz3 = 3,
@@ -13,13 +19,35 @@ pub enum Frequency {
     Hz9 = 9,
     Hz10 = 10,
 }
-impl Default for Frequency {
-    fn default() -> Self {
-        Self::Hz1
+impl Frequency {
+    pub const ALL: [Frequency; 10] = [
+        Frequency::Hz1,
+        Frequency::Hz2,
+        Frequency::Hz3,
+        Frequency::Hz4,
+        Frequency::Hz5,
+        Frequency::Hz6,
+        Frequency::Hz7,
+        Frequency::Hz8,
+        Frequency::Hz9,
+        Frequency::Hz10,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Blinks per second
+    pub fn hz(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_hz(hz: u8) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|f| f.hz() == hz)
+            .ok_or_else(|| ProtocolError::InvalidValue(format!("{}Hz", hz)))
     }
-}
 
-impl Frequency {
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
         Self::from_id(raw)
     }
@@ -45,10 +73,11 @@ impl Frequency {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Times {
     T1 = 1,
     T2 = 2,
+    #[default]
     T3 = 3,
     T4 = 4,
     T5 = 5,
@@ -58,13 +87,35 @@ pub enum Times {
     T9 = 9,
     T10 = 10,
 }
-impl Default for Times {
-    fn default() -> Self {
-        Self::T3
+impl Times {
+    pub const ALL: [Times; 10] = [
+        Times::T1,
+        Times::T2,
+        Times::T3,
+        Times::T4,
+        Times::T5,
+        Times::T6,
+        Times::T7,
+        Times::T8,
+        Times::T9,
+        Times::T10,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Number of blinks
+    pub fn count(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_count(count: u8) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|t| t.count() == count)
+            .ok_or_else(|| ProtocolError::InvalidValue(count.to_string()))
     }
-}
 
-impl Times {
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
         Self::from_id(raw)
     }
@@ -88,3 +139,57 @@ impl Times {
         *self as u8
     }
 }
+
+impl core::fmt::Display for Frequency {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}Hz", self.hz())
+    }
+}
+
+impl core::str::FromStr for Frequency {
+    type Err = ProtocolError;
+
+    /// Accepts `"3hz"`, `"3Hz"` or `"3"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hz = parse_whole_unit(s, "hz")?;
+        Self::from_hz(hz).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
+impl core::fmt::Display for Times {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.count())
+    }
+}
+
+impl core::str::FromStr for Times {
+    type Err = ProtocolError;
+
+    /// Accepts `"3"` or `"3x"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let count = parse_whole_unit(s, "x")?;
+        Self::from_count(count).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn frequency() {
+        assert_eq!(10, Frequency::iter().count());
+        assert_eq!("3Hz", Frequency::Hz3.to_string());
+        assert_eq!(3, "3hz".parse::<Frequency>().unwrap().hz());
+        assert_eq!(10, "10 Hz".parse::<Frequency>().unwrap().hz());
+        assert!("11hz".parse::<Frequency>().is_err());
+        assert!("2.5hz".parse::<Frequency>().is_err());
+        assert!("300hz".parse::<Frequency>().is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!("7", Times::T7.to_string());
+        assert_eq!(2, "2x".parse::<Times>().unwrap().count());
+        assert!("0".parse::<Times>().is_err());
+    }
+}
@@ -1,7 +1,12 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+
+use super::parse_unit;
 use crate::protocol::error::ProtocolError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Speed {
+    #[default]
     S4 = 1,
     S5 = 2,
     S6 = 3,
@@ -9,15 +14,29 @@ pub enum Speed {
     S8 = 5,
 }
 
-impl Default for Speed {
-    fn default() -> Self {
-        Self::S4
+impl Speed {
+    pub const ALL: [Speed; 5] = [Speed::S4, Speed::S5, Speed::S6, Speed::S7, Speed::S8];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Length of one breath
+    pub fn seconds(&self) -> f64 {
+        *self as u8 as f64 + 3.0
+    }
+
+    pub fn from_seconds(seconds: f64) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|v| v.seconds() == seconds)
+            .ok_or_else(|| ProtocolError::InvalidValue(format!("{}s", seconds)))
     }
-}
 
-impl Speed {
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
-        let id = (raw - 0x02) / 0x10;
+        let id = raw
+            .checked_sub(0x02)
+            .ok_or(ProtocolError::InvalidRawInput)?
+            / 0x10;
         Self::from_id(id)
     }
 
@@ -37,9 +56,35 @@ impl Speed {
     }
 }
 
+impl core::fmt::Display for Speed {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}s", self.seconds())
+    }
+}
+
+impl core::str::FromStr for Speed {
+    type Err = ProtocolError;
+
+    /// Accepts `"5s"` or `"5"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds = parse_unit(s, "s")?;
+        Self::from_seconds(seconds).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn units() {
+        assert_eq!(4.0, Speed::S4.seconds());
+        assert_eq!("8s", Speed::S8.to_string());
+        assert_eq!(6.0, "6s".parse::<Speed>().unwrap().seconds());
+        assert!("3s".parse::<Speed>().is_err());
+        assert_eq!(7.0, Speed::from_seconds(7.0).unwrap().seconds());
+        assert!(Speed::from_seconds(4.5).is_err());
+    }
+
     #[test]
     fn speed() {
         assert_eq!(0x12, Speed::S4.to_raw());
@@ -1,3 +1,5 @@
+use alloc::borrow::ToOwned;
+
 use super::error::ProtocolError;
 
 pub mod blink;
@@ -5,6 +7,31 @@ pub mod breathe;
 pub mod neon;
 pub mod steady;
 
+/// Parses `"2.5s"`, `"85%"`, `"3hz"` or just `"3"` into a number,
+/// `unit` is matched case-insensitively.
+pub(crate) fn parse_unit(s: &str, unit: &str) -> Result<f64, ProtocolError> {
+    let trimmed = s.trim();
+    let value = if trimmed.to_lowercase().ends_with(unit) {
+        &trimmed[..trimmed.len() - unit.len()]
+    } else {
+        trimmed
+    };
+
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+}
+
+/// `parse_unit` for values that are whole numbers, like `"85%"` or `"3hz"`
+pub(crate) fn parse_whole_unit(s: &str, unit: &str) -> Result<u8, ProtocolError> {
+    let value = parse_unit(s, unit)?;
+    if value.fract() != 0.0 || !(0.0..=u8::MAX as f64).contains(&value) {
+        return Err(ProtocolError::InvalidValue(s.to_owned()));
+    }
+    Ok(value as u8)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Effect {
     Respiration(breathe::Speed),
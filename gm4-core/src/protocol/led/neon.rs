@@ -1,10 +1,15 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+
+use super::parse_unit;
 use crate::protocol::error::ProtocolError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Speed {
     S0_5 = 1,
     S1 = 2,
     S1_5 = 3,
+    #[default]
     S2 = 4,
     S2_5 = 5,
     S3 = 6,
@@ -19,13 +24,40 @@ pub enum Speed {
     S7_5 = 15,
 }
 
-impl Default for Speed {
-    fn default() -> Self {
-        Self::S2
+impl Speed {
+    pub const ALL: [Speed; 15] = [
+        Speed::S0_5,
+        Speed::S1,
+        Speed::S1_5,
+        Speed::S2,
+        Speed::S2_5,
+        Speed::S3,
+        Speed::S3_5,
+        Speed::S4,
+        Speed::S4_5,
+        Speed::S5,
+        Speed::S5_5,
+        Speed::S6,
+        Speed::S6_5,
+        Speed::S7,
+        Speed::S7_5,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// Length of one color cycle
+    pub fn seconds(&self) -> f64 {
+        *self as u8 as f64 * 0.5
+    }
+
+    pub fn from_seconds(seconds: f64) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|v| v.seconds() == seconds)
+            .ok_or_else(|| ProtocolError::InvalidValue(format!("{}s", seconds)))
     }
-}
 
-impl Speed {
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
         let id = raw / 0x10;
         Self::from_id(id)
@@ -56,3 +88,35 @@ impl Speed {
         *self as u8 * 0x10
     }
 }
+
+impl core::fmt::Display for Speed {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}s", self.seconds())
+    }
+}
+
+impl core::str::FromStr for Speed {
+    type Err = ProtocolError;
+
+    /// Accepts `"2.5s"` or `"2.5"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds = parse_unit(s, "s")?;
+        Self::from_seconds(seconds).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn units() {
+        assert_eq!(15, Speed::iter().count());
+        assert_eq!(2.5, Speed::S2_5.seconds());
+        assert_eq!("0.5s", Speed::S0_5.to_string());
+        assert_eq!("7s", Speed::S7.to_string());
+        assert_eq!(7.5, "7.5s".parse::<Speed>().unwrap().seconds());
+        assert!("8s".parse::<Speed>().is_err());
+        assert_eq!(1.5, Speed::from_seconds(1.5).unwrap().seconds());
+        assert!(Speed::from_seconds(0.0).is_err());
+    }
+}
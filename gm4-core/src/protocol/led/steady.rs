@@ -1,6 +1,10 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+
+use super::{parse_unit, parse_whole_unit};
 use crate::protocol::error::ProtocolError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum Brightnes {
     P5 = 1,
     P15 = 2,
@@ -10,17 +14,46 @@ pub enum Brightnes {
     P55 = 6,
     P65 = 7,
     P75 = 8,
+    #[default]
     P85 = 9,
     P100 = 10,
 }
-impl Default for Brightnes {
-    fn default() -> Self {
-        Self::P85
-    }
-}
 impl Brightnes {
+    pub const ALL: [Brightnes; 10] = [
+        Brightnes::P5,
+        Brightnes::P15,
+        Brightnes::P25,
+        Brightnes::P35,
+        Brightnes::P45,
+        Brightnes::P55,
+        Brightnes::P65,
+        Brightnes::P75,
+        Brightnes::P85,
+        Brightnes::P100,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    pub fn percent(&self) -> u8 {
+        match self {
+            Brightnes::P100 => 100,
+            _ => *self as u8 * 10 - 5,
+        }
+    }
+
+    pub fn from_percent(percent: u8) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|b| b.percent() == percent)
+            .ok_or_else(|| ProtocolError::InvalidValue(format!("{}%", percent)))
+    }
+
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
-        let id = (raw - 0x02) / 0x10;
+        let id = raw
+            .checked_sub(0x02)
+            .ok_or(ProtocolError::InvalidRawInput)?
+            / 0x10;
         Self::from_id(id)
     }
 
@@ -45,9 +78,10 @@ impl Brightnes {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum EffectTime {
     S0_5 = 1,
+    #[default]
     S1 = 2,
     S1_5 = 3,
     S2 = 4,
@@ -59,6 +93,34 @@ pub enum EffectTime {
     S5 = 10,
 }
 impl EffectTime {
+    pub const ALL: [EffectTime; 10] = [
+        EffectTime::S0_5,
+        EffectTime::S1,
+        EffectTime::S1_5,
+        EffectTime::S2,
+        EffectTime::S2_5,
+        EffectTime::S3,
+        EffectTime::S3_5,
+        EffectTime::S4,
+        EffectTime::S4_5,
+        EffectTime::S5,
+    ];
+
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.iter().copied()
+    }
+
+    /// How long the steady color is held before the effect
+    pub fn seconds(&self) -> f64 {
+        *self as u8 as f64 * 0.5
+    }
+
+    pub fn from_seconds(seconds: f64) -> Result<Self, ProtocolError> {
+        Self::iter()
+            .find(|v| v.seconds() == seconds)
+            .ok_or_else(|| ProtocolError::InvalidValue(format!("{}s", seconds)))
+    }
+
     pub fn from_raw(raw: u8) -> Result<Self, ProtocolError> {
         Self::from_id(raw)
     }
@@ -84,9 +146,58 @@ impl EffectTime {
     }
 }
 
+impl core::fmt::Display for Brightnes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}%", self.percent())
+    }
+}
+
+impl core::str::FromStr for Brightnes {
+    type Err = ProtocolError;
+
+    /// Accepts `"85%"` or `"85"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = parse_whole_unit(s, "%")?;
+        Self::from_percent(percent).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
+impl core::fmt::Display for EffectTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}s", self.seconds())
+    }
+}
+
+impl core::str::FromStr for EffectTime {
+    type Err = ProtocolError;
+
+    /// Accepts `"2.5s"` or `"2.5"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seconds = parse_unit(s, "s")?;
+        Self::from_seconds(seconds).map_err(|_| ProtocolError::InvalidValue(s.to_owned()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn units() {
+        let percents: Vec<u8> = Brightnes::iter().map(|b| b.percent()).collect();
+        assert_eq!(vec![5, 15, 25, 35, 45, 55, 65, 75, 85, 100], percents);
+
+        assert_eq!("85%", Brightnes::P85.to_string());
+        assert_eq!(85, "85%".parse::<Brightnes>().unwrap().percent());
+        assert_eq!(100, "100".parse::<Brightnes>().unwrap().percent());
+        assert!("90%".parse::<Brightnes>().is_err());
+
+        assert_eq!("2.5s", EffectTime::S2_5.to_string());
+        assert_eq!(5.0, "5s".parse::<EffectTime>().unwrap().seconds());
+        assert!("5.5s".parse::<EffectTime>().is_err());
+        assert_eq!(1.0, EffectTime::from_seconds(1.0).unwrap().seconds());
+        assert!(EffectTime::from_seconds(0.75).is_err());
+    }
+
     #[test]
     fn brightnes() {
         assert_eq!(0x12, Brightnes::P5.to_raw());
@@ -1,3 +1,4 @@
+pub mod dump;
 pub mod error;
 pub mod led;
 
@@ -21,17 +22,23 @@ pub struct ConfigData {
     pub data: [u8; 154 - 115],
 }
 
-impl std::fmt::Debug for ConfigData {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Point")
+impl core::fmt::Debug for ConfigData {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ConfigData")
+            .field("sec_1", &self.sec_1)
             .field("report_rate", &self.report_rate)
+            .field("sec_2", &self.sec_2)
             .field("active_profile_list_len", &self.active_profiles_list_len)
+            .field("sec_3", &self.sec_3)
             .field("profiles_dpi", &self.profiles_dpi)
+            .field("sec_5", &self.sec_5)
             .field("led_mode", &self.led_mode)
             .field("led_arg1", &self.led_arg1)
             .field("led_arg2", &self.led_arg2)
             .field("led_arg3", &self.led_arg3)
+            .field("sec_9", &self.sec_9)
             .field("profiles_rgb", &self.profiles_rgb)
+            .field("data", &self.data)
             .finish()
     }
 }
@@ -51,11 +58,20 @@ impl Default for ConfigData {
             0x78, 0xff, 0xff, 0x00, 0x00, 0xff, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff,
             0x00, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
         ];
-        unsafe { std::mem::transmute(packet_bytes) }
+        Self::from_bytes(packet_bytes)
     }
 }
 
 impl ConfigData {
+    pub fn from_bytes(bytes: [u8; 154]) -> Self {
+        unsafe { core::mem::transmute(bytes) }
+    }
+
+    /// Raw report, as it travels over the wire
+    pub fn as_bytes(&self) -> &[u8; 154] {
+        unsafe { &*(self as *const Self as *const [u8; 154]) }
+    }
+
     pub fn set_led_config(
         &mut self,
         led_mode: u8,
@@ -81,8 +97,8 @@ impl ConfigData {
             let active = ((dpi >> 7) & 1) == 0;
             // Strip first bit
             let dpi = dpi & 0b01111111;
-            // Make it 0 indexed
-            let dpi = dpi - 1;
+            // Make it 0 indexed, an invalid 0 wraps to a step `DPI_LIST` doesn't have
+            let dpi = dpi.wrapping_sub(1);
 
             *out = (active, dpi);
         }
@@ -127,6 +143,34 @@ impl ConfigData {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn bytes() {
+        let mut bytes = *ConfigData::default().as_bytes();
+        bytes[8] = 0x42;
+        bytes[100] = 0x12;
+        // Step 0 doesn't exist
+        bytes[74] = 0x80;
+
+        let data = ConfigData::from_bytes(bytes);
+        assert_eq!(0x42, data.report_rate);
+        assert_eq!(0x12, data.profiles_rgb[0][0]);
+        assert_eq!(&bytes, data.as_bytes());
+
+        assert_eq!((false, 0xff), data.profiles_dpi()[0]);
+        assert!(crate::config::Config::try_new(data.clone()).is_err());
+        let profile = crate::config::Config::new(data).profiles()[0];
+        assert_eq!(None, profile.dpi_value());
+
+        let mut bytes = *ConfigData::default().as_bytes();
+        bytes[93] = 0x99;
+        assert!(crate::config::Config::try_new(ConfigData::from_bytes(bytes)).is_err());
+        // Breathe with a speed byte below the 0x02 offset
+        bytes[93] = 0x22;
+        bytes[96] = 0x00;
+        assert!(crate::config::Config::try_new(ConfigData::from_bytes(bytes)).is_err());
+        assert!(crate::config::Config::try_new(ConfigData::default()).is_ok());
+    }
+
     #[test]
     fn steady() {
         let mut data = ConfigData::default();
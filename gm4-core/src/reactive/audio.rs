@@ -0,0 +1,519 @@
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::Source;
+
+/// Layout of signed 16 bit little endian PCM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            channels: 2,
+        }
+    }
+}
+
+impl PcmFormat {
+    /// Higher rates would only make huge buffers
+    pub const MAX_SAMPLE_RATE: u32 = 768_000;
+    pub const MAX_CHANNELS: u16 = 32;
+
+    /// Whether `AudioSource` can analyze it, there has to be at least one channel and a rate
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sample_rate == 0 || self.sample_rate > Self::MAX_SAMPLE_RATE {
+            return Err(format!(
+                "sample rate {} is not between 1 and {}",
+                self.sample_rate,
+                Self::MAX_SAMPLE_RATE
+            ));
+        }
+        if self.channels == 0 || self.channels > Self::MAX_CHANNELS {
+            return Err(format!(
+                "{} channels is not between 1 and {}",
+                self.channels,
+                Self::MAX_CHANNELS
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_wav(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid WAV: {}", msg))
+}
+
+/// Reads the header of a 16 bit PCM WAV file, leaving `reader` at the first sample
+pub fn read_wav_header<R: Read>(reader: &mut R) -> io::Result<PcmFormat> {
+    let mut riff = [0; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(invalid_wav("not a RIFF WAVE file"));
+    }
+
+    let mut format = None;
+    loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+
+        match &header[0..4] {
+            b"fmt " => {
+                // 16 bytes for PCM, 40 for WAVE_FORMAT_EXTENSIBLE
+                if len > 64 {
+                    return Err(invalid_wav("fmt chunk too large"));
+                }
+                let mut fmt = vec![0; len as usize];
+                reader.read_exact(&mut fmt)?;
+                if fmt.len() < 16 {
+                    return Err(invalid_wav("short fmt chunk"));
+                }
+
+                let tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+                // 0xfffe is WAVE_FORMAT_EXTENSIBLE, which ffmpeg and sox use for plain PCM too
+                if (tag != 1 && tag != 0xfffe) || bits != 16 {
+                    return Err(invalid_wav("only 16 bit PCM is supported"));
+                }
+
+                let pcm = PcmFormat {
+                    channels: u16::from_le_bytes([fmt[2], fmt[3]]),
+                    sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                };
+                pcm.validate().map_err(|msg| invalid_wav(&msg))?;
+                format = Some(pcm);
+            }
+            b"data" => return format.ok_or_else(|| invalid_wav("data before fmt chunk")),
+            _ => {
+                // Chunks are padded to an even length
+                io::copy(&mut reader.take(len + len % 2), &mut io::sink())?;
+            }
+        }
+    }
+}
+
+/// Records the monitor of the default sink, through `parec`.
+///
+/// Works with PulseAudio and with PipeWire's pulse server, raw PCM comes out of `stdout`.
+pub fn spawn_monitor(format: PcmFormat) -> io::Result<Child> {
+    Command::new("parec")
+        .args([
+            "--device=@DEFAULT_MONITOR@",
+            "--format=s16le",
+            &format!("--rate={}", format.sample_rate),
+            &format!("--channels={}", format.channels),
+            "--latency-msec=20",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+}
+
+/// What the sound is turned into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioMode {
+    /// Brightness of the color follows the loudness
+    Level,
+    /// Color flashes on every beat and fades out
+    Beat,
+    /// Bass, mids and treble drive red, green and blue
+    Spectrum,
+}
+
+impl FromStr for AudioMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "level" => Ok(AudioMode::Level),
+            "beat" => Ok(AudioMode::Beat),
+            "spectrum" => Ok(AudioMode::Spectrum),
+            _ => Err(format!(
+                "Unknown mode {}, expected level, beat or spectrum",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AudioSettings {
+    pub mode: AudioMode,
+    /// Color used by `Level` and `Beat`
+    pub color: [u8; 3],
+    /// How much of the previous value is kept every window, `0.0` for none,
+    /// close to `1.0` for slow changes
+    pub smoothing: f64,
+    /// Multiplier applied to the loudness before it is clamped to `1.0`
+    pub gain: f64,
+    /// Length of audio analysed for every color
+    pub window: Duration,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            mode: AudioMode::Level,
+            color: [255, 255, 255],
+            smoothing: 0.6,
+            gain: 4.0,
+            window: Duration::from_millis(20),
+        }
+    }
+}
+
+/// One pole low-pass filter
+#[derive(Debug, Clone, Copy)]
+struct LowPass {
+    alpha: f64,
+    state: f64,
+}
+
+impl LowPass {
+    fn new(cutoff: f64, sample_rate: u32) -> Self {
+        let alpha = 1.0 - (-2.0 * std::f64::consts::PI * cutoff / sample_rate as f64).exp();
+        Self { alpha, state: 0.0 }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.state += self.alpha * (sample - self.state);
+        self.state
+    }
+}
+
+/// Turns windows of mono samples in `-1.0..=1.0` into colors.
+///
+/// Pure computation, the same samples always give the same colors.
+pub struct Analyzer {
+    settings: AudioSettings,
+    bass: LowPass,
+    mids: LowPass,
+    level: f64,
+    bands: [f64; 3],
+    /// Slow moving average of the window energy, beats are windows well above it
+    energy_avg: f64,
+    beat: f64,
+}
+
+impl Analyzer {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> Self {
+        Self {
+            settings,
+            bass: LowPass::new(250.0, sample_rate),
+            mids: LowPass::new(4000.0, sample_rate),
+            level: 0.0,
+            bands: [0.0; 3],
+            energy_avg: 0.0,
+            beat: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f64]) -> [u8; 3] {
+        let mut total = 0.0;
+        let mut sums = [0.0; 3];
+        for &s in samples {
+            total += s * s;
+
+            let bass = self.bass.process(s);
+            let below_treble = self.mids.process(s);
+            let bands = [bass, below_treble - bass, s - below_treble];
+
+            for (sum, band) in sums.iter_mut().zip(bands.iter()) {
+                *sum += band * band;
+            }
+        }
+
+        let n = samples.len().max(1) as f64;
+        let gain = self.settings.gain;
+        let rms = |sum: f64| ((sum / n).sqrt() * gain).min(1.0);
+
+        let k = self.settings.smoothing.clamp(0.0, 0.99);
+        let smooth = |old: f64, new: f64| old * k + new * (1.0 - k);
+
+        let energy = total / n;
+        let level = rms(total);
+        self.level = smooth(self.level, level);
+        for (band, sum) in self.bands.iter_mut().zip(sums.iter()) {
+            *band = smooth(*band, rms(*sum));
+        }
+
+        let is_beat = energy > self.energy_avg * 1.5 && level > 0.05;
+        self.energy_avg = self.energy_avg * 0.95 + energy * 0.05;
+        self.beat = if is_beat { 1.0 } else { smooth(self.beat, 0.0) };
+
+        let scale = |color: [u8; 3], k: f64| {
+            let mut out = [0; 3];
+            for (o, c) in out.iter_mut().zip(color.iter()) {
+                *o = (*c as f64 * k).round() as u8;
+            }
+            out
+        };
+
+        match self.settings.mode {
+            AudioMode::Level => scale(self.settings.color, self.level),
+            AudioMode::Beat => scale(self.settings.color, self.beat),
+            AudioMode::Spectrum => {
+                let [r, g, b] = self.bands;
+                [
+                    (r * 255.0).round() as u8,
+                    (g * 255.0).round() as u8,
+                    (b * 255.0).round() as u8,
+                ]
+            }
+        }
+    }
+}
+
+/// Color of the audio coming from `input`, one analysis window per `color` call.
+///
+/// Reads block until a whole window is available, so live input paces `follow`
+/// by itself. The end of the input is reported as `UnexpectedEof`.
+pub struct AudioSource<R: Read> {
+    input: R,
+    format: PcmFormat,
+    analyzer: Analyzer,
+    window: usize,
+    buf: Vec<u8>,
+    samples: Vec<f64>,
+}
+
+impl<R: Read> AudioSource<R> {
+    pub fn new(input: R, format: PcmFormat, settings: AudioSettings) -> Self {
+        let window = ((format.sample_rate as f64 * settings.window.as_secs_f64()) as usize).max(1);
+        let channels = format.channels.max(1) as usize;
+
+        Self {
+            input,
+            format,
+            analyzer: Analyzer::new(settings, format.sample_rate),
+            window,
+            buf: vec![0; window * channels * 2],
+            samples: Vec::with_capacity(window),
+        }
+    }
+
+    /// Audio time covered by one `color` call
+    pub fn window_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.window as f64 / self.format.sample_rate as f64)
+    }
+}
+
+impl<R: Read> Source for AudioSource<R> {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        self.input.read_exact(&mut self.buf)?;
+
+        let channels = self.format.channels.max(1) as usize;
+        self.samples.clear();
+        for frame in self.buf.chunks_exact(channels * 2) {
+            let sum: f64 = frame
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]) as f64 / 32768.0)
+                .sum();
+            self.samples.push(sum / channels as f64);
+        }
+
+        Ok(self.analyzer.process(&self.samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{ManualClock, Player};
+    use crate::config::Config;
+    use crate::device::MockDevice;
+    use crate::reactive::{follow, ReactiveError};
+
+    /// Stereo PCM: `silence` seconds of nothing, then a 100Hz tone in 0.1s bursts
+    fn pcm(format: PcmFormat, silence: f64, tone: f64) -> Vec<u8> {
+        let rate = format.sample_rate as f64;
+        let mut out = Vec::new();
+
+        let total = ((silence + tone) * rate) as usize;
+        for i in 0..total {
+            let t = i as f64 / rate;
+            let on = t >= silence && ((t - silence) * 10.0) as u64 & 1 == 0;
+            let v = if on {
+                (t * 100.0 * 2.0 * std::f64::consts::PI).sin() * 0.5
+            } else {
+                0.0
+            };
+            let sample = ((v * 32767.0) as i16).to_le_bytes();
+            for _ in 0..format.channels {
+                out.extend_from_slice(&sample);
+            }
+        }
+        out
+    }
+
+    fn wav(format: PcmFormat, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + 10 + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&format.channels.to_le_bytes());
+        out.extend_from_slice(&format.sample_rate.to_le_bytes());
+        let block = format.channels as u32 * 2;
+        out.extend_from_slice(&(format.sample_rate * block).to_le_bytes());
+        out.extend_from_slice(&(block as u16).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        // Odd sized chunk before the data, has to be skipped with its padding
+        out.extend_from_slice(b"LIST");
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn colors(settings: AudioSettings, data: &[u8], format: PcmFormat) -> Vec<[u8; 3]> {
+        let mut source = AudioSource::new(data, format, settings);
+        let mut out = Vec::new();
+        while let Ok(color) = source.color() {
+            out.push(color);
+        }
+        out
+    }
+
+    #[test]
+    fn wav_header() {
+        let format = PcmFormat {
+            sample_rate: 8000,
+            channels: 1,
+        };
+        let file = wav(format, &[1, 0, 2, 0]);
+
+        let mut reader = file.as_slice();
+        assert_eq!(format, read_wav_header(&mut reader).unwrap());
+        assert_eq!(&[1, 0, 2, 0], reader);
+
+        assert!(read_wav_header(&mut &b"RIFF\0\0\0\0AVI "[..]).is_err());
+
+        let bad = [
+            PcmFormat {
+                sample_rate: 0,
+                channels: 1,
+            },
+            PcmFormat {
+                sample_rate: 8000,
+                channels: 0,
+            },
+        ];
+        for format in bad.iter() {
+            let file = wav(*format, &[1, 0]);
+            assert!(
+                read_wav_header(&mut file.as_slice()).is_err(),
+                "{:?}",
+                format
+            );
+        }
+
+        // A huge fmt chunk is refused before it is read
+        let mut file = wav(format, &[]);
+        file[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = read_wav_header(&mut file.as_slice()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn modes() {
+        let format = PcmFormat::default();
+        let data = pcm(format, 0.2, 0.4);
+
+        let level = colors(AudioSettings::default(), &data, format);
+        // 20ms windows
+        assert_eq!(30, level.len());
+        assert_eq!([0, 0, 0], level[9]);
+        assert!(level[14][0] > 100);
+        assert!(level.iter().all(|c| c[0] == c[1] && c[1] == c[2]));
+
+        // Deterministic
+        assert_eq!(level, colors(AudioSettings::default(), &data, format));
+
+        let spectrum = colors(
+            AudioSettings {
+                mode: AudioMode::Spectrum,
+                ..AudioSettings::default()
+            },
+            &data,
+            format,
+        );
+        // A 100Hz tone is all bass
+        assert!(spectrum[14][0] > 100);
+        assert!(spectrum[14][2] < 10);
+
+        let beat = colors(
+            AudioSettings {
+                mode: AudioMode::Beat,
+                color: [255, 0, 0],
+                ..AudioSettings::default()
+            },
+            &data,
+            format,
+        );
+        // Every burst starts a beat, silence in between fades out
+        assert_eq!([0, 0, 0], beat[9]);
+        assert_eq!([255, 0, 0], beat[10]);
+        assert!(beat[19][0] < 50);
+        assert_eq!([255, 0, 0], beat[20]);
+    }
+
+    #[test]
+    fn smoothing() {
+        let format = PcmFormat::default();
+        let data = pcm(format, 0.0, 0.1);
+
+        let sharp = colors(
+            AudioSettings {
+                smoothing: 0.0,
+                ..AudioSettings::default()
+            },
+            &data,
+            format,
+        );
+        let smooth = colors(
+            AudioSettings {
+                smoothing: 0.9,
+                ..AudioSettings::default()
+            },
+            &data,
+            format,
+        );
+        assert!(smooth[1][0] < sharp[1][0]);
+    }
+
+    #[test]
+    fn follow_wav() {
+        let format = PcmFormat::default();
+        let file = wav(format, &pcm(format, 0.2, 0.8));
+
+        let mut reader = file.as_slice();
+        let format = read_wav_header(&mut reader).unwrap();
+        let mut source = AudioSource::new(reader, format, AudioSettings::default());
+        let window = source.window_duration();
+
+        let device = MockDevice::default();
+        let mut player = Player::new(device.clone(), ManualClock::default(), Config::default())
+            .with_min_interval(Duration::from_millis(100));
+
+        let res = follow(&mut player, &mut source, window, None);
+        match res {
+            Err(ReactiveError::Source(err)) => assert_eq!(io::ErrorKind::UnexpectedEof, err.kind()),
+            other => panic!("{:?}", other),
+        }
+
+        // 1s of audio, at most 10 updates a second
+        let sent = device.sent().len();
+        assert!((5..=10).contains(&sent), "{}", sent);
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+use std::process::Command;
+
+use super::{file::parse_value, Scale, Source};
+
+/// Output of a command, parsed like `FileValue`
+pub struct CommandValue {
+    program: String,
+    args: Vec<String>,
+    scale: Scale,
+}
+
+impl CommandValue {
+    pub fn new(program: &str, args: &[&str], scale: Scale) -> Self {
+        Self {
+            program: program.to_owned(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            scale,
+        }
+    }
+
+    /// Number of notifications waiting in dunst
+    pub fn notifications(scale: Scale) -> Self {
+        Self::new("dunstctl", &["count", "waiting"], scale)
+    }
+}
+
+impl Source for CommandValue {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        let out = Command::new(&self.program).args(&self.args).output()?;
+
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "{} exited with {}",
+                self.program, out.status
+            )));
+        }
+
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        parse_value(stdout.lines().last().unwrap_or_default(), &self.scale)
+    }
+}
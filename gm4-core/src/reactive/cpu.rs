@@ -0,0 +1,146 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Scale, Source};
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// CPU usage between two samples, read from `/proc/stat`
+pub struct CpuLoad {
+    stat_path: PathBuf,
+    scale: Scale,
+    prev: Option<(u64, u64)>,
+}
+
+impl CpuLoad {
+    pub fn new(scale: Scale) -> Self {
+        Self::with_path("/proc/stat", scale)
+    }
+
+    pub fn with_path<P: AsRef<Path>>(stat_path: P, scale: Scale) -> Self {
+        Self {
+            stat_path: stat_path.as_ref().to_owned(),
+            scale,
+            prev: None,
+        }
+    }
+
+    /// Load in `0.0..=1.0`
+    pub fn sample(&mut self) -> io::Result<f64> {
+        let stat = std::fs::read_to_string(&self.stat_path)?;
+        let line = stat
+            .lines()
+            .find(|l| l.starts_with("cpu "))
+            .ok_or_else(|| invalid_data("No cpu line in stat file".into()))?;
+
+        let fields = line
+            .split_whitespace()
+            .skip(1)
+            .map(|v| v.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| invalid_data(err.to_string()))?;
+
+        if fields.len() < 4 {
+            return Err(invalid_data(format!("Malformed cpu line: {}", line)));
+        }
+
+        let total: u64 = fields.iter().sum();
+        // idle + iowait
+        let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+
+        let (prev_total, prev_idle) = self.prev.unwrap_or((0, 0));
+        self.prev = Some((total, idle));
+
+        let total = total.saturating_sub(prev_total);
+        let idle = idle.saturating_sub(prev_idle);
+
+        if total == 0 {
+            return Ok(0.0);
+        }
+
+        Ok(1.0 - idle as f64 / total as f64)
+    }
+}
+
+impl Source for CpuLoad {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        let load = self.sample()?;
+        Ok(self.scale.color(load))
+    }
+}
+
+/// Temperature from a sysfs thermal zone, in °C
+pub struct CpuTemp {
+    path: PathBuf,
+    scale: Scale,
+}
+
+impl CpuTemp {
+    pub fn new(scale: Scale) -> Self {
+        Self::with_path("/sys/class/thermal/thermal_zone0/temp", scale)
+    }
+
+    pub fn with_path<P: AsRef<Path>>(path: P, scale: Scale) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            scale,
+        }
+    }
+
+    pub fn sample(&self) -> io::Result<f64> {
+        let raw = std::fs::read_to_string(&self.path)?;
+        let millis: f64 = raw
+            .trim()
+            .parse()
+            .map_err(|_| invalid_data(format!("Invalid temperature: {}", raw.trim())))?;
+
+        Ok(millis / 1000.0)
+    }
+}
+
+impl Source for CpuTemp {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        let temp = self.sample()?;
+        Ok(self.scale.color(temp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactive::tests::temp_dir;
+
+    #[test]
+    fn cpu_load() {
+        let path = temp_dir("cpu-load").join("stat");
+
+        std::fs::write(&path, "cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 1 2 3 4\n").unwrap();
+        let mut source = CpuLoad::with_path(&path, Scale::default());
+        assert!((source.sample().unwrap() - 0.2).abs() < 1e-9);
+
+        // 100 busy and 100 idle ticks since the last sample
+        std::fs::write(&path, "cpu  150 0 150 850 50 0 0 0 0 0\n").unwrap();
+        assert!((source.sample().unwrap() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cpu_temp() {
+        let path = temp_dir("cpu-temp").join("temp");
+        std::fs::write(&path, "60000\n").unwrap();
+
+        let mut source = CpuTemp::with_path(
+            &path,
+            Scale {
+                min: 40.0,
+                max: 80.0,
+                low: [0, 0, 255],
+                high: [255, 0, 0],
+            },
+        );
+
+        assert_eq!(60.0, source.sample().unwrap());
+        assert_eq!([128, 0, 128], source.color().unwrap());
+    }
+}
@@ -0,0 +1,67 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Scale, Source};
+use crate::config::Rgb;
+
+/// Last line of a file or FIFO.
+///
+/// The line is either a number, mapped through the `Scale`,
+/// or a color like `#rrggbb` used as is, see `config::Rgb`.
+/// Reading a FIFO blocks until a writer shows up.
+pub struct FileValue {
+    path: PathBuf,
+    scale: Scale,
+}
+
+impl FileValue {
+    pub fn new<P: AsRef<Path>>(path: P, scale: Scale) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            scale,
+        }
+    }
+}
+
+pub(crate) fn parse_value(value: &str, scale: &Scale) -> io::Result<[u8; 3]> {
+    let value = value.trim();
+
+    // `123456` is a number, colors without commas need the `#`
+    if let Ok(v) = value.parse::<f64>() {
+        return Ok(scale.color(v));
+    }
+    value.parse::<Rgb>().map(|rgb| rgb.0).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Expected a number or #rrggbb, got: {}", value),
+        )
+    })
+}
+
+impl Source for FileValue {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        let content = std::fs::read_to_string(&self.path)?;
+        let line = content
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or_default();
+
+        parse_value(line, &self.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value() {
+        let scale = Scale::default();
+        assert_eq!([0, 255, 0], parse_value("0", &scale).unwrap());
+        assert_eq!([255, 0, 0], parse_value(" 1.0\n", &scale).unwrap());
+        assert_eq!([0x12, 0x34, 0x56], parse_value("#123456", &scale).unwrap());
+        assert_eq!([1, 2, 3], parse_value("1, 2, 3", &scale).unwrap());
+        assert!(parse_value("abc", &scale).is_err());
+    }
+}
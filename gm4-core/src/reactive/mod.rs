@@ -0,0 +1,188 @@
+//! Lighting driven by the state of the system.
+//!
+//! A `Source` samples some local metric and turns it into a color,
+//! `follow` keeps pushing that color to the mouse.
+
+use std::io;
+use std::time::Duration;
+
+use crate::animation::{lerp_rgb, Clock, Player};
+use crate::device::{Device, DeviceError};
+
+pub mod audio;
+pub mod command;
+pub mod cpu;
+pub mod file;
+
+pub use audio::AudioSource;
+pub use command::CommandValue;
+pub use cpu::{CpuLoad, CpuTemp};
+pub use file::FileValue;
+
+pub trait Source {
+    fn color(&mut self) -> io::Result<[u8; 3]>;
+}
+
+impl<S: Source + ?Sized> Source for Box<S> {
+    fn color(&mut self) -> io::Result<[u8; 3]> {
+        (**self).color()
+    }
+}
+
+/// Maps a value from `min..=max` onto a blend between `low` and `high` colors
+#[derive(Debug, Clone, Copy)]
+pub struct Scale {
+    pub min: f64,
+    pub max: f64,
+    pub low: [u8; 3],
+    pub high: [u8; 3],
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: 1.0,
+            low: [0, 255, 0],
+            high: [255, 0, 0],
+        }
+    }
+}
+
+impl Scale {
+    pub fn color(&self, value: f64) -> [u8; 3] {
+        let range = self.max - self.min;
+        let k = if range != 0.0 {
+            (value - self.min) / range
+        } else {
+            0.0
+        };
+
+        lerp_rgb(self.low, self.high, k)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReactiveError {
+    Source(io::Error),
+    Device(DeviceError),
+}
+
+impl std::fmt::Display for ReactiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReactiveError::Source(err) => write!(f, "Source error: {}", err),
+            ReactiveError::Device(err) => write!(f, "Device error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReactiveError {}
+
+impl From<io::Error> for ReactiveError {
+    fn from(err: io::Error) -> Self {
+        ReactiveError::Source(err)
+    }
+}
+
+impl From<DeviceError> for ReactiveError {
+    fn from(err: DeviceError) -> Self {
+        ReactiveError::Device(err)
+    }
+}
+
+/// Sample `source` every `poll` and push its color through `player`,
+/// until `limit` passes, the player is stopped or forever.
+pub fn follow<D: Device, C: Clock>(
+    player: &mut Player<D, C>,
+    source: &mut dyn Source,
+    poll: Duration,
+    limit: Option<Duration>,
+) -> Result<(), ReactiveError> {
+    let start = player.clock().now();
+
+    loop {
+        if player.stopped() {
+            break;
+        }
+        if let Some(limit) = limit {
+            if player.clock().now() - start >= limit {
+                break;
+            }
+        }
+
+        let color = source.color()?;
+        player.set_color(color)?;
+
+        player.clock().sleep(poll);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::animation::ManualClock;
+    use crate::config::Config;
+    use crate::device::MockDevice;
+    use crate::protocol::ConfigData;
+
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    /// Fresh directory for fake /proc and /sys files
+    pub fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gm4-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn scale() {
+        let scale = Scale {
+            min: 40.0,
+            max: 80.0,
+            low: [0, 0, 0],
+            high: [200, 100, 0],
+        };
+
+        assert_eq!([0, 0, 0], scale.color(20.0));
+        assert_eq!([100, 50, 0], scale.color(60.0));
+        assert_eq!([200, 100, 0], scale.color(100.0));
+    }
+
+    #[test]
+    fn follow_file() {
+        let dir = temp_dir("follow");
+        let path = dir.join("value");
+        std::fs::write(&path, "0.5\n").unwrap();
+
+        let mut source = FileValue::new(&path, Scale::default());
+
+        let device = MockDevice::default();
+        let mut player = Player::new(
+            device.clone(),
+            ManualClock::default(),
+            Config::new(ConfigData::default()),
+        );
+
+        follow(
+            &mut player,
+            &mut source,
+            Duration::from_secs(1),
+            Some(Duration::from_secs(3)),
+        )
+        .unwrap();
+
+        // Same value every time, so only the first one reaches the mouse
+        assert_eq!(1, device.sent().len());
+        assert_eq!([[128, 128, 0]; 5], device.config_data().profiles_rgb());
+
+        // Without a limit only the stop flag ends it
+        let mut player = player.with_stop(Arc::new(AtomicBool::new(true)));
+        follow(&mut player, &mut source, Duration::from_secs(1), None).unwrap();
+        assert_eq!(1, device.sent().len());
+    }
+}
@@ -0,0 +1,503 @@
+//! Serde support, enabled by the `serde` feature.
+//!
+//! Values are written the way a user would type them: `"85%"`, `"2.5s"`, `"3Hz"`,
+//! DPI as a number and colors as `"#rrggbb"`. Plain numbers are accepted for the led
+//! values, so `85` reads the same as `"85%"`. Effects look like
+//! `{"mode": "blink", "frequency": "3Hz", "times": "3", "effect": {"mode": "neon", "speed": "2s"}}`,
+//! missing fields take their default value.
+//!
+//! The unit of the report rate byte is not known, so it is kept raw as `report_rate_raw`
+//! rather than passed off as a rate.
+
+use alloc::format;
+use alloc::string::ToString;
+use core::fmt;
+use core::marker::PhantomData;
+use core::str::FromStr;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, Profile, Rgb};
+use crate::protocol::error::ProtocolError;
+use crate::protocol::led::{self, blink, breathe, neon, steady};
+
+struct UnitVisitor<T>(PhantomData<T>);
+
+impl<T> UnitVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    fn parse<E: de::Error>(v: &str) -> Result<T, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de, T> Visitor<'de> for UnitVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value like \"85%\", \"2.5s\" or a number")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        Self::parse(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        Self::parse(&v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        Self::parse(&v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+        Self::parse(&v.to_string())
+    }
+}
+
+/// Led values go through their `Display` and `FromStr` impls
+macro_rules! unit_serde {
+    ($($ty:ty),*) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(UnitVisitor(PhantomData))
+            }
+        }
+    )*};
+}
+
+unit_serde!(
+    steady::Brightnes,
+    steady::EffectTime,
+    breathe::Speed,
+    neon::Speed,
+    blink::Frequency,
+    blink::Times
+);
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum EffectRepr {
+    Breathe {
+        #[serde(default)]
+        speed: breathe::Speed,
+    },
+    Neon {
+        #[serde(default)]
+        speed: neon::Speed,
+    },
+    Steady {
+        #[serde(default)]
+        brightness: steady::Brightnes,
+    },
+}
+
+impl From<led::Effect> for EffectRepr {
+    fn from(effect: led::Effect) -> Self {
+        match effect {
+            led::Effect::Respiration(speed) => EffectRepr::Breathe { speed },
+            led::Effect::Neon(speed) => EffectRepr::Neon { speed },
+            led::Effect::Steady(brightness) => EffectRepr::Steady { brightness },
+        }
+    }
+}
+
+impl From<EffectRepr> for led::Effect {
+    fn from(repr: EffectRepr) -> Self {
+        match repr {
+            EffectRepr::Breathe { speed } => led::Effect::Respiration(speed),
+            EffectRepr::Neon { speed } => led::Effect::Neon(speed),
+            EffectRepr::Steady { brightness } => led::Effect::Steady(brightness),
+        }
+    }
+}
+
+impl Serialize for led::Effect {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EffectRepr::from(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for led::Effect {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        EffectRepr::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum LedConfigRepr {
+    Steady {
+        #[serde(default)]
+        brightness: steady::Brightnes,
+    },
+    Breathe {
+        #[serde(default)]
+        speed: breathe::Speed,
+    },
+    Blink {
+        #[serde(default)]
+        frequency: blink::Frequency,
+        #[serde(default)]
+        times: blink::Times,
+        #[serde(default)]
+        effect: led::Effect,
+    },
+    SteadyEffect {
+        #[serde(default)]
+        time: steady::EffectTime,
+        #[serde(default)]
+        effect: led::Effect,
+    },
+}
+
+impl From<led::Config> for LedConfigRepr {
+    fn from(config: led::Config) -> Self {
+        match config {
+            led::Config::Steady(brightness) => LedConfigRepr::Steady { brightness },
+            led::Config::Breathe(speed) => LedConfigRepr::Breathe { speed },
+            led::Config::BlinkEffect(frequency, times, effect) => LedConfigRepr::Blink {
+                frequency,
+                times,
+                effect,
+            },
+            led::Config::SteadyEffect(time, effect) => LedConfigRepr::SteadyEffect { time, effect },
+        }
+    }
+}
+
+impl From<LedConfigRepr> for led::Config {
+    fn from(repr: LedConfigRepr) -> Self {
+        match repr {
+            LedConfigRepr::Steady { brightness } => led::Config::Steady(brightness),
+            LedConfigRepr::Breathe { speed } => led::Config::Breathe(speed),
+            LedConfigRepr::Blink {
+                frequency,
+                times,
+                effect,
+            } => led::Config::BlinkEffect(frequency, times, effect),
+            LedConfigRepr::SteadyEffect { time, effect } => led::Config::SteadyEffect(time, effect),
+        }
+    }
+}
+
+impl Serialize for led::Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LedConfigRepr::from(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for led::Config {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        LedConfigRepr::deserialize(deserializer).map(Into::into)
+    }
+}
+
+/// `"#rrggbb"`, anything `Rgb::from_str` takes and `[r, g, b]` are accepted
+impl Serialize for Rgb {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RgbVisitor;
+
+        impl<'de> Visitor<'de> for RgbVisitor {
+            type Value = Rgb;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a \"#rrggbb\" or \"r,g,b\" color or [r, g, b]")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Rgb, E> {
+                v.parse()
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Rgb, A::Error> {
+                let mut rgb = [0; 3];
+                for (i, c) in rgb.iter_mut().enumerate() {
+                    *c = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(de::Error::invalid_length(4, &self));
+                }
+                Ok(Rgb(rgb))
+            }
+        }
+
+        deserializer.deserialize_any(RgbVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProfileRepr {
+    active: bool,
+    /// `null` for a step that is not in `DPI_LIST`
+    dpi: Option<u16>,
+    rgb: Rgb,
+}
+
+impl Serialize for Profile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ProfileRepr {
+            active: self.active,
+            dpi: self.dpi_value(),
+            rgb: Rgb(self.rgb),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Profile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ProfileRepr::deserialize(deserializer)?;
+        let dpi = match repr.dpi {
+            Some(dpi) => config::dpi_id(dpi)
+                .ok_or_else(|| de::Error::custom(format!("unsupported DPI {}", dpi)))?,
+            None => return Err(de::Error::custom("missing DPI")),
+        };
+
+        Ok(Profile {
+            active: repr.active,
+            dpi,
+            rgb: repr.rgb.0,
+        })
+    }
+}
+
+/// Changes to a profile, fields that are left out are kept. Takes the same values as
+/// `Profile`, e.g. `{"dpi": 1600}` or `{"active": false, "rgb": "#ff0000"}`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileUpdate {
+    active: Option<bool>,
+    dpi: Option<u16>,
+    rgb: Option<Rgb>,
+}
+
+impl ProfileUpdate {
+    /// Applies the update to profile `id`, nothing is changed on an unsupported DPI
+    pub fn apply(&self, config: &mut Config, id: usize) -> Result<(), ProtocolError> {
+        let dpi =
+            match self.dpi {
+                Some(dpi) => Some(config::dpi_id(dpi).ok_or_else(|| {
+                    ProtocolError::InvalidValue(format!("unsupported DPI {}", dpi))
+                })?),
+                None => None,
+            };
+
+        if let Some(active) = self.active {
+            config.set_profile_active(id, active);
+        }
+        if let Some(dpi) = dpi {
+            config.set_profile_dpi(id, dpi);
+        }
+        if let Some(rgb) = &self.rgb {
+            config.set_profile_rgb(id, rgb.0);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ConfigRepr {
+    profiles: [Profile; 5],
+    effect: led::Config,
+    report_rate_raw: u8,
+}
+
+impl Default for ConfigRepr {
+    fn default() -> Self {
+        let config = Config::default();
+        ConfigRepr {
+            profiles: *config.profiles(),
+            effect: *config.led_config(),
+            report_rate_raw: config.report_rate(),
+        }
+    }
+}
+
+impl Serialize for Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigRepr {
+            profiles: *self.profiles(),
+            effect: *self.led_config(),
+            report_rate_raw: self.report_rate(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Report bytes the schema doesn't cover are taken from the default report
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ConfigRepr::deserialize(deserializer)?;
+
+        let mut config = Config::default();
+        for (id, profile) in repr.profiles.iter().enumerate() {
+            // Also rewrites the active profile count, which the default report doesn't
+            // store the usual way, so only touch it on an actual change
+            if config.profiles()[id].active != profile.active {
+                config.set_profile_active(id, profile.active);
+            }
+            config.set_profile_dpi(id, profile.dpi);
+            config.set_profile_rgb(id, profile.rgb);
+        }
+        config.set_led_effect(repr.effect);
+        config.set_report_rate(repr.report_rate_raw);
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ConfigData;
+    use serde_json::json;
+
+    #[test]
+    fn led_values() {
+        let config = led::Config::BlinkEffect(
+            blink::Frequency::Hz3,
+            blink::Times::T2,
+            led::Effect::Neon(neon::Speed::S1_5),
+        );
+        let value = serde_json::to_value(config).unwrap();
+        assert_eq!(
+            json!({
+                "mode": "blink",
+                "frequency": "3Hz",
+                "times": "2",
+                "effect": { "mode": "neon", "speed": "1.5s" },
+            }),
+            value
+        );
+        let back: led::Config = serde_json::from_value(value).unwrap();
+        assert_eq!(config.to_raw(), back.to_raw());
+
+        let steady: led::Config =
+            serde_json::from_value(json!({ "mode": "steady", "brightness": 45 })).unwrap();
+        assert_eq!(
+            led::Config::Steady(steady::Brightnes::P45).to_raw(),
+            steady.to_raw()
+        );
+
+        let partial: led::Config =
+            serde_json::from_value(json!({ "mode": "steady_effect", "time": 2.5 })).unwrap();
+        let expected = led::Config::SteadyEffect(steady::EffectTime::S2_5, led::Effect::default());
+        assert_eq!(expected.to_raw(), partial.to_raw());
+
+        assert!(serde_json::from_value::<led::Config>(json!({ "mode": "disco" })).is_err());
+        assert!(serde_json::from_value::<steady::Brightnes>(json!("42%")).is_err());
+    }
+
+    #[test]
+    fn profile() {
+        let profile = Profile {
+            active: true,
+            dpi: config::dpi_id(3200).unwrap(),
+            rgb: [0xff, 0x00, 0x10],
+        };
+        let value = serde_json::to_value(profile).unwrap();
+        assert_eq!(
+            json!({ "active": true, "dpi": 3200, "rgb": "#ff0010" }),
+            value
+        );
+
+        let back: Profile =
+            serde_json::from_value(json!({ "active": false, "dpi": 800, "rgb": [1, 2, 3] }))
+                .unwrap();
+        assert_eq!(0, back.dpi);
+        assert_eq!([1, 2, 3], back.rgb);
+
+        let back: Profile =
+            serde_json::from_value(json!({ "active": true, "dpi": 800, "rgb": " 4, 5,6" }))
+                .unwrap();
+        assert_eq!([4, 5, 6], back.rgb);
+
+        let bad = [
+            json!({ "active": true, "dpi": 1234, "rgb": "#000000" }),
+            json!({ "active": true, "dpi": 800, "rgb": "1,2" }),
+            json!({ "active": true, "dpi": 800, "rgb": "#00000" }),
+            json!({ "active": true, "dpi": 800, "rgb": [1, 2] }),
+        ];
+        for value in bad.iter() {
+            assert!(
+                serde_json::from_value::<Profile>(value.clone()).is_err(),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn config() {
+        let mut config = Config::default();
+        config.set_profile_active(4, false);
+        config.set_profile_dpi(1, config::dpi_id(7200).unwrap());
+        config.set_profile_rgb(2, [1, 2, 3]);
+        config.set_led_effect(led::Config::Steady(steady::Brightnes::P85));
+        config.set_report_rate(7);
+
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(json!(7200), value["profiles"][1]["dpi"]);
+        assert_eq!(json!("85%"), value["effect"]["brightness"]);
+        assert_eq!(json!(7), value["report_rate_raw"]);
+
+        let back: Config = serde_json::from_value(value).unwrap();
+        let raw: &ConfigData = (&config).into();
+        let back_raw: &ConfigData = (&back).into();
+        assert_eq!(&raw.as_bytes()[..], &back_raw.as_bytes()[..]);
+
+        let empty: Config = serde_json::from_value(json!({})).unwrap();
+        let empty_raw: &ConfigData = (&empty).into();
+        assert_eq!(
+            &ConfigData::default().as_bytes()[..],
+            &empty_raw.as_bytes()[..]
+        );
+    }
+
+    #[test]
+    fn profile_update() {
+        let update = |config: &mut Config, value| {
+            serde_json::from_value::<ProfileUpdate>(value)
+                .map_err(|err| ProtocolError::InvalidValue(err.to_string()))?
+                .apply(config, 2)
+        };
+
+        let mut config = Config::default();
+        update(&mut config, json!({ "dpi": 1600, "rgb": "#010203" })).unwrap();
+        update(&mut config, json!({ "active": false })).unwrap();
+        let profile = config.profiles()[2];
+        assert_eq!(
+            (false, Some(1600), [1, 2, 3]),
+            (profile.active, profile.dpi_value(), profile.rgb)
+        );
+
+        // A bad DPI leaves the rest alone
+        assert!(update(&mut config, json!({ "dpi": 1234, "rgb": [4, 5, 6] })).is_err());
+        assert!(update(&mut config, json!({ "color": "#000000" })).is_err());
+        assert_eq!([1, 2, 3], config.profiles()[2].rgb);
+    }
+}
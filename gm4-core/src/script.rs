@@ -0,0 +1,569 @@
+//! User scripts, written in [Rhai](https://rhai.rs).
+//!
+//! Scripts only see the functions registered here, there is no file system or process
+//! access beyond `read_file` on explicitly allowed paths:
+//!
+//! - `profile_color(id)`, `set_profile_color(id, "#rrggbb")`, `set_profile_color(id, r, g, b)`
+//! - `profile_dpi(id)`, `set_profile_dpi(id, dpi)`, DPI as a number like `1600`
+//! - `profile_active(id)`, `set_profile_active(id, active)`
+//! - `effect()`, `set_effect(#{ mode: "steady", brightness: "85%" })` or `set_effect("breathe")`
+//! - `apply()` sends the changes to the mouse, `connected()`. Scripts usually apply
+//!   frame after frame, so their applies are deliberately not added to the history.
+//! - `sleep(seconds)`, at least `MIN_SLEEP`, `now()` in seconds since the script started
+//! - `read_file(path)`
+//!
+//! The top level runs once. Scripts that want to react to the mouse being plugged in
+//! or out define `fn on_connect()` and `fn on_disconnect()`, these are only called
+//! after the top level has returned.
+//!
+//! `import` is disabled, and a script that runs more than `MAX_OPERATIONS` without a
+//! `sleep` or builds huge strings or arrays is stopped with an error.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, ParseError, Scope, AST};
+
+use crate::animation::Clock;
+use crate::config::{self, Config, Rgb};
+use crate::device::{Device, DeviceError};
+use crate::protocol::led;
+use crate::protocol::ConfigData;
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Parse(ParseError),
+    Eval(Box<EvalAltResult>),
+    Device(DeviceError),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Parse(err) => write!(f, "Script parse error: {}", err),
+            ScriptError::Eval(err) => write!(f, "Script error: {}", err),
+            ScriptError::Device(err) => write!(f, "Device error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<ParseError> for ScriptError {
+    fn from(err: ParseError) -> Self {
+        ScriptError::Parse(err)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(err: Box<EvalAltResult>) -> Self {
+        ScriptError::Eval(err)
+    }
+}
+
+impl From<DeviceError> for ScriptError {
+    fn from(err: DeviceError) -> Self {
+        ScriptError::Device(err)
+    }
+}
+
+type RhaiResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Operations a script may run between two `sleep` calls, scripts animating the LEDs
+/// run forever but sleep all the time
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+/// Shorter sleeps are rounded up to this
+pub const MIN_SLEEP: Duration = Duration::from_millis(10);
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Small files under `/proc` with system state, everything else there stays hidden,
+/// e.g. `/proc/self/environ`
+pub const PROC_FILES: [&str; 5] = [
+    "/proc/loadavg",
+    "/proc/meminfo",
+    "/proc/stat",
+    "/proc/uptime",
+    "/proc/net/dev",
+];
+
+/// State the registered functions work on
+struct Host {
+    device: Option<Box<dyn Device + Send>>,
+    config: Config,
+    last_sent: Option<[u8; 154]>,
+    allowed_paths: Vec<PathBuf>,
+}
+
+impl Host {
+    fn profile(&self, id: i64) -> RhaiResult<&config::Profile> {
+        self.config
+            .profiles()
+            .get(id as usize)
+            .filter(|_| id >= 0)
+            .ok_or_else(|| format!("no profile {}", id).into())
+    }
+
+    fn apply(&mut self) -> RhaiResult<()> {
+        let device = self.device.as_mut().ok_or("mouse is not connected")?;
+
+        let raw: &ConfigData = (&self.config).into();
+        let bytes = *raw.as_bytes();
+        if self.last_sent == Some(bytes) {
+            return Ok(());
+        }
+
+        device.send(raw).map_err(|err| err.to_string())?;
+        self.last_sent = Some(bytes);
+        Ok(())
+    }
+
+    fn read_file(&self, path: &str) -> RhaiResult<String> {
+        let denied = || format!("reading {} is not allowed", path).into();
+
+        let path = Path::new(path).canonicalize().map_err(|_| denied())?;
+        if !self.allowed_paths.iter().any(|p| path.starts_with(p)) {
+            return Err(denied());
+        }
+
+        std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+}
+
+fn parse_color(color: &str) -> RhaiResult<[u8; 3]> {
+    color
+        .parse::<Rgb>()
+        .map(|rgb| rgb.0)
+        .map_err(|err| err.to_string().into())
+}
+
+fn color_component(v: i64) -> RhaiResult<u8> {
+    if (0..=255).contains(&v) {
+        Ok(v as u8)
+    } else {
+        Err(format!("color component {} out of range", v).into())
+    }
+}
+
+/// Sleeps without holding the host, so the config stays available to other threads
+type SharedClock = Arc<Mutex<Box<dyn Clock + Send>>>;
+
+fn register_api(
+    engine: &mut Engine,
+    host: &Arc<Mutex<Host>>,
+    clock: &SharedClock,
+    slept_at: &Arc<AtomicU64>,
+) {
+    fn get(host: &Arc<Mutex<Host>>) -> MutexGuard<'_, Host> {
+        host.lock().unwrap()
+    }
+
+    let h = host.clone();
+    engine.register_fn("profile_color", move |id: i64| -> RhaiResult<String> {
+        Ok(Rgb(get(&h).profile(id)?.rgb).to_string())
+    });
+    let h = host.clone();
+    engine.register_fn(
+        "set_profile_color",
+        move |id: i64, color: &str| -> RhaiResult<()> {
+            let rgb = parse_color(color)?;
+            let mut host = get(&h);
+            host.profile(id)?;
+            host.config.set_profile_rgb(id as usize, rgb);
+            Ok(())
+        },
+    );
+    let h = host.clone();
+    engine.register_fn(
+        "set_profile_color",
+        move |id: i64, r: i64, g: i64, b: i64| -> RhaiResult<()> {
+            let rgb = [
+                color_component(r)?,
+                color_component(g)?,
+                color_component(b)?,
+            ];
+            let mut host = get(&h);
+            host.profile(id)?;
+            host.config.set_profile_rgb(id as usize, rgb);
+            Ok(())
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn("profile_dpi", move |id: i64| -> RhaiResult<i64> {
+        let dpi = get(&h).profile(id)?.dpi_value();
+        dpi.map(i64::from)
+            .ok_or_else(|| format!("profile {} has an unknown DPI step", id).into())
+    });
+    let h = host.clone();
+    engine.register_fn(
+        "set_profile_dpi",
+        move |id: i64, dpi: i64| -> RhaiResult<()> {
+            let dpi_id = u16::try_from(dpi)
+                .ok()
+                .and_then(config::dpi_id)
+                .ok_or_else(|| format!("unsupported DPI {}", dpi))?;
+            let mut host = get(&h);
+            host.profile(id)?;
+            host.config.set_profile_dpi(id as usize, dpi_id);
+            Ok(())
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn("profile_active", move |id: i64| -> RhaiResult<bool> {
+        Ok(get(&h).profile(id)?.active)
+    });
+    let h = host.clone();
+    engine.register_fn(
+        "set_profile_active",
+        move |id: i64, active: bool| -> RhaiResult<()> {
+            let mut host = get(&h);
+            host.profile(id)?;
+            host.config.set_profile_active(id as usize, active);
+            Ok(())
+        },
+    );
+
+    let h = host.clone();
+    engine.register_fn("effect", move || -> RhaiResult<Dynamic> {
+        rhai::serde::to_dynamic(get(&h).config.led_config())
+    });
+    let h = host.clone();
+    engine.register_fn("set_effect", move |effect: Dynamic| -> RhaiResult<()> {
+        let effect = if effect.is_string() {
+            let mut map = rhai::Map::new();
+            map.insert("mode".into(), effect);
+            map.into()
+        } else {
+            effect
+        };
+
+        let config: led::Config = rhai::serde::from_dynamic(&effect)?;
+        get(&h).config.set_led_effect(config);
+        Ok(())
+    });
+
+    let h = host.clone();
+    engine.register_fn("apply", move || get(&h).apply());
+    let h = host.clone();
+    engine.register_fn("connected", move || get(&h).device.is_some());
+
+    let sleep = {
+        let clock = clock.clone();
+        let slept_at = slept_at.clone();
+        move |duration: Duration| {
+            // Starts a new operations budget, see `MAX_OPERATIONS`. The minimum keeps
+            // `loop { sleep(0) }` from spinning the CPU with fresh budgets.
+            slept_at.store(u64::MAX, Ordering::Relaxed);
+            clock.lock().unwrap().sleep(duration.max(MIN_SLEEP));
+        }
+    };
+    let s = sleep.clone();
+    engine.register_fn("sleep", move |seconds: f64| -> RhaiResult<()> {
+        if !seconds.is_finite() || seconds < 0.0 || seconds > u32::MAX as f64 {
+            return Err(format!("invalid sleep time {}", seconds).into());
+        }
+        s(Duration::from_secs_f64(seconds));
+        Ok(())
+    });
+    engine.register_fn("sleep", move |seconds: i64| -> RhaiResult<()> {
+        if seconds < 0 {
+            return Err(format!("invalid sleep time {}", seconds).into());
+        }
+        sleep(Duration::from_secs(seconds as u64));
+        Ok(())
+    });
+    let c = clock.clone();
+    engine.register_fn("now", move || c.lock().unwrap().now().as_secs_f64());
+
+    let h = host.clone();
+    engine.register_fn("read_file", move |path: &str| get(&h).read_file(path));
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    host: Arc<Mutex<Host>>,
+    /// Operation count of the last `sleep`, `u64::MAX` until the next operation
+    slept_at: Arc<AtomicU64>,
+}
+
+impl Script {
+    /// Compiles `source`, the script starts without a device, see `connect`
+    pub fn new(source: &str, clock: Box<dyn Clock + Send>) -> Result<Self, ScriptError> {
+        let host = Arc::new(Mutex::new(Host {
+            device: None,
+            config: Config::default(),
+            last_sent: None,
+            allowed_paths: Vec::new(),
+        }));
+        let clock = Arc::new(Mutex::new(clock));
+        let slept_at = Arc::new(AtomicU64::new(0));
+
+        let mut engine = Engine::new();
+        engine.disable_symbol("eval");
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.set_max_string_size(MAX_STRING_SIZE);
+        engine.set_max_array_size(MAX_COLLECTION_SIZE);
+        engine.set_max_map_size(MAX_COLLECTION_SIZE);
+        engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+
+        let s = slept_at.clone();
+        engine.on_progress(move |operations| {
+            let slept_at = match s.load(Ordering::Relaxed) {
+                u64::MAX => {
+                    s.store(operations, Ordering::Relaxed);
+                    operations
+                }
+                slept_at => slept_at,
+            };
+            if operations.saturating_sub(slept_at) > MAX_OPERATIONS {
+                Some("script ran too long without sleep".into())
+            } else {
+                None
+            }
+        });
+        register_api(&mut engine, &host, &clock, &slept_at);
+
+        let ast = engine.compile(source)?;
+
+        Ok(Self {
+            engine,
+            ast,
+            scope: Scope::new(),
+            host,
+            slept_at,
+        })
+    }
+
+    /// Lets `read_file` read `/sys` and the `PROC_FILES`
+    pub fn allow_system_info(&mut self) {
+        self.allow_read("/sys");
+        for path in PROC_FILES.iter() {
+            self.allow_read(path);
+        }
+    }
+
+    /// Lets `read_file` read `path` and everything below it
+    pub fn allow_read(&mut self, path: impl AsRef<Path>) {
+        if let Ok(path) = path.as_ref().canonicalize() {
+            self.host.lock().unwrap().allowed_paths.push(path);
+        }
+    }
+
+    /// Whether the script defines `on_connect` or `on_disconnect`
+    pub fn has_hotplug_hooks(&self) -> bool {
+        self.defines("on_connect") || self.defines("on_disconnect")
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.is_empty())
+    }
+
+    /// Reads the config of `device` and uses it from now on, without calling `on_connect`
+    pub fn attach(&mut self, mut device: Box<dyn Device + Send>) -> Result<(), ScriptError> {
+        let raw_data = device.read()?;
+        let last_sent = *raw_data.as_bytes();
+        let config = Config::try_new(raw_data).map_err(DeviceError::from)?;
+
+        let mut host = self.host.lock().unwrap();
+        host.last_sent = Some(last_sent);
+        host.config = config;
+        host.device = Some(device);
+        Ok(())
+    }
+
+    /// Runs the top level of the script
+    pub fn run(&mut self) -> Result<(), ScriptError> {
+        self.slept_at.store(u64::MAX, Ordering::Relaxed);
+        self.engine.run_ast_with_scope(&mut self.scope, &self.ast)?;
+        Ok(())
+    }
+
+    /// Attaches `device` and calls `on_connect`
+    pub fn connected(&mut self, device: Box<dyn Device + Send>) -> Result<(), ScriptError> {
+        self.attach(device)?;
+        self.call_hook("on_connect")
+    }
+
+    /// Drops the device, then calls `on_disconnect`
+    pub fn disconnected(&mut self) -> Result<(), ScriptError> {
+        self.host.lock().unwrap().device = None;
+        self.call_hook("on_disconnect")
+    }
+
+    fn call_hook(&mut self, name: &str) -> Result<(), ScriptError> {
+        if !self.defines(name) {
+            return Ok(());
+        }
+
+        self.slept_at.store(u64::MAX, Ordering::Relaxed);
+        let options = CallFnOptions::new().eval_ast(false);
+        // Hooks return nothing useful, `Dynamic` accepts whatever the last expression was
+        let _: Dynamic =
+            self.engine
+                .call_fn_with_options(options, &mut self.scope, &self.ast, name, ())?;
+        Ok(())
+    }
+
+    /// Config as the script left it, sent or not
+    pub fn config(&self) -> Config {
+        self.host.lock().unwrap().config.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::ManualClock;
+    use crate::device::MockDevice;
+    use crate::protocol::led::steady;
+
+    fn script(source: &str) -> (Script, MockDevice, ManualClock) {
+        let device = MockDevice::new(ConfigData::default());
+        let clock = ManualClock::default();
+
+        let mut script = Script::new(source, Box::new(clock.clone())).unwrap();
+        script.attach(Box::new(device.clone())).unwrap();
+        (script, device, clock)
+    }
+
+    #[test]
+    fn profiles_and_effect() {
+        let (mut script, device, clock) = script(
+            r##"
+            set_profile_color(0, "#ff0010");
+            set_profile_color(1, 1, 2, 3);
+            set_profile_dpi(2, 1600);
+            set_profile_active(4, false);
+            set_effect(#{ mode: "steady", brightness: 85 });
+            apply();
+            apply();
+
+            sleep(1.5);
+            sleep(0);
+            if effect().brightness != "85%" { throw "wrong effect"; }
+            if profile_color(1) != "#010203" { throw "wrong color"; }
+            set_effect("breathe");
+            apply();
+            "##,
+        );
+        script.run().unwrap();
+
+        assert_eq!(Duration::from_millis(1510), clock.now());
+        let sent = device.sent();
+        assert_eq!(2, sent.len());
+
+        let config = Config::new(sent[0].clone());
+        assert_eq!([0xff, 0x00, 0x10], config.profiles()[0].rgb);
+        assert_eq!([1, 2, 3], config.profiles()[1].rgb);
+        assert_eq!(Some(1600), config.profiles()[2].dpi_value());
+        assert!(!config.profiles()[4].active);
+        assert_eq!(
+            led::Config::Steady(steady::Brightnes::P85).to_raw(),
+            config.led_config().to_raw()
+        );
+        assert_eq!(
+            led::Config::Breathe(Default::default()).to_raw(),
+            script.config().led_config().to_raw()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let bad = [
+            "set_profile_color(5, \"#000000\")",
+            "set_profile_color(0, \"red\")",
+            "set_profile_color(0, 0, 256, 0)",
+            "set_profile_dpi(0, 1234)",
+            "set_effect(#{ mode: \"disco\" })",
+            "sleep(-1)",
+            "read_file(\"/etc/passwd\")",
+            "eval(\"1\")",
+            "import \"/tmp/module\" as m;",
+            "loop {}",
+            "let s = \"x\"; loop { s += s; sleep(0); }",
+            "let a = []; loop { a.push(1); sleep(0); }",
+        ];
+        for source in bad.iter() {
+            let res =
+                Script::new(source, Box::new(ManualClock::default())).and_then(|mut s| s.run());
+            assert!(res.is_err(), "{}", source);
+        }
+
+        let mut bytes = *ConfigData::default().as_bytes();
+        bytes[93] = 0x99;
+        let device = MockDevice::new(ConfigData::from_bytes(bytes));
+        let mut script = Script::new("", Box::new(ManualClock::default())).unwrap();
+        assert!(script.attach(Box::new(device)).is_err());
+    }
+
+    #[test]
+    fn read_file() {
+        let dir = std::env::temp_dir().join(format!("gm4-script-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("value"), "42\n").unwrap();
+
+        let source = format!(
+            "let v = read_file({:?}); v.trim(); if v != \"42\" {{ throw \"wrong content\"; }}",
+            dir.join("value").display().to_string()
+        );
+        let (mut script, _, _) = script(&source);
+        assert!(script.run().is_err());
+
+        script.allow_read(&dir);
+        script.run().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn system_info() {
+        let (mut uptime, _, _) = script("read_file(\"/proc/uptime\");");
+        uptime.allow_system_info();
+        uptime.run().unwrap();
+
+        let (mut environ, _, _) = script("read_file(\"/proc/self/environ\");");
+        environ.allow_system_info();
+        assert!(environ.run().is_err());
+    }
+
+    #[test]
+    fn hotplug() {
+        let (mut script, device, _) = script(
+            r##"
+            fn on_disconnect() {
+                if connected() { throw "still connected"; }
+                set_profile_color(0, "#ff0000");
+            }
+            fn on_connect() {
+                set_profile_color(0, "#00ff00");
+                apply();
+            }
+            "##,
+        );
+        assert!(script.has_hotplug_hooks());
+        script.run().unwrap();
+
+        script.disconnected().unwrap();
+        assert!(device.sent().is_empty());
+
+        script.connected(Box::new(device.clone())).unwrap();
+        assert_eq!(1, device.sent().len());
+        assert_eq!(
+            [0, 0xff, 0],
+            Config::new(device.config_data()).profiles()[0].rgb
+        );
+    }
+}
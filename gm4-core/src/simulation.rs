@@ -0,0 +1,184 @@
+//! Simulation of the firmware LED effects, for previews.
+//!
+//! Timings come from the protocol docs, how the phases of combined
+//! effects are chained is an approximation of what the mouse does.
+
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use crate::animation::{hsv_to_rgb, Animation};
+use crate::protocol::led::{self, steady};
+
+/// State of the LED at a given moment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    /// Color before dimming
+    pub color: [u8; 3],
+    /// Brightness in `0.0..=1.0`
+    pub level: f64,
+}
+
+impl Frame {
+    /// Color that actually shows up on the LED
+    pub fn rgb(&self) -> [u8; 3] {
+        let mut out = self.color;
+        for c in out.iter_mut() {
+            *c = (*c as f64 * self.level).round() as u8;
+        }
+        out
+    }
+}
+
+fn brightness(b: steady::Brightnes) -> f64 {
+    b.percent() as f64 / 100.0
+}
+
+/// How long one cycle of a sub effect lasts, in seconds
+fn effect_period(effect: &led::Effect) -> f64 {
+    match effect {
+        led::Effect::Respiration(s) => s.seconds(),
+        led::Effect::Neon(s) => s.seconds(),
+        // Steady has no cycle of its own, hold it for a second
+        led::Effect::Steady(_) => 1.0,
+    }
+}
+
+fn effect_frame(effect: &led::Effect, base: [u8; 3], t: f64) -> Frame {
+    match effect {
+        led::Effect::Respiration(s) => breathe_frame(base, s.seconds(), t),
+        led::Effect::Steady(b) => Frame {
+            color: base,
+            level: brightness(*b),
+        },
+        led::Effect::Neon(s) => {
+            let period = s.seconds();
+            let hue = (t % period) / period * 360.0;
+            Frame {
+                color: hsv_to_rgb(hue, 1.0, 1.0),
+                level: 1.0,
+            }
+        }
+    }
+}
+
+fn breathe_frame(base: [u8; 3], period: f64, t: f64) -> Frame {
+    let phase = (t % period) / period;
+    Frame {
+        color: base,
+        level: (1.0 - (2.0 * PI * phase).cos()) / 2.0,
+    }
+}
+
+/// LED state `t` after `config` was applied, for profile color `base`
+pub fn frame_at(config: &led::Config, base: [u8; 3], t: Duration) -> Frame {
+    let t = t.as_secs_f64();
+
+    match config {
+        led::Config::Steady(b) => Frame {
+            color: base,
+            level: brightness(*b),
+        },
+        led::Config::Breathe(s) => breathe_frame(base, s.seconds(), t),
+        led::Config::BlinkEffect(f, times, effect) => {
+            // `times` blinks at `f`, then one cycle of the effect
+            let blink_period = 1.0 / f.hz() as f64;
+            let blink_len = blink_period * times.count() as f64;
+            let cycle = blink_len + effect_period(effect);
+
+            let t = t % cycle;
+            if t < blink_len {
+                let on = t % blink_period < blink_period / 2.0;
+                Frame {
+                    color: base,
+                    level: if on { 1.0 } else { 0.0 },
+                }
+            } else {
+                effect_frame(effect, base, t - blink_len)
+            }
+        }
+        led::Config::SteadyEffect(time, effect) => {
+            // Steady color for `time`, then one cycle of the effect
+            let steady_len = time.seconds();
+            let cycle = steady_len + effect_period(effect);
+
+            let t = t % cycle;
+            if t < steady_len {
+                Frame {
+                    color: base,
+                    level: 1.0,
+                }
+            } else {
+                effect_frame(effect, base, t - steady_len)
+            }
+        }
+    }
+}
+
+/// Firmware effect as an endless `Animation`
+#[derive(Debug, Clone, Copy)]
+pub struct Simulation {
+    pub config: led::Config,
+    pub base: [u8; 3],
+}
+
+impl Animation for Simulation {
+    fn color_at(&self, t: Duration) -> Option<[u8; 3]> {
+        Some(frame_at(&self.config, self.base, t).rgb())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::led::{blink, breathe, neon};
+
+    const RED: [u8; 3] = [255, 0, 0];
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn steady() {
+        let config = led::Config::Steady(steady::Brightnes::P55);
+        assert_eq!([140, 0, 0], frame_at(&config, RED, secs(3.0)).rgb());
+    }
+
+    #[test]
+    fn breathe() {
+        let config = led::Config::Breathe(breathe::Speed::S4);
+
+        assert_eq!(0.0, frame_at(&config, RED, secs(0.0)).level);
+        assert!((frame_at(&config, RED, secs(2.0)).level - 1.0).abs() < 1e-9);
+        assert!(frame_at(&config, RED, secs(4.0)).level < 1e-9);
+    }
+
+    #[test]
+    fn blink_effect() {
+        let config = led::Config::BlinkEffect(
+            blink::Frequency::Hz2,
+            blink::Times::T2,
+            led::Effect::Steady(steady::Brightnes::P25),
+        );
+
+        // Two blinks of 0.5s
+        assert_eq!(1.0, frame_at(&config, RED, secs(0.1)).level);
+        assert_eq!(0.0, frame_at(&config, RED, secs(0.3)).level);
+        assert_eq!(1.0, frame_at(&config, RED, secs(0.6)).level);
+        assert_eq!(0.0, frame_at(&config, RED, secs(0.8)).level);
+        // Then the effect, for a second
+        assert_eq!(0.25, frame_at(&config, RED, secs(1.5)).level);
+        // And again
+        assert_eq!(1.0, frame_at(&config, RED, secs(2.1)).level);
+    }
+
+    #[test]
+    fn steady_neon() {
+        let config =
+            led::Config::SteadyEffect(steady::EffectTime::S1, led::Effect::Neon(neon::Speed::S3));
+
+        assert_eq!(RED, frame_at(&config, RED, secs(0.5)).rgb());
+        assert_eq!([0, 255, 0], frame_at(&config, RED, secs(2.0)).rgb());
+        assert_eq!([0, 0, 255], frame_at(&config, RED, secs(3.0)).rgb());
+    }
+}
@@ -1,72 +1,205 @@
 use rusb::{Device, DeviceDescriptor, DeviceHandle, GlobalContext};
 use std::time::Duration;
 
+use crate::device::log::{GET_REPORT, SET_REPORT};
+use crate::device::DeviceError;
+use crate::lock::{DeviceLock, LockPolicy};
+
 pub struct MouseDevice {
     device: Device<GlobalContext>,
     device_desc: DeviceDescriptor,
     handle: DeviceHandle<GlobalContext>,
+    lock_policy: LockPolicy,
+    serial: Option<String>,
 }
 
-impl MouseDevice {
-    pub fn new() -> Result<Self, rusb::Error> {
-        let vid = 0x258a;
-        let pid = 0x1007;
+const VID: u16 = 0x258a;
+const PID: u16 = 0x1007;
 
-        let devices = rusb::DeviceList::new()?;
+fn find_device() -> Result<Option<Device<GlobalContext>>, rusb::Error> {
+    let devices = rusb::DeviceList::new()?;
 
-        let device = devices.iter().find(|d| match d.device_descriptor() {
-            Ok(d) => d.vendor_id() == vid && d.product_id() == pid,
-            Err(_) => false,
-        });
+    Ok(devices.iter().find(|d| match d.device_descriptor() {
+        Ok(d) => d.vendor_id() == VID && d.product_id() == PID,
+        Err(_) => false,
+    }))
+}
 
-        let device = if let Some(device) = device {
-            device
-        } else {
-            return Err(rusb::Error::NotFound);
-        };
+/// Whether the mouse is plugged in, without opening it
+pub fn is_plugged_in() -> bool {
+    matches!(find_device(), Ok(Some(_)))
+}
+
+impl MouseDevice {
+    pub fn new() -> Result<Self, rusb::Error> {
+        let device = find_device()?.ok_or(rusb::Error::NotFound)?;
 
         let handle = device.open()?;
 
+        let device_desc = device.device_descriptor()?;
+        let serial = device_desc
+            .serial_number_string_index()
+            .and_then(|_| handle.read_serial_number_string_ascii(&device_desc).ok());
+
         Ok(Self {
-            device_desc: device.device_descriptor()?,
+            device_desc,
             device,
             handle,
+            lock_policy: LockPolicy::from_env(),
+            serial,
         })
     }
-    pub fn kernel_detach(&mut self) -> Result<(), rusb::Error> {
-        let nc = self.device_desc.num_configurations();
 
-        for n in 0..nc {
+    /// What to do when another process is using the mouse, `GM4_LOCK` by default
+    pub fn set_lock_policy(&mut self, policy: LockPolicy) {
+        self.lock_policy = policy;
+    }
+
+    /// Takes the device lock and detaches the kernel driver from every interface that
+    /// has one bound.
+    ///
+    /// The drivers come back when the guard is dropped, also while unwinding from a panic.
+    pub fn detach_kernel_drivers(&mut self) -> Result<KernelDriverGuard<'_>, DeviceError> {
+        let lock = DeviceLock::acquire(
+            self.device.bus_number(),
+            self.device.address(),
+            self.lock_policy,
+        )?;
+
+        let mut interfaces = Vec::new();
+        for n in 0..self.device_desc.num_configurations() {
             let cd = self.device.config_descriptor(n)?;
             for i in cd.interfaces() {
-                if self.handle.kernel_driver_active(i.number()).is_ok() {
-                    self.handle.detach_kernel_driver(i.number()).ok();
+                if !interfaces.contains(&i.number()) {
+                    interfaces.push(i.number());
                 }
             }
         }
 
+        let mut guard = KernelDriverGuard {
+            device: self,
+            detached: Vec::new(),
+            _lock: lock,
+        };
+        for number in interfaces {
+            let active = guard.device.handle.kernel_driver_active(number);
+            // The transfer may still go through when a driver can't be detached
+            if active == Ok(true) && guard.device.handle.detach_kernel_driver(number).is_ok() {
+                guard.detached.push(number);
+            }
+        }
+
+        Ok(guard)
+    }
+
+    /// Detaches the kernel drivers until `kernel_attach`
+    #[deprecated(note = "use `detach_kernel_drivers`, which re-attaches them on drop")]
+    pub fn kernel_detach(&mut self) -> Result<(), rusb::Error> {
+        let guard = self.detach_kernel_drivers().map_err(|err| match err {
+            DeviceError::Usb(err) => err,
+            _ => rusb::Error::Busy,
+        })?;
+        guard.keep_detached();
         Ok(())
     }
+
+    /// Re-attaches the kernel driver of interface 0
+    #[deprecated(note = "use `detach_kernel_drivers`, which re-attaches them on drop")]
     pub fn kernel_attach(&mut self) -> Result<(), rusb::Error> {
-        self.handle.attach_kernel_driver(0)?;
-        Ok(())
+        self.handle.attach_kernel_driver(0)
     }
-    pub fn read(&mut self) -> Result<crate::protocol::ConfigData, rusb::Error> {
+
+    /// Reads the config report with the kernel drivers detached
+    pub fn read(&mut self) -> Result<crate::protocol::ConfigData, DeviceError> {
+        Ok(self.detach_kernel_drivers()?.read()?)
+    }
+
+    /// Sends the config report with the kernel drivers detached
+    pub fn send(&mut self, config_data: &crate::protocol::ConfigData) -> Result<(), DeviceError> {
+        Ok(self.detach_kernel_drivers()?.send(config_data)?)
+    }
+
+    fn read_report(&mut self) -> Result<crate::protocol::ConfigData, rusb::Error> {
         let mut out: [u8; 154] = [0; 154];
 
-        self.handle
-            .read_control(0xa1, 0x01, 0x304, 1, &mut out, Duration::from_secs(1))?;
+        let setup = GET_REPORT;
+        self.handle.read_control(
+            setup.0,
+            setup.1,
+            setup.2,
+            setup.3,
+            &mut out,
+            Duration::from_secs(1),
+        )?;
+
+        Ok(crate::protocol::ConfigData::from_bytes(out))
+    }
+
+    fn send_report(
+        &mut self,
+        config_data: &crate::protocol::ConfigData,
+    ) -> Result<(), rusb::Error> {
+        let setup = SET_REPORT;
+        self.handle.write_control(
+            setup.0,
+            setup.1,
+            setup.2,
+            setup.3,
+            config_data.as_bytes(),
+            Duration::from_secs(1),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Kernel drivers detached by `MouseDevice::detach_kernel_drivers`, re-attached on drop
+pub struct KernelDriverGuard<'a> {
+    device: &'a mut MouseDevice,
+    detached: Vec<u8>,
+    // Dropped after `Drop::drop` re-attached the drivers
+    _lock: DeviceLock,
+}
+
+impl KernelDriverGuard<'_> {
+    /// Interfaces that had a driver detached
+    pub fn detached(&self) -> &[u8] {
+        &self.detached
+    }
 
-        let data: crate::protocol::ConfigData = unsafe { std::mem::transmute(out) };
+    pub fn read(&mut self) -> Result<crate::protocol::ConfigData, rusb::Error> {
+        self.device.read_report()
+    }
 
-        Ok(data)
+    /// Releases the lock but leaves the drivers detached, for `kernel_detach`
+    fn keep_detached(mut self) {
+        self.detached.clear();
     }
+
     pub fn send(&mut self, config_data: &crate::protocol::ConfigData) -> Result<(), rusb::Error> {
-        let data: &[u8; 154] = unsafe { std::mem::transmute(config_data) };
+        self.device.send_report(config_data)
+    }
+}
 
-        self.handle
-            .write_control(0x21, 0x09, 0x0304, 1, data, Duration::from_secs(1))?;
+impl Drop for KernelDriverGuard<'_> {
+    fn drop(&mut self) {
+        for &number in &self.detached {
+            // Nothing to do about a failure here, keep restoring the others
+            self.device.handle.attach_kernel_driver(number).ok();
+        }
+    }
+}
 
-        Ok(())
+impl crate::device::Device for MouseDevice {
+    fn read(&mut self) -> Result<crate::protocol::ConfigData, DeviceError> {
+        MouseDevice::read(self)
+    }
+
+    fn send(&mut self, config_data: &crate::protocol::ConfigData) -> Result<(), DeviceError> {
+        MouseDevice::send(self, config_data)
+    }
+
+    fn serial(&self) -> Option<String> {
+        self.serial.clone()
     }
 }
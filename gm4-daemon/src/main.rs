@@ -0,0 +1,188 @@
+use std::net::SocketAddr;
+#[cfg(feature = "script")]
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+#[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+use gm4_core::history::Journal;
+
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod openrgb;
+#[cfg(feature = "ratbag")]
+mod ratbag;
+mod state;
+#[cfg(feature = "web")]
+mod web;
+
+use state::State;
+
+#[derive(StructOpt)]
+#[structopt(name = "gm4-daemon", about = "Modecom MC-GM4 daemon")]
+struct Opt {
+    /// Address of the OpenRGB SDK server
+    #[structopt(long, default_value = "127.0.0.1:6742")]
+    openrgb: SocketAddr,
+
+    /// Provide the ratbagd D-Bus API, so Piper can configure the mouse
+    #[cfg(feature = "ratbag")]
+    #[structopt(long)]
+    ratbag: bool,
+
+    /// Use the session bus instead of the system bus for the ratbagd API
+    #[cfg(feature = "ratbag")]
+    #[structopt(long)]
+    ratbag_session: bool,
+
+    /// MQTT broker to connect to, as `host[:port]`
+    #[cfg(feature = "mqtt")]
+    #[structopt(long)]
+    mqtt: Option<String>,
+
+    /// Prefix of the state and command topics
+    #[cfg(feature = "mqtt")]
+    #[structopt(long, default_value = "gm4")]
+    mqtt_prefix: String,
+
+    /// Prefix of the Home Assistant discovery topics
+    #[cfg(feature = "mqtt")]
+    #[structopt(long, default_value = "homeassistant")]
+    mqtt_discovery_prefix: String,
+
+    /// Serve the web configuration page, on `127.0.0.1:8080` unless a port is given.
+    /// Other interfaces need a full address, e.g. `0.0.0.0:8080`
+    #[cfg(feature = "web")]
+    #[structopt(long)]
+    web: Option<Option<web::BindAddr>>,
+
+    /// Rhai script to run next to the services, see `gm4_core::script`
+    #[cfg(feature = "script")]
+    #[structopt(long)]
+    script: Option<PathBuf>,
+}
+
+/// How often a script with hotplug hooks checks whether the mouse was plugged in or out
+#[cfg(feature = "script")]
+const HOTPLUG_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Runs the top level, then calls `on_connect` and `on_disconnect` as the mouse comes
+/// and goes. A replugged mouse is used by every service.
+#[cfg(feature = "script")]
+fn run_script(
+    mut script: gm4_core::script::Script,
+    state: state::SharedState,
+) -> Result<(), gm4_core::script::ScriptError> {
+    script.run()?;
+    if !script.has_hotplug_hooks() {
+        return Ok(());
+    }
+
+    let mut connected = true;
+    loop {
+        std::thread::sleep(HOTPLUG_POLL);
+
+        match (connected, gm4_core::backend::is_plugged_in()) {
+            (true, false) => {
+                connected = false;
+                script.disconnected()?;
+            }
+            (false, true) => {
+                // It may take a moment until the device can be opened
+                let reconnected = gm4_core::backend::open_from_env()
+                    .and_then(|device| state.lock().unwrap().reconnect(device));
+                if reconnected.is_ok() {
+                    connected = true;
+                    script.connected(Box::new(state::StateDevice::new(state.clone())))?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn run(opt: Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let device = gm4_core::backend::open_from_env()
+        .map_err(|err| format!("Could not connect to the mouse: {}", err))?;
+    let state = State::open(device)?;
+    // A dry run doesn't change the mouse, so there's nothing to record
+    #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+    let state = match Journal::open_default() {
+        _ if gm4_core::backend::dry_run() => state,
+        Ok(journal) => state.with_journal(journal),
+        Err(err) => {
+            eprintln!("History is off: {}", err);
+            state
+        }
+    };
+    let state = state.shared();
+
+    #[cfg(feature = "ratbag")]
+    {
+        if opt.ratbag {
+            let state = state.clone();
+            let session = opt.ratbag_session;
+            std::thread::spawn(move || {
+                if let Err(err) = ratbag::run(state, session) {
+                    eprintln!("ratbag D-Bus service failed: {}", err);
+                }
+            });
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    {
+        if let Some(broker) = &opt.mqtt {
+            let (host, port) = match broker.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse()?),
+                None => (broker.as_str(), 1883),
+            };
+            let options = rumqttc::MqttOptions::new("gm4-daemon", host, port);
+            let bridge =
+                mqtt::Bridge::new(state.clone(), &opt.mqtt_prefix, &opt.mqtt_discovery_prefix);
+            std::thread::spawn(move || bridge.run(options));
+        }
+    }
+
+    #[cfg(feature = "web")]
+    {
+        if let Some(addr) = opt.web {
+            let web::BindAddr(addr) = addr.unwrap_or_default();
+            let server = web::Server::bind(addr, state.clone())?;
+            println!("Web configuration on http://{}", server.local_addr()?);
+            std::thread::spawn(move || server.run());
+        }
+    }
+
+    #[cfg(feature = "script")]
+    {
+        if let Some(path) = &opt.script {
+            let source = std::fs::read_to_string(path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            let clock = gm4_core::animation::SystemClock::default();
+            let mut script = gm4_core::script::Script::new(&source, Box::new(clock))?;
+            script.allow_system_info();
+            script.attach(Box::new(state::StateDevice::new(state.clone())))?;
+
+            let state = state.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = run_script(script, state) {
+                    eprintln!("{}", err);
+                }
+            });
+        }
+    }
+
+    let server = openrgb::Server::bind(opt.openrgb, state)?;
+    println!("OpenRGB SDK server listening on {}", server.local_addr()?);
+    server.run()?;
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run(Opt::from_args()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
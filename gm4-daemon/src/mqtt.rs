@@ -0,0 +1,469 @@
+//! MQTT bridge, for home automation.
+//!
+//! The config is published as retained JSON on `<prefix>/state`, commands
+//! are taken from `<prefix>/profile/<n>/{light,color,dpi,active}/set` and
+//! `<prefix>/effect/set`. Home Assistant discovery payloads describe a light,
+//! a DPI select and a switch for the DPI stage per profile, plus an effect select.
+//!
+//! The LED has no off state, a light that is turned off gets the color black.
+
+use std::fmt;
+use std::time::Duration;
+
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+
+use gm4_core::config::DPI_LIST;
+use gm4_core::device::DeviceError;
+use gm4_core::protocol::error::ProtocolError;
+use gm4_core::protocol::led;
+use gm4_core::schema::ProfileUpdate;
+
+use crate::state::SharedState;
+
+const EFFECT_MODES: [&str; 4] = ["steady", "breathe", "blink", "steady_effect"];
+/// Color of a light that is turned on while it is black
+const ON_COLOR: [u8; 3] = [0xff, 0xff, 0xff];
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownTopic(String),
+    InvalidPayload(String),
+    Protocol(ProtocolError),
+    Device(DeviceError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownTopic(topic) => write!(f, "Unknown topic: {}", topic),
+            Error::InvalidPayload(msg) => write!(f, "Invalid payload: {}", msg),
+            Error::Protocol(err) => err.fmt(f),
+            Error::Device(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ProtocolError> for Error {
+    fn from(err: ProtocolError) -> Self {
+        Error::Protocol(err)
+    }
+}
+
+impl From<DeviceError> for Error {
+    fn from(err: DeviceError) -> Self {
+        Error::Device(err)
+    }
+}
+
+fn invalid(msg: impl fmt::Display) -> Error {
+    Error::InvalidPayload(msg.to_string())
+}
+
+fn parse_switch(payload: &str) -> Result<bool, Error> {
+    match payload.trim().to_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(invalid(payload)),
+    }
+}
+
+pub struct Bridge {
+    state: SharedState,
+    prefix: String,
+    discovery_prefix: String,
+}
+
+impl Bridge {
+    pub fn new(state: SharedState, prefix: &str, discovery_prefix: &str) -> Self {
+        Self {
+            state,
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            discovery_prefix: discovery_prefix.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    pub fn state_topic(&self) -> String {
+        self.topic("state")
+    }
+
+    pub fn availability_topic(&self) -> String {
+        self.topic("availability")
+    }
+
+    pub fn subscriptions(&self) -> Vec<String> {
+        vec![self.topic("effect/set"), self.topic("profile/+/+/set")]
+    }
+
+    pub fn state_payload(&self) -> String {
+        json!(self.state.lock().unwrap().config()).to_string()
+    }
+
+    /// Home Assistant discovery topics and their retained config payloads
+    pub fn discovery(&self) -> Vec<(String, String)> {
+        let node = self.prefix.replace('/', "_");
+        let device = json!({
+            "identifiers": [node],
+            "name": "Modecom MC-GM4",
+            "manufacturer": "Modecom",
+            "model": "MC-GM4",
+        });
+        let common = |name: String, unique_id: String| {
+            json!({
+                "name": name,
+                "unique_id": unique_id,
+                "device": device,
+                "availability_topic": self.availability_topic(),
+                "state_topic": self.state_topic(),
+            })
+        };
+        let dpi_options: Vec<String> = {
+            let mut list = DPI_LIST.to_vec();
+            list.sort_unstable();
+            list.iter().map(u16::to_string).collect()
+        };
+
+        let mut out = Vec::new();
+
+        for id in 0..5 {
+            let mut light = common(
+                format!("DPI {} color", id + 1),
+                format!("{}_profile{}_light", node, id),
+            );
+            let extra = json!({
+                "command_topic": self.topic(&format!("profile/{}/light/set", id)),
+                "state_value_template": format!(
+                    "{{{{ 'OFF' if value_json.profiles[{}].rgb == '#000000' else 'ON' }}}}",
+                    id
+                ),
+                "rgb_command_topic": self.topic(&format!("profile/{}/color/set", id)),
+                "rgb_state_topic": self.state_topic(),
+                "rgb_value_template": format!(
+                    "{{% set c = value_json.profiles[{}].rgb %}}\
+                     {{{{ c[1:3] | int(0, 16) }}}},{{{{ c[3:5] | int(0, 16) }}}},{{{{ c[5:7] | int(0, 16) }}}}",
+                    id
+                ),
+            });
+            merge(&mut light, extra);
+            out.push((
+                format!(
+                    "{}/light/{}/profile{}/config",
+                    self.discovery_prefix, node, id
+                ),
+                light.to_string(),
+            ));
+
+            let mut dpi = common(
+                format!("DPI {}", id + 1),
+                format!("{}_profile{}_dpi", node, id),
+            );
+            let extra = json!({
+                "command_topic": self.topic(&format!("profile/{}/dpi/set", id)),
+                "value_template": format!("{{{{ value_json.profiles[{}].dpi }}}}", id),
+                "options": dpi_options,
+            });
+            merge(&mut dpi, extra);
+            out.push((
+                format!(
+                    "{}/select/{}/profile{}_dpi/config",
+                    self.discovery_prefix, node, id
+                ),
+                dpi.to_string(),
+            ));
+
+            let mut stage = common(
+                format!("DPI {} enabled", id + 1),
+                format!("{}_profile{}_active", node, id),
+            );
+            let extra = json!({
+                "command_topic": self.topic(&format!("profile/{}/active/set", id)),
+                "value_template":
+                    format!("{{{{ 'ON' if value_json.profiles[{}].active else 'OFF' }}}}", id),
+            });
+            merge(&mut stage, extra);
+            out.push((
+                format!(
+                    "{}/switch/{}/profile{}_active/config",
+                    self.discovery_prefix, node, id
+                ),
+                stage.to_string(),
+            ));
+        }
+
+        let mut effect = common("LED effect".into(), format!("{}_effect", node));
+        let extra = json!({
+            "command_topic": self.topic("effect/set"),
+            "value_template": "{{ value_json.effect.mode }}",
+            "options": EFFECT_MODES,
+        });
+        merge(&mut effect, extra);
+        out.push((
+            format!("{}/select/{}/effect/config", self.discovery_prefix, node),
+            effect.to_string(),
+        ));
+
+        out
+    }
+
+    /// Applies the command on `topic` and sends it to the mouse
+    pub fn handle(&self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let payload = std::str::from_utf8(payload).map_err(invalid)?;
+        let unknown = || Error::UnknownTopic(topic.to_owned());
+
+        let name = topic
+            .strip_prefix(&self.prefix)
+            .and_then(|t| t.strip_prefix('/'))
+            .and_then(|t| t.strip_suffix("/set"))
+            .ok_or_else(unknown)?;
+        let parts: Vec<&str> = name.split('/').collect();
+
+        let mut state = self.state.lock().unwrap();
+
+        match parts.as_slice() {
+            ["effect"] => {
+                // The select sends a bare mode name, which takes default values
+                let value = match serde_json::from_str(payload) {
+                    Ok(Value::String(mode)) => json!({ "mode": mode }),
+                    Ok(value) => value,
+                    Err(_) => json!({ "mode": payload.trim() }),
+                };
+                let effect: led::Config = serde_json::from_value(value).map_err(invalid)?;
+                state.config_mut().set_led_effect(effect);
+            }
+            ["profile", id, field] => {
+                let id: usize = id.parse().map_err(|_| unknown())?;
+                if id >= 5 {
+                    return Err(unknown());
+                }
+                let value = match *field {
+                    "light" => {
+                        let rgb = state.config().profiles()[id].rgb;
+                        match parse_switch(payload)? {
+                            false => json!({ "rgb": [0, 0, 0] }),
+                            true if rgb == [0, 0, 0] => json!({ "rgb": ON_COLOR }),
+                            true => json!({}),
+                        }
+                    }
+                    "color" => json!({ "rgb": payload.trim() }),
+                    "active" => json!({ "active": parse_switch(payload)? }),
+                    "dpi" => json!({ "dpi": payload.trim().parse::<u64>().map_err(invalid)? }),
+                    _ => return Err(unknown()),
+                };
+                let update: ProfileUpdate = serde_json::from_value(value).map_err(invalid)?;
+                update.apply(state.config_mut(), id)?;
+            }
+            _ => return Err(unknown()),
+        }
+
+        // Every command is something the user asked for, so it goes to the history
+        state.commit("gm4-daemon mqtt")?;
+        Ok(())
+    }
+
+    /// Connects to the broker and serves until the process exits,
+    /// dropped connections are retried
+    pub fn run(self, mut options: MqttOptions) {
+        options.set_last_will(LastWill::new(
+            self.availability_topic(),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+
+        let (mut client, mut connection) = Client::new(options, 10);
+        let mut published = None;
+
+        loop {
+            match connection.recv_timeout(Duration::from_secs(1)) {
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                    if let Err(err) = self.announce(&mut client) {
+                        eprintln!("MQTT: {}", err);
+                    }
+                    published = None;
+                }
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    if let Err(err) = self.handle(&publish.topic, &publish.payload) {
+                        eprintln!("MQTT: {}: {}", publish.topic, err);
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    eprintln!("MQTT: {}", err);
+                    std::thread::sleep(Duration::from_secs(5));
+                    continue;
+                }
+                // Nothing happened, just check whether the state changed
+                Err(rumqttc::RecvTimeoutError::Timeout) => {}
+                Err(rumqttc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            // Also catches changes made through the other services
+            let payload = self.state_payload();
+            if published.as_ref() != Some(&payload) {
+                let res = client.try_publish(
+                    self.state_topic(),
+                    QoS::AtLeastOnce,
+                    true,
+                    payload.as_bytes(),
+                );
+                if res.is_ok() {
+                    published = Some(payload);
+                }
+            }
+        }
+    }
+
+    fn announce(&self, client: &mut Client) -> Result<(), rumqttc::ClientError> {
+        for topic in self.subscriptions() {
+            client.try_subscribe(topic, QoS::AtLeastOnce)?;
+        }
+        for (topic, payload) in self.discovery() {
+            client.try_publish(topic, QoS::AtLeastOnce, true, payload)?;
+        }
+        client.try_publish(self.availability_topic(), QoS::AtLeastOnce, true, "online")
+    }
+}
+
+fn merge(dst: &mut Value, src: Value) {
+    if let (Value::Object(dst), Value::Object(src)) = (dst, src) {
+        dst.extend(src);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use gm4_core::config::Config;
+    use gm4_core::device::MockDevice;
+    use gm4_core::protocol::ConfigData;
+
+    fn bridge() -> (MockDevice, Bridge) {
+        let device = MockDevice::new(ConfigData::default());
+        let state = State::open(Box::new(device.clone())).unwrap().shared();
+        (device, Bridge::new(state, "gm4", "homeassistant"))
+    }
+
+    #[test]
+    fn commands() {
+        let (device, bridge) = bridge();
+
+        bridge.handle("gm4/profile/2/color/set", b"1,2,3").unwrap();
+        bridge
+            .handle("gm4/profile/3/color/set", b"#0a0b0c")
+            .unwrap();
+        bridge.handle("gm4/profile/1/dpi/set", b"3200").unwrap();
+        bridge.handle("gm4/profile/4/active/set", b"OFF").unwrap();
+        bridge.handle("gm4/effect/set", b"blink").unwrap();
+        assert_eq!(5, device.sent().len());
+
+        let config = Config::new(device.config_data());
+        assert_eq!([1, 2, 3], config.profiles()[2].rgb);
+        assert_eq!([10, 11, 12], config.profiles()[3].rgb);
+        assert_eq!(Some(3200), config.profiles()[1].dpi_value());
+        assert!(!config.profiles()[4].active);
+
+        let state: Value = serde_json::from_str(&bridge.state_payload()).unwrap();
+        assert_eq!(json!("#010203"), state["profiles"][2]["rgb"]);
+        assert_eq!(json!(3200), state["profiles"][1]["dpi"]);
+        assert_eq!(json!("blink"), state["effect"]["mode"]);
+
+        assert!(bridge.handle("gm4/profile/1/dpi/set", b"1234").is_err());
+        assert!(bridge.handle("gm4/profile/7/color/set", b"1,2,3").is_err());
+        assert!(bridge.handle("other/effect/set", b"breathe").is_err());
+        assert_eq!(5, device.sent().len());
+
+        // Turning the light off and on changes the color, not the DPI stage
+        bridge.handle("gm4/profile/2/light/set", b"OFF").unwrap();
+        let config = Config::new(device.config_data());
+        assert_eq!([0, 0, 0], config.profiles()[2].rgb);
+        assert!(config.profiles()[2].active);
+        bridge.handle("gm4/profile/2/light/set", b"ON").unwrap();
+        assert_eq!(
+            ON_COLOR,
+            Config::new(device.config_data()).profiles()[2].rgb
+        );
+        bridge.handle("gm4/profile/3/light/set", b"ON").unwrap();
+        assert_eq!(
+            [10, 11, 12],
+            Config::new(device.config_data()).profiles()[3].rgb
+        );
+    }
+
+    #[test]
+    fn discovery() {
+        let (_device, bridge) = bridge();
+        let discovery = bridge.discovery();
+        assert_eq!(16, discovery.len());
+
+        let (topic, payload) = &discovery[0];
+        assert_eq!("homeassistant/light/gm4/profile0/config", topic);
+
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(json!("gm4/profile/0/light/set"), payload["command_topic"]);
+        assert_eq!(
+            json!("gm4/profile/0/color/set"),
+            payload["rgb_command_topic"]
+        );
+        assert_eq!(json!("gm4/state"), payload["state_topic"]);
+
+        let (topic, payload) = &discovery[2];
+        assert_eq!("homeassistant/switch/gm4/profile0_active/config", topic);
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(json!("gm4/profile/0/active/set"), payload["command_topic"]);
+    }
+
+    /// Needs a broker, e.g. `mosquitto -p 1883`, address taken from `GM4_MQTT_BROKER`
+    #[test]
+    #[ignore]
+    fn broker() {
+        let addr = std::env::var("GM4_MQTT_BROKER").unwrap_or_else(|_| "127.0.0.1:1883".into());
+        let (host, port) = addr.rsplit_once(':').unwrap();
+        let port = port.parse().unwrap();
+
+        let (device, bridge) = bridge();
+        let prefix = format!("gm4-test-{}", std::process::id());
+        let bridge = Bridge::new(bridge.state.clone(), &prefix, "homeassistant-test");
+        let options = MqttOptions::new("gm4-test-bridge", host, port);
+        std::thread::spawn(move || bridge.run(options));
+
+        let (mut client, mut connection) =
+            Client::new(MqttOptions::new("gm4-test-client", host, port), 10);
+        client
+            .subscribe(format!("{}/state", prefix), QoS::AtLeastOnce)
+            .unwrap();
+
+        let mut commanded = false;
+        for event in connection.iter() {
+            if let Ok(Event::Incoming(Packet::Publish(publish))) = event {
+                let state: Value = serde_json::from_slice(&publish.payload).unwrap();
+
+                if state["profiles"][0]["rgb"] == json!("#090807") {
+                    break;
+                }
+                if !commanded {
+                    client
+                        .publish(
+                            format!("{}/profile/0/color/set", prefix),
+                            QoS::AtLeastOnce,
+                            false,
+                            "9,8,7",
+                        )
+                        .unwrap();
+                    commanded = true;
+                }
+            }
+        }
+
+        assert_eq!(
+            [9, 8, 7],
+            Config::new(device.config_data()).profiles()[0].rgb
+        );
+    }
+}
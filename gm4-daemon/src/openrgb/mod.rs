@@ -0,0 +1,522 @@
+//! OpenRGB SDK server, so OpenRGB and its clients can drive the mouse LED.
+//!
+//! The mouse shows up as one controller with a zone of five LEDs,
+//! one per DPI profile. Firmware effects are exposed as modes.
+
+use std::convert::TryFrom;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use gm4_core::config::Config;
+use gm4_core::protocol::error::ProtocolError;
+use gm4_core::protocol::led::{self, blink, breathe, neon, steady};
+
+use crate::state::SharedState;
+
+mod packet;
+
+use packet::{ControllerData, Header, Mode, Reader};
+
+/// Highest protocol version we speak
+const PROTOCOL_VERSION: u32 = 1;
+
+const LED_NAMES: [&str; 5] = ["DPI 1", "DPI 2", "DPI 3", "DPI 4", "DPI 5"];
+
+/// Mode names, the index is the OpenRGB mode value
+const MODE_NAMES: [&str; 7] = [
+    "Static",
+    "Breathing",
+    "Blink + Breathing",
+    "Blink + Static",
+    "Blink + Neon",
+    "Static + Breathing",
+    "Static + Neon",
+];
+
+fn invalid_data(err: ProtocolError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// OpenRGB mode value and speed matching `config`
+fn config_to_mode(config: &led::Config) -> (i32, u32) {
+    use led::{Config, Effect};
+
+    match config {
+        Config::Steady(_) => (0, 0),
+        Config::Breathe(s) => (1, *s as u32),
+        Config::BlinkEffect(f, _, Effect::Respiration(_)) => (2, f.hz() as u32),
+        Config::BlinkEffect(f, _, Effect::Steady(_)) => (3, f.hz() as u32),
+        Config::BlinkEffect(f, _, Effect::Neon(_)) => (4, f.hz() as u32),
+        Config::SteadyEffect(_, Effect::Respiration(s)) => (5, *s as u32),
+        Config::SteadyEffect(_, Effect::Neon(s)) => (6, *s as u32),
+        // Steady followed by steady looks just like steady
+        Config::SteadyEffect(_, Effect::Steady(_)) => (0, 0),
+    }
+}
+
+/// Settings OpenRGB can't express are kept from `current`, if it has them
+fn mode_to_config(
+    value: i32,
+    speed: u32,
+    current: &led::Config,
+) -> Result<led::Config, ProtocolError> {
+    let speed =
+        u8::try_from(speed).map_err(|_| ProtocolError::InvalidValue(format!("speed {}", speed)))?;
+
+    let (times, sub_effect) = match current {
+        led::Config::BlinkEffect(_, t, e) => (*t, Some(*e)),
+        led::Config::SteadyEffect(_, e) => (Default::default(), Some(*e)),
+        _ => (Default::default(), None),
+    };
+    let time = match current {
+        led::Config::SteadyEffect(t, _) => *t,
+        _ => Default::default(),
+    };
+    let brightnes = match (current, sub_effect) {
+        (led::Config::Steady(b), _) => *b,
+        (_, Some(led::Effect::Steady(b))) => b,
+        _ => steady::Brightnes::P100,
+    };
+
+    Ok(match value {
+        0 => led::Config::Steady(brightnes),
+        1 => led::Config::Breathe(breathe::Speed::from_id(speed)?),
+        2..=4 => {
+            let frequency = blink::Frequency::from_hz(speed)?;
+            let effect = match (value, sub_effect) {
+                (2, Some(e @ led::Effect::Respiration(_))) => e,
+                (2, _) => led::Effect::Respiration(Default::default()),
+                (3, _) => led::Effect::Steady(brightnes),
+                (_, Some(e @ led::Effect::Neon(_))) => e,
+                _ => led::Effect::Neon(Default::default()),
+            };
+            led::Config::BlinkEffect(frequency, times, effect)
+        }
+        5 => led::Config::SteadyEffect(
+            time,
+            led::Effect::Respiration(breathe::Speed::from_id(speed)?),
+        ),
+        6 => led::Config::SteadyEffect(time, led::Effect::Neon(neon::Speed::from_id(speed)?)),
+        _ => return Err(ProtocolError::InvalidValue(format!("mode {}", value))),
+    })
+}
+
+fn modes(config: &led::Config) -> Vec<Mode> {
+    let (active, active_speed) = config_to_mode(config);
+
+    MODE_NAMES
+        .iter()
+        .enumerate()
+        .map(|(id, name)| {
+            // Slowest first, as OpenRGB expects
+            let (speed_min, speed_max) = match id {
+                0 => (0, 0),
+                1 | 5 => (breathe::Speed::S8 as u32, breathe::Speed::S4 as u32),
+                2..=4 => (1, 10),
+                _ => (neon::Speed::S7_5 as u32, neon::Speed::S0_5 as u32),
+            };
+
+            let speed = if id as i32 == active {
+                active_speed
+            } else {
+                // Middle of the range
+                (speed_min + speed_max) / 2
+            };
+
+            let mut flags = packet::MODE_FLAG_HAS_PER_LED_COLOR;
+            if id != 0 {
+                flags |= packet::MODE_FLAG_HAS_SPEED;
+            }
+
+            Mode {
+                name: name.to_string(),
+                value: id as i32,
+                flags,
+                speed_min,
+                speed_max,
+                colors_min: 0,
+                colors_max: 0,
+                speed,
+                direction: 0,
+                color_mode: packet::MODE_COLORS_PER_LED,
+                colors: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+fn controller_data(config: &Config, protocol: u32) -> Vec<u8> {
+    let modes = modes(config.led_config());
+    let (active_mode, _) = config_to_mode(config.led_config());
+    let colors: Vec<[u8; 3]> = config.profiles().iter().map(|p| p.rgb).collect();
+
+    ControllerData {
+        name: "Modecom MC-GM4",
+        vendor: "Modecom",
+        description: "Modecom MC-GM4 gaming mouse",
+        version: env!("CARGO_PKG_VERSION"),
+        serial: "",
+        location: "USB 258a:1007",
+        modes: &modes,
+        active_mode,
+        zone_name: "DPI profiles",
+        leds: &LED_NAMES,
+        colors: &colors,
+    }
+    .to_bytes(protocol)
+}
+
+pub struct Server {
+    listener: TcpListener,
+    state: SharedState,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, state: SharedState) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            state,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts clients forever, each one on its own thread
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let mut connection = Connection {
+                stream: stream?,
+                state: self.state.clone(),
+                protocol: 0,
+            };
+
+            std::thread::spawn(move || {
+                if let Err(err) = connection.run() {
+                    eprintln!("OpenRGB client error: {}", err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    state: SharedState,
+    protocol: u32,
+}
+
+impl Connection {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let header = match Header::read(&mut self.stream) {
+                Ok(header) => header,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+
+            // A bad size drops the connection, there is no way to skip the packet
+            let data = header.read_data(&mut self.stream)?;
+            self.handle(header, &data)?;
+        }
+    }
+
+    fn reply(&mut self, header: Header, data: &[u8]) -> io::Result<()> {
+        packet::write_packet(&mut self.stream, header.device, header.id, data)
+    }
+
+    fn handle(&mut self, header: Header, data: &[u8]) -> io::Result<()> {
+        let mut r = Reader::new(data);
+
+        match header.id {
+            packet::REQUEST_CONTROLLER_COUNT => self.reply(header, &1u32.to_le_bytes()),
+            packet::REQUEST_PROTOCOL_VERSION => {
+                let client = r.u32().unwrap_or(0);
+                self.protocol = client.min(PROTOCOL_VERSION);
+                self.reply(header, &PROTOCOL_VERSION.to_le_bytes())
+            }
+            packet::SET_CLIENT_NAME => Ok(()),
+            // There is only controller 0, requests for others get an empty answer so
+            // clients don't wait for one
+            packet::REQUEST_CONTROLLER_DATA if header.device != 0 => self.reply(header, &[]),
+            _ if header.device != 0 => Ok(()),
+            packet::REQUEST_CONTROLLER_DATA => {
+                // Newer clients tell which version they want
+                let protocol = r
+                    .u32()
+                    .map(|v| v.min(PROTOCOL_VERSION))
+                    .unwrap_or(self.protocol);
+                let data = controller_data(self.state.lock().unwrap().config(), protocol);
+                self.reply(header, &data)
+            }
+            packet::RGBCONTROLLER_UPDATELEDS => {
+                let _size = r.u32()?;
+                let colors = read_colors(&mut r)?;
+                self.set_colors(0, &colors)
+            }
+            packet::RGBCONTROLLER_UPDATEZONELEDS => {
+                let _size = r.u32()?;
+                let _zone = r.u32()?;
+                let colors = read_colors(&mut r)?;
+                self.set_colors(0, &colors)
+            }
+            packet::RGBCONTROLLER_UPDATESINGLELED => {
+                let id = r.i32()?;
+                let color = r.color()?;
+                self.set_colors(id as usize, &[color])
+            }
+            packet::RGBCONTROLLER_SETCUSTOMMODE => self.set_mode(0, 0),
+            packet::RGBCONTROLLER_UPDATEMODE => {
+                let _size = r.u32()?;
+                let value = r.i32()?;
+                let mode = Mode::read(&mut r)?;
+                self.set_mode(value, mode.speed)
+            }
+            // Our only zone has a fixed size
+            packet::RGBCONTROLLER_RESIZEZONE => Ok(()),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_colors(&mut self, first: usize, colors: &[[u8; 3]]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        for (id, rgb) in (first..LED_NAMES.len()).zip(colors.iter()) {
+            state.config_mut().set_profile_rgb(id, *rgb);
+        }
+
+        if let Err(err) = state.apply() {
+            eprintln!("Could not update the mouse: {}", err);
+        }
+        Ok(())
+    }
+
+    fn set_mode(&mut self, value: i32, speed: u32) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        let config =
+            mode_to_config(value, speed, state.config().led_config()).map_err(invalid_data)?;
+        state.config_mut().set_led_effect(config);
+
+        if let Err(err) = state.apply() {
+            eprintln!("Could not update the mouse: {}", err);
+        }
+        Ok(())
+    }
+}
+
+fn read_colors(r: &mut Reader) -> io::Result<Vec<[u8; 3]>> {
+    let len = r.u16()?;
+    (0..len).map(|_| r.color()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use gm4_core::device::MockDevice;
+    use gm4_core::protocol::ConfigData;
+
+    use packet::Writer;
+
+    fn start() -> (MockDevice, TcpStream) {
+        let device = MockDevice::new(ConfigData::default());
+        let state = State::open(Box::new(device.clone())).unwrap().shared();
+
+        let server = Server::bind("127.0.0.1:0", state).unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+
+        (device, TcpStream::connect(addr).unwrap())
+    }
+
+    fn send(stream: &mut TcpStream, id: u32, data: &[u8]) {
+        packet::write_packet(stream, 0, id, data).unwrap();
+    }
+
+    fn request(stream: &mut TcpStream, id: u32, data: &[u8]) -> Vec<u8> {
+        send(stream, id, data);
+
+        let header = Header::read(stream).unwrap();
+        assert_eq!(id, header.id);
+        header.read_data(stream).unwrap()
+    }
+
+    /// Waits for the server to process everything sent so far
+    fn sync(stream: &mut TcpStream) {
+        request(stream, packet::REQUEST_CONTROLLER_COUNT, &[]);
+    }
+
+    #[test]
+    fn controller_list() {
+        let (_device, mut stream) = start();
+
+        let count = request(&mut stream, packet::REQUEST_CONTROLLER_COUNT, &[]);
+        assert_eq!(1u32.to_le_bytes().to_vec(), count);
+
+        let version = request(
+            &mut stream,
+            packet::REQUEST_PROTOCOL_VERSION,
+            &3u32.to_le_bytes(),
+        );
+        assert_eq!(PROTOCOL_VERSION.to_le_bytes().to_vec(), version);
+
+        let data = request(
+            &mut stream,
+            packet::REQUEST_CONTROLLER_DATA,
+            &1u32.to_le_bytes(),
+        );
+        let mut r = Reader::new(&data);
+        assert_eq!(data.len() as u32, r.u32().unwrap());
+        assert_eq!(packet::DEVICE_TYPE_MOUSE, r.i32().unwrap());
+        assert_eq!("Modecom MC-GM4", r.string().unwrap());
+        assert_eq!("Modecom", r.string().unwrap());
+
+        // Description, version, serial, location
+        for _ in 0..4 {
+            r.string().unwrap();
+        }
+
+        assert_eq!(MODE_NAMES.len() as u16, r.u16().unwrap());
+        // Default config is Breathe
+        assert_eq!(1, r.i32().unwrap());
+        for name in MODE_NAMES.iter() {
+            assert_eq!(*name, Mode::read(&mut r).unwrap().name);
+        }
+
+        assert_eq!(1, r.u16().unwrap());
+        assert_eq!("DPI profiles", r.string().unwrap());
+        assert_eq!(packet::ZONE_TYPE_LINEAR, r.i32().unwrap());
+        assert_eq!(
+            [5, 5, 5],
+            [r.u32().unwrap(), r.u32().unwrap(), r.u32().unwrap()]
+        );
+        assert_eq!(0, r.u16().unwrap());
+
+        assert_eq!(5, r.u16().unwrap());
+        for name in LED_NAMES.iter() {
+            assert_eq!(*name, r.string().unwrap());
+            r.u32().unwrap();
+        }
+
+        assert_eq!(5, r.u16().unwrap());
+        assert_eq!(ConfigData::default().profiles_rgb()[0], r.color().unwrap());
+    }
+
+    #[test]
+    fn update_leds() {
+        let (device, mut stream) = start();
+
+        let mut w = Writer::default();
+        w.u32(0);
+        w.u16(5);
+        for n in 0..5 {
+            w.color([n, 0, 255]);
+        }
+        send(
+            &mut stream,
+            packet::RGBCONTROLLER_UPDATELEDS,
+            &w.into_inner(),
+        );
+
+        let mut w = Writer::default();
+        w.i32(2);
+        w.color([1, 2, 3]);
+        send(
+            &mut stream,
+            packet::RGBCONTROLLER_UPDATESINGLELED,
+            &w.into_inner(),
+        );
+        sync(&mut stream);
+
+        assert_eq!(2, device.sent().len());
+        assert_eq!(
+            [
+                [0, 0, 255],
+                [1, 0, 255],
+                [1, 2, 3],
+                [3, 0, 255],
+                [4, 0, 255]
+            ],
+            device.config_data().profiles_rgb()
+        );
+    }
+
+    #[test]
+    fn update_mode() {
+        let (device, mut stream) = start();
+
+        let mut mode = modes(&Default::default()).remove(4);
+        mode.speed = 7;
+
+        let mut w = Writer::default();
+        w.u32(0);
+        w.i32(mode.value);
+        mode.write(&mut w);
+        send(
+            &mut stream,
+            packet::RGBCONTROLLER_UPDATEMODE,
+            &w.into_inner(),
+        );
+        sync(&mut stream);
+
+        let data = device.config_data();
+        let config =
+            led::Config::from_raw(data.led_mode, data.led_arg1, data.led_arg2, data.led_arg3)
+                .unwrap();
+
+        match config {
+            led::Config::BlinkEffect(f, _, led::Effect::Neon(_)) => assert_eq!(7, f.hz()),
+            other => panic!("Unexpected config {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_packets() {
+        let (_device, mut stream) = start();
+
+        // Another controller gets an answer too
+        packet::write_packet(&mut stream, 1, packet::REQUEST_CONTROLLER_DATA, &[]).unwrap();
+        let header = Header::read(&mut stream).unwrap();
+        assert_eq!((1, 0), (header.device, header.size));
+
+        // Speeds that don't fit a byte are not truncated
+        assert!(mode_to_config(4, 7 + 256, &Default::default()).is_err());
+
+        let mut w = Writer::default();
+        w.bytes(packet::MAGIC);
+        w.u32(0);
+        w.u32(packet::RGBCONTROLLER_UPDATELEDS);
+        w.u32(u32::MAX);
+        io::Write::write_all(&mut stream, &w.into_inner()).unwrap();
+        // The connection is dropped instead of allocating 4 GiB
+        let mut buf = [0; 1];
+        assert_eq!(0, io::Read::read(&mut stream, &mut buf).unwrap_or(0));
+    }
+
+    #[test]
+    fn mode_mapping() {
+        for config in [
+            led::Config::Steady(steady::Brightnes::P45),
+            led::Config::Breathe(breathe::Speed::S6),
+            led::Config::BlinkEffect(
+                blink::Frequency::Hz3,
+                blink::Times::T7,
+                led::Effect::Neon(neon::Speed::S2),
+            ),
+            led::Config::SteadyEffect(
+                steady::EffectTime::S3,
+                led::Effect::Respiration(breathe::Speed::S5),
+            ),
+        ]
+        .iter()
+        {
+            let (value, speed) = config_to_mode(config);
+            let back = mode_to_config(value, speed, config).unwrap();
+            assert_eq!(config.to_raw(), back.to_raw());
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Wire format of the OpenRGB SDK protocol, everything is little endian.
+
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 4] = b"ORGB";
+/// Largest packet accepted, the ones clients send are a few hundred bytes
+pub const MAX_PACKET_SIZE: u32 = 64 * 1024;
+
+pub const REQUEST_CONTROLLER_COUNT: u32 = 0;
+pub const REQUEST_CONTROLLER_DATA: u32 = 1;
+pub const REQUEST_PROTOCOL_VERSION: u32 = 40;
+pub const SET_CLIENT_NAME: u32 = 50;
+pub const RGBCONTROLLER_RESIZEZONE: u32 = 1000;
+pub const RGBCONTROLLER_UPDATELEDS: u32 = 1050;
+pub const RGBCONTROLLER_UPDATEZONELEDS: u32 = 1051;
+pub const RGBCONTROLLER_UPDATESINGLELED: u32 = 1052;
+pub const RGBCONTROLLER_SETCUSTOMMODE: u32 = 1100;
+pub const RGBCONTROLLER_UPDATEMODE: u32 = 1101;
+
+pub const DEVICE_TYPE_MOUSE: i32 = 6;
+pub const ZONE_TYPE_LINEAR: i32 = 1;
+
+pub const MODE_FLAG_HAS_SPEED: u32 = 1 << 0;
+pub const MODE_FLAG_HAS_PER_LED_COLOR: u32 = 1 << 5;
+pub const MODE_COLORS_PER_LED: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub device: u32,
+    pub id: u32,
+    pub size: u32,
+}
+
+impl Header {
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = [0; 16];
+        r.read_exact(&mut buf)?;
+
+        if &buf[0..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad magic"));
+        }
+
+        let mut reader = Reader::new(&buf[4..]);
+        Ok(Self {
+            device: reader.u32()?,
+            id: reader.u32()?,
+            size: reader.u32()?,
+        })
+    }
+
+    /// Reads the data that follows the header
+    pub fn read_data<R: Read>(&self, r: &mut R) -> io::Result<Vec<u8>> {
+        if self.size > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Packet of {} bytes is too large", self.size),
+            ));
+        }
+
+        let mut data = vec![0; self.size as usize];
+        r.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+pub fn write_packet<W: Write>(w: &mut W, device: u32, id: u32, data: &[u8]) -> io::Result<()> {
+    let mut buf = Writer::default();
+    buf.bytes(MAGIC);
+    buf.u32(device);
+    buf.u32(id);
+    buf.u32(data.len() as u32);
+    buf.bytes(data);
+
+    w.write_all(&buf.into_inner())
+}
+
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn i32(&mut self, v: i32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    /// Length prefixed, null terminated
+    pub fn string(&mut self, v: &str) {
+        self.u16(v.len() as u16 + 1);
+        self.bytes(v.as_bytes());
+        self.bytes(&[0]);
+    }
+
+    pub fn color(&mut self, rgb: [u8; 3]) {
+        self.bytes(&[rgb[0], rgb[1], rgb[2], 0]);
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Packet too short",
+            ));
+        }
+
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        let mut b = [0; 2];
+        b.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(b))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    pub fn string(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        let bytes = self.bytes(len)?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    pub fn color(&mut self) -> io::Result<[u8; 3]> {
+        let b = self.bytes(4)?;
+        Ok([b[0], b[1], b[2]])
+    }
+}
+
+/// One entry of the mode list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mode {
+    pub name: String,
+    pub value: i32,
+    pub flags: u32,
+    pub speed_min: u32,
+    pub speed_max: u32,
+    pub colors_min: u32,
+    pub colors_max: u32,
+    pub speed: u32,
+    pub direction: u32,
+    pub color_mode: u32,
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Mode {
+    pub fn write(&self, w: &mut Writer) {
+        w.string(&self.name);
+        w.i32(self.value);
+        w.u32(self.flags);
+        w.u32(self.speed_min);
+        w.u32(self.speed_max);
+        w.u32(self.colors_min);
+        w.u32(self.colors_max);
+        w.u32(self.speed);
+        w.u32(self.direction);
+        w.u32(self.color_mode);
+        w.u16(self.colors.len() as u16);
+        for c in self.colors.iter() {
+            w.color(*c);
+        }
+    }
+
+    pub fn read(r: &mut Reader) -> io::Result<Self> {
+        let name = r.string()?;
+        let value = r.i32()?;
+        let flags = r.u32()?;
+        let speed_min = r.u32()?;
+        let speed_max = r.u32()?;
+        let colors_min = r.u32()?;
+        let colors_max = r.u32()?;
+        let speed = r.u32()?;
+        let direction = r.u32()?;
+        let color_mode = r.u32()?;
+
+        let len = r.u16()?;
+        let colors = (0..len).map(|_| r.color()).collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            name,
+            value,
+            flags,
+            speed_min,
+            speed_max,
+            colors_min,
+            colors_max,
+            speed,
+            direction,
+            color_mode,
+            colors,
+        })
+    }
+}
+
+/// Everything OpenRGB needs to know about a controller
+pub struct ControllerData<'a> {
+    pub name: &'a str,
+    pub vendor: &'a str,
+    pub description: &'a str,
+    pub version: &'a str,
+    pub serial: &'a str,
+    pub location: &'a str,
+    pub modes: &'a [Mode],
+    pub active_mode: i32,
+    pub zone_name: &'a str,
+    pub leds: &'a [&'a str],
+    pub colors: &'a [[u8; 3]],
+}
+
+impl<'a> ControllerData<'a> {
+    /// Serialized for the negotiated `protocol` version (0 or 1)
+    pub fn to_bytes(&self, protocol: u32) -> Vec<u8> {
+        let mut w = Writer::default();
+
+        w.i32(DEVICE_TYPE_MOUSE);
+        w.string(self.name);
+        if protocol >= 1 {
+            w.string(self.vendor);
+        }
+        w.string(self.description);
+        w.string(self.version);
+        w.string(self.serial);
+        w.string(self.location);
+
+        w.u16(self.modes.len() as u16);
+        w.i32(self.active_mode);
+        for mode in self.modes.iter() {
+            mode.write(&mut w);
+        }
+
+        // Single zone holding every LED
+        w.u16(1);
+        w.string(self.zone_name);
+        w.i32(ZONE_TYPE_LINEAR);
+        w.u32(self.leds.len() as u32);
+        w.u32(self.leds.len() as u32);
+        w.u32(self.leds.len() as u32);
+        // No matrix map
+        w.u16(0);
+
+        w.u16(self.leds.len() as u16);
+        for (id, name) in self.leds.iter().enumerate() {
+            w.string(name);
+            w.u32(id as u32);
+        }
+
+        w.u16(self.colors.len() as u16);
+        for c in self.colors.iter() {
+            w.color(*c);
+        }
+
+        // Prefixed with the size of the whole blob, prefix included
+        let body = w.into_inner();
+        let mut out = Writer::default();
+        out.u32(body.len() as u32 + 4);
+        out.bytes(&body);
+        out.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_roundtrip() {
+        let mode = Mode {
+            name: "Breathing".into(),
+            value: 1,
+            flags: MODE_FLAG_HAS_SPEED | MODE_FLAG_HAS_PER_LED_COLOR,
+            speed_min: 5,
+            speed_max: 1,
+            colors_min: 0,
+            colors_max: 0,
+            speed: 3,
+            direction: 0,
+            color_mode: MODE_COLORS_PER_LED,
+            colors: vec![[1, 2, 3]],
+        };
+
+        let mut w = Writer::default();
+        mode.write(&mut w);
+        let bytes = w.into_inner();
+
+        assert_eq!(&[10, 0], &bytes[0..2]);
+        assert_eq!(b"Breathing\0", &bytes[2..12]);
+        assert_eq!(mode, Mode::read(&mut Reader::new(&bytes)).unwrap());
+    }
+}
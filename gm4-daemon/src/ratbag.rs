@@ -0,0 +1,607 @@
+//! ratbagd compatible D-Bus API, so Piper can configure the mouse.
+//!
+//! The GM4 has a single profile, its five DPI stages are exposed as
+//! resolutions and the stage colors as five LEDs. Every LED shares the
+//! one firmware effect. Changes are only sent to the mouse on `Commit`.
+
+use std::convert::TryFrom;
+
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{dbus_interface, fdo};
+
+use gm4_core::config::{self, DPI_LIST};
+use gm4_core::protocol::led::{self, breathe, neon, steady};
+
+use crate::state::SharedState;
+
+pub const BUS_NAME: &str = "org.freedesktop.ratbag1";
+
+const API_VERSION: i32 = 2;
+
+const MANAGER_PATH: &str = "/org/freedesktop/ratbag1";
+const DEVICE_PATH: &str = "/org/freedesktop/ratbag1/device/gm4";
+const PROFILE_PATH: &str = "/org/freedesktop/ratbag1/profile/gm4/p0";
+
+const LED_MODE_ON: u32 = 1;
+const LED_MODE_CYCLE: u32 = 2;
+const LED_MODE_BREATHING: u32 = 3;
+
+/// `ColorDepth` value for 8 bits per channel
+const COLOR_DEPTH_RGB_888: u32 = 1;
+
+fn path(s: String) -> OwnedObjectPath {
+    ObjectPath::try_from(s).unwrap().into()
+}
+
+fn resolution_path(id: usize) -> OwnedObjectPath {
+    path(format!(
+        "/org/freedesktop/ratbag1/resolution/gm4/p0/r{}",
+        id
+    ))
+}
+
+fn led_path(id: usize) -> OwnedObjectPath {
+    path(format!("/org/freedesktop/ratbag1/led/gm4/p0/l{}", id))
+}
+
+fn nearest<T: Copy>(iter: impl Iterator<Item = T>, key: impl Fn(T) -> f64, target: f64) -> T {
+    iter.min_by(|a, b| {
+        let a = (key(*a) - target).abs();
+        let b = (key(*b) - target).abs();
+        a.partial_cmp(&b).unwrap()
+    })
+    .unwrap()
+}
+
+fn led_mode(config: &led::Config) -> u32 {
+    use led::{Config, Effect};
+
+    match config {
+        Config::Breathe(_)
+        | Config::BlinkEffect(_, _, Effect::Respiration(_))
+        | Config::SteadyEffect(_, Effect::Respiration(_)) => LED_MODE_BREATHING,
+        Config::BlinkEffect(_, _, Effect::Neon(_)) | Config::SteadyEffect(_, Effect::Neon(_)) => {
+            LED_MODE_CYCLE
+        }
+        _ => LED_MODE_ON,
+    }
+}
+
+/// Length of one effect cycle in ms, 0 when there is none
+fn effect_duration(config: &led::Config) -> u32 {
+    use led::{Config, Effect};
+
+    let seconds = match config {
+        Config::Breathe(s)
+        | Config::BlinkEffect(_, _, Effect::Respiration(s))
+        | Config::SteadyEffect(_, Effect::Respiration(s)) => s.seconds(),
+        Config::BlinkEffect(_, _, Effect::Neon(s)) | Config::SteadyEffect(_, Effect::Neon(s)) => {
+            s.seconds()
+        }
+        _ => 0.0,
+    };
+
+    (seconds * 1000.0) as u32
+}
+
+fn brightnes(config: &led::Config) -> Option<steady::Brightnes> {
+    use led::{Config, Effect};
+
+    match config {
+        Config::Steady(b)
+        | Config::BlinkEffect(_, _, Effect::Steady(b))
+        | Config::SteadyEffect(_, Effect::Steady(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+fn breathe_speed(ms: u32) -> breathe::Speed {
+    nearest(breathe::Speed::iter(), |s| s.seconds(), ms as f64 / 1000.0)
+}
+
+fn neon_speed(ms: u32) -> neon::Speed {
+    nearest(neon::Speed::iter(), |s| s.seconds(), ms as f64 / 1000.0)
+}
+
+fn set_led_mode(config: &led::Config, mode: u32) -> fdo::Result<led::Config> {
+    let duration = effect_duration(config);
+
+    Ok(match mode {
+        LED_MODE_ON => led::Config::Steady(brightnes(config).unwrap_or(steady::Brightnes::P100)),
+        LED_MODE_BREATHING if duration > 0 => led::Config::Breathe(breathe_speed(duration)),
+        LED_MODE_BREATHING => led::Config::Breathe(Default::default()),
+        // Shortest possible steady phase, so it is mostly the color cycle
+        LED_MODE_CYCLE => led::Config::SteadyEffect(
+            steady::EffectTime::S0_5,
+            led::Effect::Neon(if duration > 0 {
+                neon_speed(duration)
+            } else {
+                Default::default()
+            }),
+        ),
+        _ => {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "Unsupported LED mode {}",
+                mode
+            )))
+        }
+    })
+}
+
+fn set_effect_duration(config: &led::Config, ms: u32) -> led::Config {
+    use led::{Config, Effect};
+
+    match *config {
+        Config::Breathe(_) => Config::Breathe(breathe_speed(ms)),
+        Config::BlinkEffect(f, t, Effect::Respiration(_)) => {
+            Config::BlinkEffect(f, t, Effect::Respiration(breathe_speed(ms)))
+        }
+        Config::BlinkEffect(f, t, Effect::Neon(_)) => {
+            Config::BlinkEffect(f, t, Effect::Neon(neon_speed(ms)))
+        }
+        Config::SteadyEffect(t, Effect::Respiration(_)) => {
+            Config::SteadyEffect(t, Effect::Respiration(breathe_speed(ms)))
+        }
+        Config::SteadyEffect(t, Effect::Neon(_)) => {
+            Config::SteadyEffect(t, Effect::Neon(neon_speed(ms)))
+        }
+        other => other,
+    }
+}
+
+fn set_brightness(config: &led::Config, value: u32) -> led::Config {
+    use led::{Config, Effect};
+
+    let b = nearest(
+        steady::Brightnes::iter(),
+        |b| b.percent() as f64,
+        value as f64 * 100.0 / 255.0,
+    );
+
+    match *config {
+        Config::Steady(_) => Config::Steady(b),
+        Config::BlinkEffect(f, t, Effect::Steady(_)) => {
+            Config::BlinkEffect(f, t, Effect::Steady(b))
+        }
+        Config::SteadyEffect(t, Effect::Steady(_)) => Config::SteadyEffect(t, Effect::Steady(b)),
+        other => other,
+    }
+}
+
+struct Manager;
+
+#[dbus_interface(name = "org.freedesktop.ratbag1.Manager")]
+impl Manager {
+    #[dbus_interface(property, name = "APIVersion")]
+    fn api_version(&self) -> i32 {
+        API_VERSION
+    }
+
+    #[dbus_interface(property)]
+    fn devices(&self) -> Vec<OwnedObjectPath> {
+        vec![path(DEVICE_PATH.into())]
+    }
+}
+
+struct Device {
+    state: SharedState,
+}
+
+#[dbus_interface(name = "org.freedesktop.ratbag1.Device")]
+impl Device {
+    #[dbus_interface(property)]
+    fn model(&self) -> String {
+        "usb:258a:1007:0".into()
+    }
+
+    #[dbus_interface(property)]
+    fn name(&self) -> String {
+        "Modecom MC-GM4".into()
+    }
+
+    #[dbus_interface(property)]
+    fn firmware_version(&self) -> String {
+        String::new()
+    }
+
+    #[dbus_interface(property)]
+    fn profiles(&self) -> Vec<OwnedObjectPath> {
+        vec![path(PROFILE_PATH.into())]
+    }
+
+    /// Sends every pending change to the mouse
+    fn commit(&self) -> fdo::Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .commit("gm4-daemon ratbag")
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+struct Profile {
+    state: SharedState,
+}
+
+#[dbus_interface(name = "org.freedesktop.ratbag1.Profile")]
+impl Profile {
+    #[dbus_interface(property)]
+    fn index(&self) -> u32 {
+        0
+    }
+
+    #[dbus_interface(property)]
+    fn name(&self) -> String {
+        String::new()
+    }
+
+    #[dbus_interface(property)]
+    fn disabled(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn is_dirty(&self) -> bool {
+        self.state.lock().unwrap().is_dirty()
+    }
+
+    /// Unit of the raw report rate byte is not known yet
+    #[dbus_interface(property)]
+    fn report_rate(&self) -> u32 {
+        0
+    }
+
+    #[dbus_interface(property)]
+    fn report_rates(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn resolutions(&self) -> Vec<OwnedObjectPath> {
+        (0..5).map(resolution_path).collect()
+    }
+
+    #[dbus_interface(property)]
+    fn buttons(&self) -> Vec<OwnedObjectPath> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn leds(&self) -> Vec<OwnedObjectPath> {
+        (0..5).map(led_path).collect()
+    }
+
+    fn set_active(&self) {}
+}
+
+struct Resolution {
+    state: SharedState,
+    index: usize,
+}
+
+impl Resolution {
+    fn profile(&self) -> config::Profile {
+        self.state.lock().unwrap().config().profiles()[self.index]
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.ratbag1.Resolution")]
+impl Resolution {
+    #[dbus_interface(property)]
+    fn index(&self) -> u32 {
+        self.index as u32
+    }
+
+    /// The mouse doesn't report which stage is in use
+    #[dbus_interface(property)]
+    fn is_active(&self) -> bool {
+        self.index == 0
+    }
+
+    #[dbus_interface(property)]
+    fn is_default(&self) -> bool {
+        self.index == 0
+    }
+
+    #[dbus_interface(property)]
+    fn is_disabled(&self) -> bool {
+        !self.profile().active
+    }
+
+    #[dbus_interface(property)]
+    fn set_is_disabled(&mut self, disabled: bool) {
+        self.state
+            .lock()
+            .unwrap()
+            .config_mut()
+            .set_profile_active(self.index, !disabled);
+    }
+
+    #[dbus_interface(property)]
+    fn resolution(&self) -> OwnedValue {
+        // 0 tells clients the step is not one they can set
+        (self.profile().dpi_value().unwrap_or(0) as u32).into()
+    }
+
+    #[dbus_interface(property)]
+    fn set_resolution(&mut self, value: OwnedValue) -> fdo::Result<()> {
+        let dpi = u32::try_from(value).map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        let id = config::dpi_id(dpi as u16)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unsupported DPI {}", dpi)))?;
+
+        self.state
+            .lock()
+            .unwrap()
+            .config_mut()
+            .set_profile_dpi(self.index, id);
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn resolutions(&self) -> Vec<u32> {
+        let mut list: Vec<u32> = DPI_LIST.iter().map(|&d| d as u32).collect();
+        list.sort_unstable();
+        list
+    }
+
+    fn set_active(&self) {}
+
+    fn set_default(&self) {}
+}
+
+struct Led {
+    state: SharedState,
+    index: usize,
+}
+
+impl Led {
+    fn led_config(&self) -> led::Config {
+        *self.state.lock().unwrap().config().led_config()
+    }
+
+    fn set_led_config(&self, config: led::Config) {
+        self.state
+            .lock()
+            .unwrap()
+            .config_mut()
+            .set_led_effect(config);
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.ratbag1.Led")]
+impl Led {
+    #[dbus_interface(property)]
+    fn index(&self) -> u32 {
+        self.index as u32
+    }
+
+    #[dbus_interface(property)]
+    fn mode(&self) -> u32 {
+        led_mode(&self.led_config())
+    }
+
+    #[dbus_interface(property)]
+    fn set_mode(&mut self, mode: u32) -> fdo::Result<()> {
+        let config = set_led_mode(&self.led_config(), mode)?;
+        self.set_led_config(config);
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn modes(&self) -> Vec<u32> {
+        vec![LED_MODE_ON, LED_MODE_CYCLE, LED_MODE_BREATHING]
+    }
+
+    #[dbus_interface(property)]
+    fn color(&self) -> (u32, u32, u32) {
+        let rgb = self.state.lock().unwrap().config().profiles()[self.index].rgb;
+        (rgb[0] as u32, rgb[1] as u32, rgb[2] as u32)
+    }
+
+    #[dbus_interface(property)]
+    fn set_color(&mut self, color: (u32, u32, u32)) {
+        let rgb = [color.0 as u8, color.1 as u8, color.2 as u8];
+        self.state
+            .lock()
+            .unwrap()
+            .config_mut()
+            .set_profile_rgb(self.index, rgb);
+    }
+
+    #[dbus_interface(property)]
+    fn color_depth(&self) -> u32 {
+        COLOR_DEPTH_RGB_888
+    }
+
+    #[dbus_interface(property)]
+    fn effect_duration(&self) -> u32 {
+        effect_duration(&self.led_config())
+    }
+
+    #[dbus_interface(property)]
+    fn set_effect_duration(&mut self, ms: u32) {
+        let config = set_effect_duration(&self.led_config(), ms);
+        self.set_led_config(config);
+    }
+
+    #[dbus_interface(property)]
+    fn brightness(&self) -> u32 {
+        let percent = brightnes(&self.led_config())
+            .map(|b| b.percent())
+            .unwrap_or(100);
+        percent as u32 * 255 / 100
+    }
+
+    #[dbus_interface(property)]
+    fn set_brightness(&mut self, value: u32) {
+        let config = set_brightness(&self.led_config(), value);
+        self.set_led_config(config);
+    }
+}
+
+/// Registers every ratbag object on `builder`
+pub fn serve(
+    builder: zbus::blocking::ConnectionBuilder<'static>,
+    state: &SharedState,
+) -> zbus::Result<zbus::blocking::ConnectionBuilder<'static>> {
+    let mut builder = builder
+        .serve_at(MANAGER_PATH, Manager)?
+        .serve_at(
+            DEVICE_PATH,
+            Device {
+                state: state.clone(),
+            },
+        )?
+        .serve_at(
+            PROFILE_PATH,
+            Profile {
+                state: state.clone(),
+            },
+        )?;
+
+    for index in 0..5 {
+        builder = builder
+            .serve_at(
+                resolution_path(index),
+                Resolution {
+                    state: state.clone(),
+                    index,
+                },
+            )?
+            .serve_at(
+                led_path(index),
+                Led {
+                    state: state.clone(),
+                    index,
+                },
+            )?;
+    }
+
+    Ok(builder)
+}
+
+/// Owns `org.freedesktop.ratbag1` on the system or session bus, until the process exits
+pub fn run(state: SharedState, session: bool) -> zbus::Result<()> {
+    let builder = if session {
+        zbus::blocking::ConnectionBuilder::session()?
+    } else {
+        zbus::blocking::ConnectionBuilder::system()?
+    };
+
+    let _connection = serve(builder, &state)?.name(BUS_NAME)?.build()?;
+
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use gm4_core::config::Config;
+    use gm4_core::device::MockDevice;
+    use gm4_core::protocol::ConfigData;
+
+    use std::os::unix::net::UnixStream;
+    use zbus::blocking::{Connection, ConnectionBuilder, Proxy, ProxyBuilder};
+    use zbus::CacheProperties;
+
+    /// Private peer to peer bus between the service and a client
+    fn connect() -> (MockDevice, Connection, Connection) {
+        let device = MockDevice::new(ConfigData::default());
+        let state = State::open(Box::new(device.clone())).unwrap().shared();
+
+        let (a, b) = UnixStream::pair().unwrap();
+        let guid = zbus::Guid::generate();
+
+        let server = std::thread::spawn(move || {
+            serve(ConnectionBuilder::unix_stream(a), &state)
+                .unwrap()
+                .server(&guid)
+                .p2p()
+                .build()
+                .unwrap()
+        });
+        let client = ConnectionBuilder::unix_stream(b).p2p().build().unwrap();
+
+        (device, server.join().unwrap(), client)
+    }
+
+    fn proxy(conn: &Connection, path: &str, iface: &'static str) -> Proxy<'static> {
+        ProxyBuilder::new_bare(conn)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(path.to_owned())
+            .unwrap()
+            .interface(iface)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn object_model() {
+        let (_device, _server, client) = connect();
+
+        let manager = proxy(&client, MANAGER_PATH, "org.freedesktop.ratbag1.Manager");
+        let devices: Vec<OwnedObjectPath> = manager.get_property("Devices").unwrap();
+        assert_eq!(DEVICE_PATH, devices[0].as_str());
+
+        let profile = proxy(&client, PROFILE_PATH, "org.freedesktop.ratbag1.Profile");
+        let resolutions: Vec<OwnedObjectPath> = profile.get_property("Resolutions").unwrap();
+        assert_eq!(5, resolutions.len());
+
+        let resolution = proxy(
+            &client,
+            resolutions[2].as_str(),
+            "org.freedesktop.ratbag1.Resolution",
+        );
+        let dpi: u32 = resolution.get_property("Resolution").unwrap();
+        let expected = Config::new(ConfigData::default()).profiles()[2]
+            .dpi_value()
+            .unwrap();
+        assert_eq!(expected as u32, dpi);
+    }
+
+    #[test]
+    fn commit() {
+        let (device, _server, client) = connect();
+
+        let resolution = proxy(
+            &client,
+            resolution_path(1).as_str(),
+            "org.freedesktop.ratbag1.Resolution",
+        );
+        resolution
+            .set_property("Resolution", OwnedValue::from(3200u32))
+            .unwrap();
+
+        let led = proxy(&client, led_path(3).as_str(), "org.freedesktop.ratbag1.Led");
+        led.set_property("Color", (1u32, 2u32, 3u32)).unwrap();
+        led.set_property("Mode", LED_MODE_BREATHING).unwrap();
+        led.set_property("EffectDuration", 6000u32).unwrap();
+
+        let profile = proxy(&client, PROFILE_PATH, "org.freedesktop.ratbag1.Profile");
+        assert!(profile.get_property::<bool>("IsDirty").unwrap());
+        assert!(device.sent().is_empty());
+
+        let dev = proxy(&client, DEVICE_PATH, "org.freedesktop.ratbag1.Device");
+        dev.call_method("Commit", &()).unwrap();
+
+        let config = Config::new(device.config_data());
+        assert_eq!(Some(3200), config.profiles()[1].dpi_value());
+        assert_eq!([1, 2, 3], config.profiles()[3].rgb);
+        assert_eq!(
+            led::Config::Breathe(breathe::Speed::S6).to_raw(),
+            config.led_config().to_raw()
+        );
+        assert!(!profile.get_property::<bool>("IsDirty").unwrap());
+    }
+}
@@ -0,0 +1,229 @@
+#[cfg(feature = "script")]
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use gm4_core::config::Config;
+use gm4_core::device::{Device, DeviceError};
+#[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+use gm4_core::history::Journal;
+use gm4_core::protocol::ConfigData;
+
+/// Mouse and its config, shared by every service of the daemon
+pub struct State {
+    config: Config,
+    device: Box<dyn Device + Send>,
+    /// Last report sent, so identical updates don't hit the USB
+    last_sent: Option<[u8; 154]>,
+    /// Every commit is added to it, only the web, ratbag and MQTT services commit
+    #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+    journal: Option<Journal>,
+    /// Last report added to the journal
+    #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+    last_journaled: Option<[u8; 154]>,
+}
+
+pub type SharedState = Arc<Mutex<State>>;
+
+impl State {
+    pub fn new(device: Box<dyn Device + Send>, config: Config) -> Self {
+        Self {
+            config,
+            device,
+            last_sent: None,
+            #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+            journal: None,
+            #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+            last_journaled: None,
+        }
+    }
+
+    /// Reads the current config from `device`
+    pub fn open(mut device: Box<dyn Device + Send>) -> Result<Self, DeviceError> {
+        let raw_data = device.read()?;
+        let last_sent = Some(*raw_data.as_bytes());
+
+        Ok(Self {
+            last_sent,
+            ..Self::new(device, Config::try_new(raw_data)?)
+        })
+    }
+
+    #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+    pub fn with_journal(self, journal: Journal) -> Self {
+        Self {
+            journal: Some(journal),
+            // The config the mouse had on start is not a change
+            last_journaled: self.last_sent,
+            ..self
+        }
+    }
+
+    /// Uses a newly opened `device`, the config is sent again on the next apply when
+    /// the mouse doesn't have it
+    #[cfg(feature = "script")]
+    pub fn reconnect(&mut self, mut device: Box<dyn Device + Send>) -> Result<(), DeviceError> {
+        self.last_sent = Some(*device.read()?.as_bytes());
+        self.device = device;
+        Ok(())
+    }
+
+    pub fn shared(self) -> SharedState {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Whether the config differs from what the mouse has
+    pub fn is_dirty(&self) -> bool {
+        let raw: &ConfigData = (&self.config).into();
+        self.last_sent != Some(*raw.as_bytes())
+    }
+
+    /// Sends the config to the mouse.
+    ///
+    /// Used for every frame of a lighting client, so it stays out of the history,
+    /// see `commit`.
+    pub fn apply(&mut self) -> Result<(), DeviceError> {
+        if !self.is_dirty() {
+            return Ok(());
+        }
+
+        let raw: &ConfigData = (&self.config).into();
+        let bytes = *raw.as_bytes();
+
+        self.device.send(raw)?;
+        self.last_sent = Some(bytes);
+        Ok(())
+    }
+
+    /// Sends the config and adds it to the history, for changes a user asked to keep
+    #[cfg(any(feature = "web", feature = "ratbag", feature = "mqtt"))]
+    pub fn commit(&mut self, source: &str) -> Result<(), DeviceError> {
+        self.apply()?;
+
+        if self.last_journaled == self.last_sent {
+            return Ok(());
+        }
+        if let Some(journal) = &self.journal {
+            let raw: &ConfigData = (&self.config).into();
+            if let Err(err) = journal.append(source, raw) {
+                eprintln!("Could not write the history: {}", err);
+            }
+            self.last_journaled = self.last_sent;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes that only make sense together, a color or the LED effect
+#[cfg(feature = "script")]
+fn field(offset: usize) -> Range<usize> {
+    match offset {
+        93..=96 => 93..97,
+        100..=114 => {
+            let start = 100 + (offset - 100) / 3 * 3;
+            start..start + 3
+        }
+        _ => offset..offset + 1,
+    }
+}
+
+/// `Device` view of the shared state, so scripts go through the same config and
+/// USB handle as the other services.
+///
+/// A script works on its own copy of the config, only the fields it changed since it
+/// last read or sent are taken over, so changes made meanwhile by other services stay.
+#[cfg(feature = "script")]
+pub struct StateDevice {
+    state: SharedState,
+    /// What the script last read or sent
+    base: Option<[u8; 154]>,
+}
+
+#[cfg(feature = "script")]
+impl StateDevice {
+    pub fn new(state: SharedState) -> Self {
+        Self { state, base: None }
+    }
+}
+
+#[cfg(feature = "script")]
+impl Device for StateDevice {
+    fn read(&mut self) -> Result<ConfigData, DeviceError> {
+        let state = self.state.lock().unwrap();
+        let raw: &ConfigData = state.config().into();
+        self.base = Some(*raw.as_bytes());
+        Ok(raw.clone())
+    }
+
+    fn send(&mut self, config_data: &ConfigData) -> Result<(), DeviceError> {
+        let mut state = self.state.lock().unwrap();
+        let current: &ConfigData = state.config().into();
+        let mut merged = *current.as_bytes();
+        let base = self.base.unwrap_or(merged);
+        let new = config_data.as_bytes();
+
+        let mut offset = 0;
+        while offset < new.len() {
+            let field = field(offset);
+            if base[field.clone()] != new[field.clone()] {
+                merged[field.clone()].copy_from_slice(&new[field.clone()]);
+            }
+            offset = field.end;
+        }
+
+        let mut merged = ConfigData::from_bytes(merged);
+        // The count has to match the merged profiles, not the script's
+        if base[71] != new[71] {
+            let active = merged.profiles_dpi().iter().filter(|p| p.0).count();
+            merged.set_active_profiles_list_len(active as u8);
+        }
+
+        *state.config_mut() = Config::try_new(merged)?;
+        self.base = Some(*config_data.as_bytes());
+        state.apply()
+    }
+}
+
+#[cfg(all(test, feature = "script"))]
+mod tests {
+    use super::*;
+    use gm4_core::device::MockDevice;
+
+    #[test]
+    fn script_edits_merge() {
+        let device = MockDevice::new(ConfigData::default());
+        let state = State::open(Box::new(device.clone())).unwrap().shared();
+
+        let mut script = StateDevice::new(state.clone());
+        let mut config = Config::new(script.read().unwrap());
+
+        // Another service changes profile 2 while the script changes profile 1
+        state
+            .lock()
+            .unwrap()
+            .config_mut()
+            .set_profile_rgb(1, [1, 2, 3]);
+        config.set_profile_rgb(0, [4, 5, 6]);
+        script.send((&config).into()).unwrap();
+
+        let sent = Config::new(device.sent().last().unwrap().clone());
+        assert_eq!([4, 5, 6], sent.profiles()[0].rgb);
+        assert_eq!([1, 2, 3], sent.profiles()[1].rgb);
+
+        // An unknown LED mode is an error, not a panic that poisons the state
+        let mut bytes = *ConfigData::default().as_bytes();
+        bytes[93] = 0x99;
+        assert!(script.send(&ConfigData::from_bytes(bytes)).is_err());
+        assert!(state.lock().is_ok());
+
+        let device = MockDevice::new(ConfigData::from_bytes(bytes));
+        assert!(State::open(Box::new(device)).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//! Web configuration page and JSON REST API.
+//!
+//! `PUT` only changes the pending config, `POST /api/apply` sends it to the
+//! mouse. Bodies use the `gm4_core::schema` format, the report rate is the raw byte.
+//! Routes:
+//!
+//! - `GET /api/config`
+//! - `GET|PUT /api/profiles`, `GET|PUT /api/profiles/<n>`
+//! - `GET|PUT /api/effect`
+//! - `GET|PUT /api/report_rate`
+//! - `GET /api/dpi_list`
+//! - `POST /api/apply`
+//!
+//! Only requests addressed to `localhost` or an IP address are answered, so other sites
+//! can't reach the API through DNS rebinding. `PUT` and `POST` need a JSON content type,
+//! which browsers don't send cross origin without asking first.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use gm4_core::config::DPI_LIST;
+use gm4_core::device::DeviceError;
+use gm4_core::protocol::error::ProtocolError;
+use gm4_core::protocol::led;
+use gm4_core::schema::ProfileUpdate;
+
+use crate::state::{SharedState, State};
+
+const INDEX_HTML: &str = include_str!("index.html");
+/// Largest request body read, the API only takes small JSON documents
+const MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Debug)]
+enum ApiError {
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    BadRequest(String),
+    Protocol(ProtocolError),
+    Device(DeviceError),
+}
+
+impl ApiError {
+    fn status(&self) -> u16 {
+        match self {
+            ApiError::Forbidden => 403,
+            ApiError::NotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::PayloadTooLarge => 413,
+            ApiError::UnsupportedMediaType => 415,
+            ApiError::BadRequest(_) | ApiError::Protocol(_) => 400,
+            ApiError::Device(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Forbidden => write!(f, "Forbidden"),
+            ApiError::NotFound => write!(f, "Not found"),
+            ApiError::MethodNotAllowed => write!(f, "Method not allowed"),
+            ApiError::PayloadTooLarge => write!(f, "Request body is too large"),
+            ApiError::UnsupportedMediaType => write!(f, "Expected application/json"),
+            ApiError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            ApiError::Protocol(err) => err.fmt(f),
+            ApiError::Device(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<ProtocolError> for ApiError {
+    fn from(err: ProtocolError) -> Self {
+        ApiError::Protocol(err)
+    }
+}
+
+impl From<DeviceError> for ApiError {
+    fn from(err: DeviceError) -> Self {
+        ApiError::Device(err)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::BadRequest(err.to_string())
+    }
+}
+
+/// Address given to `--web`, a port on the loopback address or a full address
+#[derive(Debug, PartialEq)]
+pub struct BindAddr(pub SocketAddr);
+
+impl Default for BindAddr {
+    fn default() -> Self {
+        BindAddr(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 8080))
+    }
+}
+
+impl FromStr for BindAddr {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u16>() {
+            Ok(port) => Ok(BindAddr(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port))),
+            Err(_) => s.parse().map(BindAddr),
+        }
+    }
+}
+
+/// Strips the port from a `Host` header value, keeps IPv6 brackets off
+fn host_name(host: &str) -> &str {
+    match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn config_json(state: &State) -> Value {
+    let mut value = json!(state.config());
+    value["dirty"] = state.is_dirty().into();
+    value
+}
+
+fn profile_id(id: &str) -> Result<usize, ApiError> {
+    match id.parse() {
+        Ok(id) if id < 5 => Ok(id),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+fn report_rate_from_json(value: &Value) -> Result<u8, ApiError> {
+    let value = value.get("report_rate_raw").unwrap_or(value);
+
+    value
+        .as_u64()
+        .filter(|&v| v <= u8::MAX as u64)
+        .map(|v| v as u8)
+        .ok_or_else(|| ApiError::BadRequest(format!("invalid report rate {}", value)))
+}
+
+/// Answers one API call, `path` is relative to `/api/`
+fn route(state: &SharedState, method: &Method, path: &str, body: &str) -> Result<Value, ApiError> {
+    let body = || -> Result<Value, ApiError> { Ok(serde_json::from_str(body)?) };
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let mut state = state.lock().unwrap();
+
+    match (method, parts.as_slice()) {
+        (Method::Get, ["config"]) => Ok(config_json(&state)),
+        (Method::Get, ["dpi_list"]) => {
+            let mut list = DPI_LIST.to_vec();
+            list.sort_unstable();
+            Ok(json!(list))
+        }
+
+        (Method::Get, ["profiles"]) => Ok(json!(state.config().profiles())),
+        (Method::Put, ["profiles"]) => {
+            let body = body()?;
+            let list = body
+                .as_array()
+                .filter(|list| list.len() <= 5)
+                .ok_or_else(|| ApiError::BadRequest("expected up to 5 profiles".into()))?;
+
+            // Check everything first, so a bad entry doesn't leave half of them applied
+            let mut config = state.config().clone();
+            for (id, value) in list.iter().enumerate() {
+                let update: ProfileUpdate = serde_json::from_value(value.clone())?;
+                update.apply(&mut config, id)?;
+            }
+            *state.config_mut() = config;
+
+            Ok(json!(state.config().profiles()))
+        }
+        (Method::Get, ["profiles", id]) => {
+            let id = profile_id(id)?;
+            Ok(json!(state.config().profiles()[id]))
+        }
+        (Method::Put, ["profiles", id]) => {
+            let id = profile_id(id)?;
+            let update: ProfileUpdate = serde_json::from_value(body()?)?;
+            update.apply(state.config_mut(), id)?;
+
+            Ok(json!(state.config().profiles()[id]))
+        }
+
+        (Method::Get, ["effect"]) => Ok(json!(state.config().led_config())),
+        (Method::Put, ["effect"]) => {
+            let effect: led::Config = serde_json::from_value(body()?)?;
+            state.config_mut().set_led_effect(effect);
+            Ok(json!(state.config().led_config()))
+        }
+
+        (Method::Get, ["report_rate"]) => Ok(json!(state.config().report_rate())),
+        (Method::Put, ["report_rate"]) => {
+            let report_rate = report_rate_from_json(&body()?)?;
+            state.config_mut().set_report_rate(report_rate);
+            Ok(json!(report_rate))
+        }
+
+        (Method::Post, ["apply"]) => {
+            state.commit("gm4-daemon web")?;
+            Ok(config_json(&state))
+        }
+
+        (
+            _,
+            ["config"]
+            | ["dpi_list"]
+            | ["profiles"]
+            | ["profiles", _]
+            | ["effect"]
+            | ["report_rate"]
+            | ["apply"],
+        ) => Err(ApiError::MethodNotAllowed),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+fn json_response(status: u16, value: &Value) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+pub struct Server {
+    server: tiny_http::Server,
+    state: SharedState,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, state: SharedState) -> io::Result<Self> {
+        let server = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+        Ok(Self { server, state })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| io::Error::other("not an IP socket"))
+    }
+
+    /// Whether `host` names this server, a hostname other than `localhost` could be
+    /// an attacker's domain pointed at us
+    fn allowed_host(&self, host: &str) -> bool {
+        let host = host_name(host);
+        if host.eq_ignore_ascii_case("localhost") {
+            return true;
+        }
+
+        let bound = self.local_addr().map(|addr| addr.ip());
+        match (host.parse::<IpAddr>(), bound) {
+            (Ok(ip), _) if ip.is_loopback() => true,
+            (Ok(ip), Ok(bound)) => bound.is_unspecified() || ip == bound,
+            _ => false,
+        }
+    }
+
+    /// Checks the headers of an API call and reads its body
+    fn check(&self, request: &mut Request) -> Result<String, ApiError> {
+        let host_allowed = header(request, "Host").is_some_and(|host| self.allowed_host(host));
+        let origin_allowed = header(request, "Origin").is_none_or(|origin| {
+            origin
+                .strip_prefix("http://")
+                .is_some_and(|host| self.allowed_host(host))
+        });
+        if !host_allowed || !origin_allowed {
+            return Err(ApiError::Forbidden);
+        }
+
+        if *request.method() != Method::Get {
+            let json = header(request, "Content-Type").is_some_and(|value| {
+                let media_type = value.split(';').next().unwrap_or("").trim();
+                media_type.eq_ignore_ascii_case("application/json")
+            });
+            if !json {
+                return Err(ApiError::UnsupportedMediaType);
+            }
+        }
+
+        let mut body = Vec::new();
+        request
+            .as_reader()
+            .take(MAX_BODY + 1)
+            .read_to_end(&mut body)
+            .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+        if body.len() as u64 > MAX_BODY {
+            return Err(ApiError::PayloadTooLarge);
+        }
+        String::from_utf8(body).map_err(|err| ApiError::BadRequest(err.to_string()))
+    }
+
+    /// Serves requests one at a time, until the process exits
+    pub fn run(&self) {
+        for request in self.server.incoming_requests() {
+            if let Err(err) = self.respond(request) {
+                eprintln!("web: {}", err);
+            }
+        }
+    }
+
+    fn respond(&self, mut request: Request) -> io::Result<()> {
+        let url = request.url().split('?').next().unwrap_or("").to_owned();
+
+        if url == "/" || url == "/index.html" {
+            let response = Response::from_string(INDEX_HTML).with_header(
+                Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap(),
+            );
+            return request.respond(response);
+        }
+
+        let path = match url.strip_prefix("/api/") {
+            Some(path) => path,
+            None => return request.respond(json_response(404, &json!({ "error": "Not found" }))),
+        };
+
+        let response = match self
+            .check(&mut request)
+            .and_then(|body| route(&self.state, request.method(), path, &body))
+        {
+            Ok(value) => json_response(200, &value),
+            Err(err) => json_response(err.status(), &json!({ "error": err.to_string() })),
+        };
+        request.respond(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gm4_core::config::Config;
+    use gm4_core::device::MockDevice;
+    use gm4_core::protocol::ConfigData;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn serve() -> (MockDevice, SocketAddr) {
+        let device = MockDevice::new(ConfigData::default());
+        let state = State::open(Box::new(device.clone())).unwrap().shared();
+
+        let server = Server::bind("127.0.0.1:0", state).unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run());
+
+        (device, addr)
+    }
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let headers = "Host: localhost\r\nContent-Type: application/json\r\n";
+        request_with(addr, method, path, headers, body)
+    }
+
+    fn request_with(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &str,
+        body: &str,
+    ) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\n{}Connection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap().to_owned();
+        (status, body)
+    }
+
+    fn api(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let (status, body) = request(addr, method, path, body);
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn index() {
+        let (_device, addr) = serve();
+        let (status, body) = request(addr, "GET", "/", "");
+        assert_eq!(200, status);
+        assert!(body.contains("<html>"));
+    }
+
+    #[test]
+    fn put_and_apply() {
+        let (device, addr) = serve();
+
+        let (status, _) = api(
+            addr,
+            "PUT",
+            "/api/profiles/1",
+            r##"{"dpi": 3200, "rgb": "#010203"}"##,
+        );
+        assert_eq!(200, status);
+        let (status, _) = api(
+            addr,
+            "PUT",
+            "/api/effect",
+            r#"{"mode": "blink", "times": "5x"}"#,
+        );
+        assert_eq!(200, status);
+        let (status, _) = api(addr, "PUT", "/api/report_rate", "7");
+        assert_eq!(200, status);
+
+        let (_, config) = api(addr, "GET", "/api/config", "");
+        assert_eq!(json!(true), config["dirty"]);
+        assert_eq!(json!(3200), config["profiles"][1]["dpi"]);
+        assert_eq!(json!("5"), config["effect"]["times"]);
+        assert!(device.sent().is_empty());
+
+        let (status, config) = api(addr, "POST", "/api/apply", "");
+        assert_eq!(200, status);
+        assert_eq!(json!(false), config["dirty"]);
+
+        let config = Config::new(device.config_data());
+        assert_eq!(1, device.sent().len());
+        assert_eq!(Some(3200), config.profiles()[1].dpi_value());
+        assert_eq!([1, 2, 3], config.profiles()[1].rgb);
+        assert_eq!(7, config.report_rate());
+    }
+
+    #[test]
+    fn errors() {
+        let (device, addr) = serve();
+
+        assert_eq!(404, api(addr, "GET", "/api/nothing", "").0);
+        assert_eq!(404, api(addr, "GET", "/api/profiles/5", "").0);
+        assert_eq!(405, api(addr, "DELETE", "/api/effect", "").0);
+        assert_eq!(400, api(addr, "PUT", "/api/effect", "{").0);
+        assert_eq!(400, api(addr, "PUT", "/api/report_rate", "300").0);
+
+        // The valid first entry is not kept either
+        let body = r#"[{"dpi": 1600}, {"dpi": 1234}]"#;
+        assert_eq!(400, api(addr, "PUT", "/api/profiles", body).0);
+        let (_, profiles) = api(addr, "GET", "/api/profiles", "");
+        let expected = Config::new(ConfigData::default()).profiles()[0].dpi_value();
+        assert_eq!(json!(expected), profiles[0]["dpi"]);
+
+        assert_eq!(200, api(addr, "POST", "/api/apply", "").0);
+        assert!(device.sent().is_empty());
+
+        let body = format!("\"{}\"", "x".repeat(MAX_BODY as usize));
+        assert_eq!(413, api(addr, "PUT", "/api/effect", &body).0);
+    }
+
+    #[test]
+    fn cross_site() {
+        let (device, addr) = serve();
+        let request = |headers: &str| request_with(addr, "POST", "/api/apply", headers, "").0;
+
+        assert_eq!(
+            200,
+            request("Host: 127.0.0.1\r\nContent-Type: application/json\r\n")
+        );
+        assert_eq!(
+            200,
+            request("Host: [::1]:80\r\nContent-Type: application/json\r\n")
+        );
+        // DNS rebinding, another site's name resolving to us
+        assert_eq!(
+            403,
+            request("Host: evil.example\r\nContent-Type: application/json\r\n")
+        );
+        assert_eq!(403, request("Content-Type: application/json\r\n"));
+        let origin = "Host: localhost\r\nOrigin: http://evil.example\r\n";
+        assert_eq!(
+            403,
+            request(&format!("{}Content-Type: application/json\r\n", origin))
+        );
+        // A form post from another site
+        let form = "Host: localhost\r\nContent-Type: text/plain\r\n";
+        assert_eq!(415, request(form));
+        assert_eq!(415, request("Host: localhost\r\n"));
+
+        assert!(device.sent().is_empty());
+    }
+
+    #[test]
+    fn bind_addr() {
+        let addr = |s: &str| s.parse::<BindAddr>().map(|addr| addr.0.to_string());
+        assert_eq!(Ok("127.0.0.1:9000".to_owned()), addr("9000"));
+        assert_eq!(Ok("0.0.0.0:80".to_owned()), addr("0.0.0.0:80"));
+        assert!(addr("localhost").is_err());
+    }
+}
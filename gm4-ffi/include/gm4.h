@@ -0,0 +1,120 @@
+/*
+ * C ABI of gm4-core, link with -lgm4_ffi.
+ *
+ * Ownership:
+ *   - gm4_device handles come from gm4_device_open or gm4_device_open_mock
+ *     and must be released with gm4_device_close exactly once.
+ *   - Report buffers are always owned by the caller, the library never keeps
+ *     a pointer to them past the call.
+ *   - Strings returned by gm4_strerror are static, never free them.
+ *
+ * Thread safety:
+ *   - A gm4_device may be moved between threads, but must not be used from
+ *     two threads at the same time. Different handles are independent.
+ *   - gm4_report_* functions only touch the buffer they are given and are
+ *     safe to call from any thread.
+ *
+ * Every function returning int returns GM4_OK or a negative GM4_ERR_* code.
+ */
+
+#ifndef GM4_H
+#define GM4_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GM4_REPORT_SIZE 154
+#define GM4_PROFILE_COUNT 5
+
+enum gm4_error {
+    GM4_OK = 0,
+    /* NULL pointer or profile index out of range */
+    GM4_ERR_INVALID_ARGUMENT = -1,
+    /* Value the mouse doesn't support, e.g. 1234 DPI */
+    GM4_ERR_INVALID_VALUE = -2,
+    /* Report bytes that don't decode */
+    GM4_ERR_INVALID_REPORT = -3,
+    /* Mouse not connected */
+    GM4_ERR_NO_DEVICE = -4,
+    /* Insufficient permissions to open the USB device */
+    GM4_ERR_ACCESS = -5,
+    /* Device claimed by another program */
+    GM4_ERR_BUSY = -6,
+    GM4_ERR_TIMEOUT = -7,
+    /* Transfer failed */
+    GM4_ERR_IO = -8,
+    /* Any other libusb error */
+    GM4_ERR_USB = -9,
+    /* Bug in the library, the call had no effect */
+    GM4_ERR_INTERNAL = -10,
+};
+
+enum gm4_led_mode {
+    GM4_LED_STEADY = 0,
+    GM4_LED_BREATHE = 1,
+    /* Blink, then `effect` */
+    GM4_LED_BLINK = 2,
+    /* Steady for `time_ms`, then `effect` */
+    GM4_LED_STEADY_EFFECT = 3,
+};
+
+enum gm4_led_effect {
+    GM4_EFFECT_RESPIRATION = 0,
+    GM4_EFFECT_STEADY = 1,
+    GM4_EFFECT_NEON = 2,
+};
+
+/*
+ * LED settings, fields not used by `mode` and `effect` are ignored on write
+ * and zeroed on read.
+ */
+typedef struct gm4_led {
+    uint32_t mode;         /* enum gm4_led_mode */
+    uint32_t effect;       /* enum gm4_led_effect, BLINK and STEADY_EFFECT */
+    uint32_t brightness;   /* percent, STEADY or the steady effect */
+    uint32_t speed_ms;     /* cycle length of breathe, respiration or neon */
+    uint32_t frequency_hz; /* BLINK */
+    uint32_t times;        /* BLINK */
+    uint32_t time_ms;      /* STEADY_EFFECT */
+} gm4_led;
+
+typedef struct gm4_device gm4_device;
+
+/* Static description of `code` */
+const char *gm4_strerror(int code);
+
+/* Connects like the other tools: GM4_BACKEND picks hidraw or libusb, GM4_DRY_RUN and
+ * GM4_USB_LOG apply and the report of a first connected mouse is backed up */
+int gm4_device_open(gm4_device **out);
+/* In-memory stand-in for the mouse holding `report`, or the default report if NULL */
+int gm4_device_open_mock(const uint8_t *report, gm4_device **out);
+void gm4_device_close(gm4_device *device);
+
+int gm4_device_read(gm4_device *device, uint8_t report[GM4_REPORT_SIZE]);
+int gm4_device_write(gm4_device *device, const uint8_t report[GM4_REPORT_SIZE]);
+
+/* Report of a factory reset mouse */
+void gm4_report_default(uint8_t report[GM4_REPORT_SIZE]);
+
+int gm4_report_get_dpi(const uint8_t report[GM4_REPORT_SIZE], size_t profile, uint16_t *dpi);
+int gm4_report_set_dpi(uint8_t report[GM4_REPORT_SIZE], size_t profile, uint16_t dpi);
+
+int gm4_report_get_color(const uint8_t report[GM4_REPORT_SIZE], size_t profile, uint8_t rgb[3]);
+int gm4_report_set_color(uint8_t report[GM4_REPORT_SIZE], size_t profile, const uint8_t rgb[3]);
+
+int gm4_report_get_led(const uint8_t report[GM4_REPORT_SIZE], gm4_led *led);
+int gm4_report_set_led(uint8_t report[GM4_REPORT_SIZE], const gm4_led *led);
+
+/* Raw byte, its unit is not known yet */
+int gm4_report_get_report_rate(const uint8_t report[GM4_REPORT_SIZE], uint8_t *rate);
+int gm4_report_set_report_rate(uint8_t report[GM4_REPORT_SIZE], uint8_t rate);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GM4_H */
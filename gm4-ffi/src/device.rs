@@ -0,0 +1,97 @@
+use std::os::raw::c_int;
+use std::ptr;
+
+use gm4_core::device::{Device, MockDevice};
+use gm4_core::protocol::ConfigData;
+
+use crate::{device_code, guard, GM4_ERR_INVALID_ARGUMENT};
+
+/// Opaque `gm4_device`
+pub struct Gm4Device(Box<dyn Device + Send>);
+
+fn open(device: Box<dyn Device + Send>, out: *mut *mut Gm4Device) {
+    unsafe { *out = Box::into_raw(Box::new(Gm4Device(device))) };
+}
+
+/// # Safety
+/// `out` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_device_open(out: *mut *mut Gm4Device) -> c_int {
+    guard(|| {
+        if out.is_null() {
+            return Err(GM4_ERR_INVALID_ARGUMENT);
+        }
+        *out = ptr::null_mut();
+
+        let device = gm4_core::backend::open_from_env().map_err(device_code)?;
+        open(device, out);
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `report` must be NULL or point to 154 readable bytes, `out` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_device_open_mock(
+    report: *const u8,
+    out: *mut *mut Gm4Device,
+) -> c_int {
+    guard(|| {
+        if out.is_null() {
+            return Err(GM4_ERR_INVALID_ARGUMENT);
+        }
+        *out = ptr::null_mut();
+
+        let config_data = match (report as *const [u8; 154]).as_ref() {
+            Some(report) => ConfigData::from_bytes(*report),
+            None => ConfigData::default(),
+        };
+        open(Box::new(MockDevice::new(config_data)), out);
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `device` must be NULL or a handle from `gm4_device_open*` that wasn't closed yet
+#[no_mangle]
+pub unsafe extern "C" fn gm4_device_close(device: *mut Gm4Device) {
+    if !device.is_null() {
+        guard(|| {
+            drop(Box::from_raw(device));
+            Ok(())
+        });
+    }
+}
+
+/// # Safety
+/// `device` must be a live handle, `report` must point to 154 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_device_read(device: *mut Gm4Device, report: *mut u8) -> c_int {
+    guard(|| {
+        let device = device.as_mut().ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+        let report = (report as *mut [u8; 154])
+            .as_mut()
+            .ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+        let config_data = device.0.read().map_err(device_code)?;
+        *report = *config_data.as_bytes();
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `device` must be a live handle, `report` must point to 154 readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_device_write(device: *mut Gm4Device, report: *const u8) -> c_int {
+    guard(|| {
+        let device = device.as_mut().ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+        let report = (report as *const [u8; 154])
+            .as_ref()
+            .ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+        device
+            .0
+            .send(&ConfigData::from_bytes(*report))
+            .map_err(device_code)
+    })
+}
@@ -0,0 +1,89 @@
+//! C ABI of gm4-core, see `include/gm4.h` for the API and its
+//! ownership and thread safety rules.
+//!
+//! Every entry point catches panics, nothing unwinds into C.
+
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+
+use gm4_core::device::DeviceError;
+use gm4_core::protocol::error::ProtocolError;
+
+mod device;
+mod report;
+
+pub use device::*;
+pub use report::*;
+
+pub const GM4_OK: c_int = 0;
+pub const GM4_ERR_INVALID_ARGUMENT: c_int = -1;
+pub const GM4_ERR_INVALID_VALUE: c_int = -2;
+pub const GM4_ERR_INVALID_REPORT: c_int = -3;
+pub const GM4_ERR_NO_DEVICE: c_int = -4;
+pub const GM4_ERR_ACCESS: c_int = -5;
+pub const GM4_ERR_BUSY: c_int = -6;
+pub const GM4_ERR_TIMEOUT: c_int = -7;
+pub const GM4_ERR_IO: c_int = -8;
+pub const GM4_ERR_USB: c_int = -9;
+pub const GM4_ERR_INTERNAL: c_int = -10;
+
+pub(crate) fn usb_code(err: &rusb::Error) -> c_int {
+    match err {
+        rusb::Error::NoDevice | rusb::Error::NotFound => GM4_ERR_NO_DEVICE,
+        rusb::Error::Access => GM4_ERR_ACCESS,
+        rusb::Error::Busy => GM4_ERR_BUSY,
+        rusb::Error::Timeout => GM4_ERR_TIMEOUT,
+        rusb::Error::Io | rusb::Error::Pipe | rusb::Error::Overflow | rusb::Error::Interrupted => {
+            GM4_ERR_IO
+        }
+        _ => GM4_ERR_USB,
+    }
+}
+
+pub(crate) fn device_code(err: DeviceError) -> c_int {
+    match err {
+        DeviceError::Usb(err) => usb_code(&err),
+        // hidraw reports these as io errors
+        DeviceError::Io(err) if err.kind() == std::io::ErrorKind::NotFound => GM4_ERR_NO_DEVICE,
+        DeviceError::Io(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+            GM4_ERR_ACCESS
+        }
+        DeviceError::Io(_) => GM4_ERR_IO,
+        DeviceError::Lock(_) => GM4_ERR_BUSY,
+    }
+}
+
+pub(crate) fn protocol_code(err: ProtocolError) -> c_int {
+    match err {
+        ProtocolError::InvalidRawInput => GM4_ERR_INVALID_REPORT,
+        ProtocolError::InvalidValue(_) => GM4_ERR_INVALID_VALUE,
+    }
+}
+
+/// Runs `f`, turning its error or a panic into an error code
+pub(crate) fn guard(f: impl FnOnce() -> Result<(), c_int>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => GM4_OK,
+        Ok(Err(code)) => code,
+        Err(_) => GM4_ERR_INTERNAL,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn gm4_strerror(code: c_int) -> *const c_char {
+    let msg: &'static [u8] = match code {
+        GM4_OK => b"Success\0",
+        GM4_ERR_INVALID_ARGUMENT => b"Invalid argument\0",
+        GM4_ERR_INVALID_VALUE => b"Value not supported by the mouse\0",
+        GM4_ERR_INVALID_REPORT => b"Invalid report\0",
+        GM4_ERR_NO_DEVICE => b"Mouse not found\0",
+        GM4_ERR_ACCESS => b"Access denied\0",
+        GM4_ERR_BUSY => b"Device busy\0",
+        GM4_ERR_TIMEOUT => b"Timed out\0",
+        GM4_ERR_IO => b"Transfer failed\0",
+        GM4_ERR_USB => b"USB error\0",
+        GM4_ERR_INTERNAL => b"Internal error\0",
+        _ => b"Unknown error\0",
+    };
+    msg.as_ptr() as *const c_char
+}
@@ -0,0 +1,295 @@
+use std::convert::TryFrom;
+use std::os::raw::c_int;
+
+use gm4_core::config::{self, DPI_LIST};
+use gm4_core::protocol::led::{self, blink, breathe, neon, steady};
+use gm4_core::protocol::ConfigData;
+
+use crate::{
+    guard, protocol_code, GM4_ERR_INVALID_ARGUMENT, GM4_ERR_INVALID_REPORT, GM4_ERR_INVALID_VALUE,
+};
+
+pub const GM4_LED_STEADY: u32 = 0;
+pub const GM4_LED_BREATHE: u32 = 1;
+pub const GM4_LED_BLINK: u32 = 2;
+pub const GM4_LED_STEADY_EFFECT: u32 = 3;
+
+pub const GM4_EFFECT_RESPIRATION: u32 = 0;
+pub const GM4_EFFECT_STEADY: u32 = 1;
+pub const GM4_EFFECT_NEON: u32 = 2;
+
+/// `gm4_led`
+#[repr(C)]
+#[derive(Default)]
+pub struct Gm4Led {
+    pub mode: u32,
+    pub effect: u32,
+    pub brightness: u32,
+    pub speed_ms: u32,
+    pub frequency_hz: u32,
+    pub times: u32,
+    pub time_ms: u32,
+}
+
+/// Copy of the report behind `report`
+unsafe fn report_ref(report: *const u8) -> Result<ConfigData, c_int> {
+    (report as *const [u8; 154])
+        .as_ref()
+        .map(|r| ConfigData::from_bytes(*r))
+        .ok_or(GM4_ERR_INVALID_ARGUMENT)
+}
+
+/// Applies `f` to the report behind `report`
+unsafe fn update(
+    report: *mut u8,
+    f: impl FnOnce(&mut ConfigData) -> Result<(), c_int>,
+) -> Result<(), c_int> {
+    let report = (report as *mut [u8; 154])
+        .as_mut()
+        .ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+    let mut config_data = ConfigData::from_bytes(*report);
+    f(&mut config_data)?;
+    *report = *config_data.as_bytes();
+    Ok(())
+}
+
+fn check_profile(profile: usize) -> Result<usize, c_int> {
+    if profile < 5 {
+        Ok(profile)
+    } else {
+        Err(GM4_ERR_INVALID_ARGUMENT)
+    }
+}
+
+fn ms(seconds: f64) -> u32 {
+    (seconds * 1000.0).round() as u32
+}
+
+/// Step whose length in ms is `value`
+fn find<T>(
+    mut iter: impl Iterator<Item = T>,
+    seconds: impl Fn(&T) -> f64,
+    value: u32,
+) -> Result<T, c_int> {
+    iter.find(|v| ms(seconds(v)) == value)
+        .ok_or(GM4_ERR_INVALID_VALUE)
+}
+
+fn effect_to_c(effect: &led::Effect, out: &mut Gm4Led) {
+    match effect {
+        led::Effect::Respiration(s) => {
+            out.effect = GM4_EFFECT_RESPIRATION;
+            out.speed_ms = ms(s.seconds());
+        }
+        led::Effect::Steady(b) => {
+            out.effect = GM4_EFFECT_STEADY;
+            out.brightness = b.percent() as u32;
+        }
+        led::Effect::Neon(s) => {
+            out.effect = GM4_EFFECT_NEON;
+            out.speed_ms = ms(s.seconds());
+        }
+    }
+}
+
+fn brightnes(led: &Gm4Led) -> Result<steady::Brightnes, c_int> {
+    let percent = u8::try_from(led.brightness).map_err(|_| GM4_ERR_INVALID_VALUE)?;
+    steady::Brightnes::from_percent(percent).map_err(protocol_code)
+}
+
+fn breathe_speed(led: &Gm4Led) -> Result<breathe::Speed, c_int> {
+    find(breathe::Speed::iter(), |s| s.seconds(), led.speed_ms)
+}
+
+fn effect_from_c(led: &Gm4Led) -> Result<led::Effect, c_int> {
+    Ok(match led.effect {
+        GM4_EFFECT_RESPIRATION => led::Effect::Respiration(breathe_speed(led)?),
+        GM4_EFFECT_STEADY => led::Effect::Steady(brightnes(led)?),
+        GM4_EFFECT_NEON => {
+            led::Effect::Neon(find(neon::Speed::iter(), |s| s.seconds(), led.speed_ms)?)
+        }
+        _ => return Err(GM4_ERR_INVALID_VALUE),
+    })
+}
+
+fn led_from_c(led: &Gm4Led) -> Result<led::Config, c_int> {
+    let small = |v: u32| u8::try_from(v).map_err(|_| GM4_ERR_INVALID_VALUE);
+
+    Ok(match led.mode {
+        GM4_LED_STEADY => led::Config::Steady(brightnes(led)?),
+        GM4_LED_BREATHE => led::Config::Breathe(breathe_speed(led)?),
+        GM4_LED_BLINK => led::Config::BlinkEffect(
+            blink::Frequency::from_hz(small(led.frequency_hz)?).map_err(protocol_code)?,
+            blink::Times::from_count(small(led.times)?).map_err(protocol_code)?,
+            effect_from_c(led)?,
+        ),
+        GM4_LED_STEADY_EFFECT => led::Config::SteadyEffect(
+            find(steady::EffectTime::iter(), |t| t.seconds(), led.time_ms)?,
+            effect_from_c(led)?,
+        ),
+        _ => return Err(GM4_ERR_INVALID_VALUE),
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_default(report: *mut u8) {
+    guard(|| {
+        update(report, |data| {
+            *data = ConfigData::default();
+            Ok(())
+        })
+    });
+}
+
+/// # Safety
+/// `report` must point to 154 readable bytes, `dpi` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_get_dpi(
+    report: *const u8,
+    profile: usize,
+    dpi: *mut u16,
+) -> c_int {
+    guard(|| {
+        let data = report_ref(report)?;
+        let dpi = dpi.as_mut().ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+        // One indexed, the top bit is the "disabled" flag
+        let id = data.profiles_dpi[check_profile(profile)?] & 0b01111111;
+        *dpi = *DPI_LIST
+            .get((id as usize).wrapping_sub(1))
+            .ok_or(GM4_ERR_INVALID_REPORT)?;
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_set_dpi(report: *mut u8, profile: usize, dpi: u16) -> c_int {
+    guard(|| {
+        let profile = check_profile(profile)?;
+        let id = config::dpi_id(dpi).ok_or(GM4_ERR_INVALID_VALUE)?;
+        update(report, |data| {
+            data.set_profile_dpi(profile, id);
+            Ok(())
+        })
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 readable bytes, `rgb` to 3 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_get_color(
+    report: *const u8,
+    profile: usize,
+    rgb: *mut u8,
+) -> c_int {
+    guard(|| {
+        let data = report_ref(report)?;
+        let rgb = (rgb as *mut [u8; 3])
+            .as_mut()
+            .ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+        *rgb = data.profiles_rgb()[check_profile(profile)?];
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 writable bytes, `rgb` to 3 readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_set_color(
+    report: *mut u8,
+    profile: usize,
+    rgb: *const u8,
+) -> c_int {
+    guard(|| {
+        let profile = check_profile(profile)?;
+        let rgb = *(rgb as *const [u8; 3])
+            .as_ref()
+            .ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+        update(report, |data| {
+            data.set_profile_rgb(profile, rgb);
+            Ok(())
+        })
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 readable bytes, `led` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_get_led(report: *const u8, led: *mut Gm4Led) -> c_int {
+    guard(|| {
+        let data = report_ref(report)?;
+        let out = led.as_mut().ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+
+        let config =
+            led::Config::from_raw(data.led_mode, data.led_arg1, data.led_arg2, data.led_arg3)
+                .map_err(protocol_code)?;
+
+        let mut led = Gm4Led::default();
+        match config {
+            led::Config::Steady(b) => {
+                led.mode = GM4_LED_STEADY;
+                led.brightness = b.percent() as u32;
+            }
+            led::Config::Breathe(s) => {
+                led.mode = GM4_LED_BREATHE;
+                led.speed_ms = ms(s.seconds());
+            }
+            led::Config::BlinkEffect(f, t, e) => {
+                led.mode = GM4_LED_BLINK;
+                led.frequency_hz = f.hz() as u32;
+                led.times = t.count() as u32;
+                effect_to_c(&e, &mut led);
+            }
+            led::Config::SteadyEffect(t, e) => {
+                led.mode = GM4_LED_STEADY_EFFECT;
+                led.time_ms = ms(t.seconds());
+                effect_to_c(&e, &mut led);
+            }
+        }
+        *out = led;
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 writable bytes, `led` must be valid for reads
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_set_led(report: *mut u8, led: *const Gm4Led) -> c_int {
+    guard(|| {
+        let led = led.as_ref().ok_or(GM4_ERR_INVALID_ARGUMENT)?;
+        let (mode, arg1, arg2, arg3) = led_from_c(led)?.to_raw();
+        update(report, |data| {
+            data.set_led_config(mode, arg1, arg2, arg3);
+            Ok(())
+        })
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 readable bytes, `rate` must be valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_get_report_rate(report: *const u8, rate: *mut u8) -> c_int {
+    guard(|| {
+        let data = report_ref(report)?;
+        *rate.as_mut().ok_or(GM4_ERR_INVALID_ARGUMENT)? = data.report_rate;
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `report` must point to 154 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn gm4_report_set_report_rate(report: *mut u8, rate: u8) -> c_int {
+    guard(|| {
+        update(report, |data| {
+            data.report_rate = rate;
+            Ok(())
+        })
+    })
+}
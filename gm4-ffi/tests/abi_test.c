@@ -0,0 +1,130 @@
+/* Exercises the C ABI against the mock device, run by tests/c_abi.rs */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "gm4.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            failures++;                                                      \
+        }                                                                    \
+    } while (0)
+
+static void test_report(void)
+{
+    uint8_t report[GM4_REPORT_SIZE];
+    uint8_t rgb[3] = {1, 2, 3};
+    uint8_t out_rgb[3];
+    uint16_t dpi;
+    uint8_t rate;
+
+    gm4_report_default(report);
+
+    CHECK(gm4_report_set_dpi(report, 2, 3200) == GM4_OK);
+    CHECK(gm4_report_get_dpi(report, 2, &dpi) == GM4_OK);
+    CHECK(dpi == 3200);
+
+    CHECK(gm4_report_set_color(report, 4, rgb) == GM4_OK);
+    CHECK(gm4_report_get_color(report, 4, out_rgb) == GM4_OK);
+    CHECK(memcmp(rgb, out_rgb, 3) == 0);
+
+    CHECK(gm4_report_set_report_rate(report, 7) == GM4_OK);
+    CHECK(gm4_report_get_report_rate(report, &rate) == GM4_OK);
+    CHECK(rate == 7);
+
+    CHECK(gm4_report_set_dpi(report, 0, 1234) == GM4_ERR_INVALID_VALUE);
+    CHECK(gm4_report_set_dpi(report, GM4_PROFILE_COUNT, 800) == GM4_ERR_INVALID_ARGUMENT);
+    CHECK(gm4_report_set_color(NULL, 0, rgb) == GM4_ERR_INVALID_ARGUMENT);
+    CHECK(gm4_report_get_dpi(report, 0, NULL) == GM4_ERR_INVALID_ARGUMENT);
+}
+
+static void test_led(void)
+{
+    uint8_t report[GM4_REPORT_SIZE];
+    gm4_led led = {0};
+    gm4_led out;
+
+    gm4_report_default(report);
+
+    led.mode = GM4_LED_BLINK;
+    led.frequency_hz = 3;
+    led.times = 2;
+    led.effect = GM4_EFFECT_NEON;
+    led.speed_ms = 1500;
+    CHECK(gm4_report_set_led(report, &led) == GM4_OK);
+
+    memset(&out, 0xff, sizeof(out));
+    CHECK(gm4_report_get_led(report, &out) == GM4_OK);
+    CHECK(memcmp(&led, &out, sizeof(led)) == 0);
+
+    led.mode = GM4_LED_STEADY;
+    led.brightness = 42;
+    CHECK(gm4_report_set_led(report, &led) == GM4_ERR_INVALID_VALUE);
+    led.mode = 9;
+    CHECK(gm4_report_set_led(report, &led) == GM4_ERR_INVALID_VALUE);
+
+    /* An unknown LED mode byte doesn't decode */
+    memset(report, 0, sizeof(report));
+    CHECK(gm4_report_get_led(report, &out) == GM4_ERR_INVALID_REPORT);
+}
+
+static void test_mock_device(void)
+{
+    uint8_t report[GM4_REPORT_SIZE];
+    uint8_t stored[GM4_REPORT_SIZE];
+    gm4_device *device = NULL;
+    uint16_t dpi;
+
+    CHECK(gm4_device_open_mock(NULL, &device) == GM4_OK);
+    CHECK(device != NULL);
+
+    CHECK(gm4_device_read(device, report) == GM4_OK);
+    CHECK(gm4_report_set_dpi(report, 0, 6000) == GM4_OK);
+    CHECK(gm4_device_write(device, report) == GM4_OK);
+
+    CHECK(gm4_device_read(device, stored) == GM4_OK);
+    CHECK(memcmp(report, stored, sizeof(report)) == 0);
+    CHECK(gm4_report_get_dpi(stored, 0, &dpi) == GM4_OK);
+    CHECK(dpi == 6000);
+
+    CHECK(gm4_device_read(NULL, report) == GM4_ERR_INVALID_ARGUMENT);
+    CHECK(gm4_device_write(device, NULL) == GM4_ERR_INVALID_ARGUMENT);
+
+    gm4_device_close(device);
+    gm4_device_close(NULL);
+
+    /* Starts out with the given report */
+    CHECK(gm4_device_open_mock(stored, &device) == GM4_OK);
+    CHECK(gm4_device_read(device, report) == GM4_OK);
+    CHECK(memcmp(report, stored, sizeof(report)) == 0);
+    gm4_device_close(device);
+
+    CHECK(gm4_device_open_mock(NULL, NULL) == GM4_ERR_INVALID_ARGUMENT);
+}
+
+static void test_strerror(void)
+{
+    CHECK(strcmp(gm4_strerror(GM4_OK), "Success") == 0);
+    CHECK(strcmp(gm4_strerror(GM4_ERR_NO_DEVICE), "Mouse not found") == 0);
+    CHECK(strcmp(gm4_strerror(-100), "Unknown error") == 0);
+}
+
+int main(void)
+{
+    test_report();
+    test_led();
+    test_mock_device();
+    test_strerror();
+
+    if (failures) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,33 @@
+//! Builds `abi_test.c` against the shared library and runs it.
+//! Needs a C compiler, `cc` or the one in `$CC`.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn c_abi() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // target/<profile>/deps, next to the test binary
+    let lib_dir = env::current_exe().unwrap().parent().unwrap().to_owned();
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let exe = out_dir.join("gm4_abi_test");
+
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror"])
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/abi_test.c"))
+        .arg("-o")
+        .arg(&exe)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lgm4_ffi")
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .status()
+        .expect("could not run the C compiler");
+    assert!(status.success(), "abi_test.c did not compile");
+
+    let status = Command::new(&exe).status().unwrap();
+    assert!(status.success(), "C ABI checks failed");
+}
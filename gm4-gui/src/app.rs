@@ -3,6 +3,8 @@ use gio::prelude::*;
 use glib::prelude::*;
 use gtk::prelude::*;
 
+use std::time::Duration;
+
 use gtk::Orientation::Vertical;
 use relm::{Relm, Widget};
 use relm_derive::{widget, Msg};
@@ -15,9 +17,113 @@ use crate::profiles::page::{
 
 use crate::effects::page::{self as effects_page, Msg::ConfigChanged, Page as EffectsPage};
 
+use gm4_core::backup::{self, Backups};
+use gm4_core::device::worker::{Event, Task};
+use gm4_core::device::Worker;
+use gm4_core::protocol::ConfigData;
+
+/// How long the config has to stay unchanged before live preview sends it
+const PREVIEW_DEBOUNCE_MS: u32 = 150;
+/// How often the worker checks for a replugged mouse or changes made by other tools
+const WATCH_INTERVAL_MS: u64 = 2000;
+
 pub struct Model {
+    relm: Relm<App>,
     config_data: gm4_core::config::Config,
-    usb_device: gm4_core::usb::MouseDevice,
+    /// Config that is stored in the mouse, restored on Cancel
+    saved_config_data: gm4_core::config::Config,
+    /// Device transfers run on the worker thread, its events come back as `Msg::Device`
+    worker: Worker,
+    _events: relm::Channel<Event>,
+    status: String,
+    /// Whether the config was read from the mouse, until then the defaults are shown
+    /// and nothing can be edited
+    loaded: bool,
+    /// Snapshots of the connected mouse
+    backups: Option<Backups>,
+    /// Saved config that goes into the history once the worker has sent it
+    pending_record: Option<(&'static str, ConfigData)>,
+
+    live_preview: bool,
+    /// Bumped on every change, so only the last scheduled preview is sent
+    preview_generation: u32,
+}
+
+impl Model {
+    fn send(&mut self, config_data: &gm4_core::config::Config) {
+        let raw: &ConfigData = config_data.into();
+        self.worker.apply(raw.clone());
+    }
+
+    /// Sends `config_data`, once the mouse has it it becomes the saved config and goes
+    /// into the history
+    fn save(&mut self, source: &'static str, config_data: &gm4_core::config::Config) {
+        self.send(config_data);
+        let raw: &ConfigData = config_data.into();
+        self.pending_record = Some((source, raw.clone()));
+    }
+
+    /// Makes the pending save the saved config once the mouse has exactly that config
+    fn applied(&mut self, raw: &ConfigData) {
+        let sent = match &self.pending_record {
+            Some((_, pending)) => pending.as_bytes() == raw.as_bytes(),
+            None => false,
+        };
+        if !sent {
+            return;
+        }
+
+        let (source, raw) = self.pending_record.take().unwrap();
+        self.saved_config_data = gm4_core::config::Config::new(raw.clone());
+        if !gm4_core::backend::dry_run() {
+            gm4_core::history::record(source, &raw).ok();
+        }
+    }
+
+    /// What the mouse should keep when the app closes, the config being saved if any
+    fn to_keep(&self) -> ConfigData {
+        match &self.pending_record {
+            Some((_, raw)) => raw.clone(),
+            None => {
+                let raw: &ConfigData = (&self.saved_config_data).into();
+                raw.clone()
+            }
+        }
+    }
+
+    fn backups(&self) -> Result<&Backups, String> {
+        self.backups
+            .as_ref()
+            .ok_or_else(|| "The mouse is not connected".to_owned())
+    }
+
+    fn backup(&self) -> Result<std::path::PathBuf, String> {
+        let raw: &ConfigData = (&self.saved_config_data).into();
+        self.backups()?.save(raw).map_err(|err| err.to_string())
+    }
+
+    fn original(&self) -> Result<ConfigData, String> {
+        backup::load(self.backups()?.original()).map_err(|err| err.to_string())
+    }
+
+    /// Whether there are changes that are not saved yet
+    fn edited(&self) -> bool {
+        let config_data: &ConfigData = (&self.config_data).into();
+        let saved: &ConfigData = (&self.saved_config_data).into();
+        config_data.as_bytes() != saved.as_bytes()
+    }
+
+    fn changed(&mut self) {
+        if !self.live_preview {
+            return;
+        }
+
+        self.preview_generation = self.preview_generation.wrapping_add(1);
+        let generation = self.preview_generation;
+        relm::timeout(self.relm.stream(), PREVIEW_DEBOUNCE_MS, move || {
+            Msg::PushPreview(generation)
+        });
+    }
 }
 
 #[derive(Msg)]
@@ -28,24 +134,59 @@ pub enum Msg {
 
     EffectConfigChanged(gm4_core::protocol::led::Config),
 
+    LivePreviewToggled(bool),
+    PushPreview(u32),
+
     Save,
+    Cancel,
     Quit,
+
+    Backup,
+    RestoreOriginal,
+    FactoryReset,
+    /// Sends a whole config and shows it
+    Replace(ConfigData),
+
+    Device(Event),
+}
+
+fn set_initial(
+    profiles: &relm::Component<ProfilesPage>,
+    effects: &relm::Component<EffectsPage>,
+    config_data: &gm4_core::config::Config,
+) {
+    {
+        use profiles_page::Msg::SetInitial;
+        profiles.emit(SetInitial(config_data.profiles().clone()));
+    }
+
+    {
+        use effects_page::Msg::SetInitial;
+        effects.emit(SetInitial(config_data.led_config().clone()));
+    }
+
+    set_preview_color(effects, config_data);
+}
+
+/// The effect preview uses the color of the first active profile
+fn set_preview_color(
+    effects: &relm::Component<EffectsPage>,
+    config_data: &gm4_core::config::Config,
+) {
+    let profiles = config_data.profiles();
+    let profile = profiles.iter().find(|p| p.active).unwrap_or(&profiles[0]);
+
+    effects.emit(effects_page::Msg::SetPreviewColor(profile.rgb));
 }
 
 #[widget]
 impl Widget for App {
     fn init_view(&mut self) {
-        {
-            use profiles_page::Msg::SetInitial;
-            self.profiles_page
-                .emit(SetInitial(self.model.config_data.profiles().clone()));
-        }
-
-        {
-            use effects_page::Msg::SetInitial;
-            self.effects_page
-                .emit(SetInitial(self.model.config_data.led_config().clone()));
-        }
+        set_initial(
+            &self.profiles_page,
+            &self.effects_page,
+            &self.model.config_data,
+        );
 
         let l1 = gtk::Label::new(Some("Colors & DPI"));
         self.notebook
@@ -99,33 +240,32 @@ impl Widget for App {
         // self.test_box.show_all();
     }
 
-    fn model(_relm: &Relm<Self>, _: ()) -> Model {
-        let mut usb_device = match gm4_core::usb::MouseDevice::new() {
-            Ok(dev) => dev,
-            Err(err) => {
-                let d = gtk::MessageDialogBuilder::new()
-                    .message_type(gtk::MessageType::Error)
-                    .buttons(gtk::ButtonsType::Ok)
-                    .text("Could not connect to the mouse.")
-                    .build();
+    fn model(relm: &Relm<Self>, _: ()) -> Model {
+        let stream = relm.stream().clone();
+        let (events, sender) = relm::Channel::new(move |event| stream.emit(Msg::Device(event)));
 
-                d.run();
+        let worker = Worker::spawn(gm4_core::backend::open_from_env, move |event| {
+            sender.send(event).ok();
+        });
+        worker.read();
+        worker.watch(Some(Duration::from_millis(WATCH_INTERVAL_MS)));
 
-                unsafe { d.destroy() };
-
-                panic!("Could not connect to the mouse. \n {:#?}", err);
-            }
-        };
-
-        usb_device.kernel_detach().ok();
-        let raw_data = usb_device
-            .read()
-            .unwrap_or(gm4_core::protocol::ConfigData::default());
-        usb_device.kernel_attach().ok();
+        // Shown until the worker reads the real config
+        let config_data = gm4_core::config::Config::new(ConfigData::default());
 
         Model {
-            usb_device,
-            config_data: gm4_core::config::Config::new(raw_data),
+            relm: relm.clone(),
+            worker,
+            _events: events,
+            status: "Connecting to the mouse...".to_owned(),
+            loaded: false,
+            backups: None,
+            pending_record: None,
+            saved_config_data: config_data.clone(),
+            config_data,
+
+            live_preview: false,
+            preview_generation: 0,
         }
     }
 
@@ -134,29 +274,154 @@ impl Widget for App {
         match event {
             ProfileActiveChanged(id, active) => {
                 self.model.config_data.set_profile_active(id, active);
+                self.model.changed();
+
+                set_preview_color(&self.effects_page, &self.model.config_data);
             }
             ProfileDPIChanged(id, dpi) => {
                 self.model.config_data.set_profile_dpi(id, dpi);
+                self.model.changed();
             }
             ProfileColorChanged(id, rgba) => {
                 self.model
                     .config_data
                     .set_profile_rgb(id, crate::rgba_to_arr(rgba));
+                self.model.changed();
+
+                set_preview_color(&self.effects_page, &self.model.config_data);
             }
             EffectConfigChanged(c) => {
                 self.model.config_data.set_led_effect(c);
+                self.model.changed();
             }
-            Save => {
-                if let Ok(_) = self.model.usb_device.kernel_detach() {
-                    let raw_config: &gm4_core::protocol::ConfigData =
-                        (&self.model.config_data).into();
-                    self.model.usb_device.send(raw_config).ok();
-                    self.model.usb_device.kernel_attach().ok();
+            LivePreviewToggled(active) => {
+                self.model.live_preview = active;
+
+                let config_data = if active {
+                    self.model.config_data.clone()
                 } else {
-                    self.model.usb_device.kernel_attach().ok();
+                    // Preview is over, put back what is really saved
+                    self.model.saved_config_data.clone()
+                };
+                self.model.send(&config_data);
+            }
+            PushPreview(generation) => {
+                if self.model.live_preview && generation == self.model.preview_generation {
+                    let config_data = self.model.config_data.clone();
+                    self.model.send(&config_data);
+                }
+            }
+            Save => {
+                let config_data = self.model.config_data.clone();
+                self.model.save("gm4-gui", &config_data);
+            }
+            Cancel => {
+                self.model.config_data = self.model.saved_config_data.clone();
+                // Stale previews must not overwrite the restored config
+                self.model.preview_generation = self.model.preview_generation.wrapping_add(1);
+
+                if self.model.live_preview {
+                    let config_data = self.model.config_data.clone();
+                    self.model.send(&config_data);
+                }
+
+                set_initial(
+                    &self.profiles_page,
+                    &self.effects_page,
+                    &self.model.config_data,
+                );
+            }
+            Quit => {
+                // Don't leave an unsaved preview on the mouse
+                let keep = self.model.to_keep();
+                let config_data: &ConfigData = (&self.model.config_data).into();
+                if self.model.live_preview && config_data.as_bytes() != keep.as_bytes() {
+                    self.model.worker.apply(keep);
                 }
+
+                // A save sent while quitting has no `Applied` event handled anymore
+                if let Some(last) = self.model.worker.shutdown() {
+                    self.model.applied(&last);
+                }
+                gtk::main_quit();
+            }
+
+            Backup => {
+                self.model.status = match self.model.backup() {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(err) => format!("Backup failed: {}", err),
+                };
+            }
+            RestoreOriginal => match self.model.original() {
+                Ok(raw_data) => self.model.relm.stream().emit(Replace(raw_data)),
+                Err(err) => self.model.status = format!("Could not restore: {}", err),
+            },
+            FactoryReset => self.model.relm.stream().emit(Replace(backup::factory())),
+            Replace(raw_data) => {
+                let config_data = match gm4_core::config::Config::try_new(raw_data) {
+                    Ok(config_data) => config_data,
+                    Err(err) => {
+                        self.model.status = format!("Could not restore: {}", err);
+                        return;
+                    }
+                };
+                self.model.save("gm4-gui restore", &config_data);
+                self.model.config_data = config_data;
+                self.model.preview_generation = self.model.preview_generation.wrapping_add(1);
+
+                set_initial(
+                    &self.profiles_page,
+                    &self.effects_page,
+                    &self.model.config_data,
+                );
+            }
+
+            Device(event) => {
+                let status = match event {
+                    Event::Busy(Task::Connect) => "Connecting to the mouse...".to_owned(),
+                    Event::Busy(Task::Read) => "Reading...".to_owned(),
+                    Event::Busy(Task::Apply) => "Sending...".to_owned(),
+                    Event::Connected(serial) => {
+                        let id = backup::mouse_id(serial.as_deref());
+                        self.model.backups = Backups::open(&id).ok();
+                        String::new()
+                    }
+                    Event::Done(_) => String::new(),
+                    Event::Applied(raw_data) => {
+                        // Previews sent before a save finish first, only the save counts
+                        self.model.applied(&raw_data);
+                        return;
+                    }
+                    Event::Disconnected => "Mouse disconnected".to_owned(),
+                    Event::Error(Task::Connect, err) => {
+                        format!("Could not connect to the mouse: {}", err)
+                    }
+                    Event::Error(_, err) => format!("Error: {}", err),
+                    Event::Read(raw_data) | Event::Changed(raw_data) => {
+                        // Changes made by another tool don't overwrite unsaved edits
+                        if self.model.loaded && self.model.edited() {
+                            return;
+                        }
+                        match gm4_core::config::Config::try_new(raw_data) {
+                            Ok(config_data) => {
+                                self.model.loaded = true;
+                                self.model.saved_config_data = config_data.clone();
+                                self.model.config_data = config_data;
+
+                                set_initial(
+                                    &self.profiles_page,
+                                    &self.effects_page,
+                                    &self.model.config_data,
+                                );
+                                return;
+                            }
+                            Err(err) => format!("Unsupported config in the mouse: {}", err),
+                        }
+                    }
+                };
+
+                self.model.status = status;
             }
-            Quit => gtk::main_quit(),
         }
     }
 
@@ -168,6 +433,7 @@ impl Widget for App {
                 #[name="notebook"]
                 gtk::Notebook{
                     vexpand: true,
+                    sensitive: self.model.loaded,
                     #[name="profiles_page"]
                     ProfilesPage {
                         ActiveChanged(id,b) => Msg::ProfileActiveChanged(id,b),
@@ -184,13 +450,56 @@ impl Widget for App {
                     //     orientation: Vertical,
                     // }
                 },
-                gtk::Button{
-                    label: "Save",
-                    clicked(_) => Msg::Save
+                gtk::Box {
+                    spacing: 10,
+                    margin_start: 10,
+                    margin_end: 10,
+                    margin_bottom: 10,
+                    sensitive: self.model.loaded,
+
+                    gtk::Button{
+                        label: "Backup",
+                        clicked(_) => Msg::Backup
+                    },
+                    gtk::Button{
+                        label: "Restore original",
+                        clicked(_) => Msg::RestoreOriginal
+                    },
+                    gtk::Button{
+                        label: "Factory reset",
+                        clicked(_) => Msg::FactoryReset
+                    },
+                },
+                gtk::Box {
+                    spacing: 10,
+                    margin_start: 10,
+                    margin_end: 10,
+                    margin_bottom: 10,
+
+                    gtk::CheckButton{
+                        label: "Live preview",
+                        hexpand: true,
+                        sensitive: self.model.loaded,
+                        toggled(cb) => Msg::LivePreviewToggled(cb.get_active())
+                    },
+                    gtk::Label{
+                        text: &self.model.status,
+                    },
+                    gtk::Button{
+                        label: "Cancel",
+                        sensitive: self.model.loaded,
+                        clicked(_) => Msg::Cancel
+                    },
+                    gtk::Button{
+                        label: "Save",
+                        sensitive: self.model.loaded,
+                        clicked(_) => Msg::Save
+                    },
                 },
 
             },
-            delete_event(_, _) => (Msg::Quit, Inhibit(false)),
+            // Quit closes the app once the mouse is restored
+            delete_event(_, _) => (Msg::Quit, Inhibit(true)),
         }
     }
 }
@@ -36,14 +36,14 @@ pub struct Model {
 #[widget]
 impl Widget for BlinkEffectControls {
     fn init_view(&mut self) {
-        for n in 1..11 {
+        for f in protocol::led::blink::Frequency::iter() {
             self.frequency_combo
-                .append(Some(&n.to_string()), &format!("{}Hz", n));
+                .append(Some(&(f as u8).to_string()), &f.to_string());
         }
 
-        for n in 1..11 {
+        for t in protocol::led::blink::Times::iter() {
             self.times_combo
-                .append(Some(&n.to_string()), &format!("{}", n));
+                .append(Some(&(t as u8).to_string()), &t.to_string());
         }
 
         let labels = ["Respiration", "Steady", "Neon"];
@@ -83,50 +83,39 @@ impl Widget for BlinkEffectControls {
                 self.model.subeffect_config = e;
                 self.update(BuildUiCombo);
             }
-            BuildUiCombo => {
-                match self.model.subeffect_config {
-                    protocol::led::Effect::Respiration(s) => {
-                        self.subeffect_combo.remove_all();
-                        // breathe::Speed
-                        for n in 1..6 {
-                            self.subeffect_combo
-                                .append(Some(&n.to_string()), &format!("{}s", n + 3));
-                        }
-
+            BuildUiCombo => match self.model.subeffect_config {
+                protocol::led::Effect::Respiration(s) => {
+                    self.subeffect_combo.remove_all();
+                    for s in protocol::led::breathe::Speed::iter() {
                         self.subeffect_combo
-                            .set_active_id(Some(&(s as i32).to_string()));
+                            .append(Some(&(s as u8).to_string()), &s.to_string());
                     }
-                    protocol::led::Effect::Steady(b) => {
-                        self.subeffect_combo.remove_all();
-                        // steady::Brightnes
-                        let labels = [
-                            "5%", "15%", "25%", "35%", "45%", "55%", "65%", "75%", "85%", "100%",
-                        ];
-                        for n in 1..11 {
-                            self.subeffect_combo
-                                .append(Some(&n.to_string()), labels[n - 1]);
-                        }
 
+                    self.subeffect_combo
+                        .set_active_id(Some(&(s as i32).to_string()));
+                }
+                protocol::led::Effect::Steady(b) => {
+                    self.subeffect_combo.remove_all();
+                    for b in protocol::led::steady::Brightnes::iter() {
                         self.subeffect_combo
-                            .set_active_id(Some(&(b as i32).to_string()));
+                            .append(Some(&(b as u8).to_string()), &b.to_string());
                     }
-                    protocol::led::Effect::Neon(s) => {
-                        self.subeffect_combo.remove_all();
-
-                        let labels = [
-                            "0.5s", "1s", "1.5s", "2s", "2.5s", "3s", "3.5s", "4s", "4.5s", "5s",
-                            "5.5s", "6s", "6.5s", "7s", "7.5s",
-                        ];
-                        for n in 1..16 {
-                            self.subeffect_combo
-                                .append(Some(&n.to_string()), labels[n - 1]);
-                        }
 
+                    self.subeffect_combo
+                        .set_active_id(Some(&(b as i32).to_string()));
+                }
+                protocol::led::Effect::Neon(s) => {
+                    self.subeffect_combo.remove_all();
+
+                    for s in protocol::led::neon::Speed::iter() {
                         self.subeffect_combo
-                            .set_active_id(Some(&(s as i32).to_string()));
+                            .append(Some(&(s as u8).to_string()), &s.to_string());
                     }
+
+                    self.subeffect_combo
+                        .set_active_id(Some(&(s as i32).to_string()));
                 }
-            }
+            },
             EffectComboChanged(new_id) => {
                 let curr_id = match self.model.subeffect_config {
                     protocol::led::Effect::Respiration(_) => 0,
@@ -24,9 +24,9 @@ pub struct Model {
 #[widget]
 impl Widget for BreatheControls {
     fn init_view(&mut self) {
-        for n in 1..6 {
+        for s in protocol::led::breathe::Speed::iter() {
             self.combo
-                .append(Some(&n.to_string()), &format!("{}s", n + 3));
+                .append(Some(&(s as u8).to_string()), &s.to_string());
         }
     }
 
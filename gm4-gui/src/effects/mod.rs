@@ -25,6 +25,9 @@ pub mod page {
 
     use gm4_core::protocol;
 
+    /// Time between two frames of the effect preview
+    const PREVIEW_FRAME_MS: u32 = 40;
+
     #[derive(Debug)]
     pub enum ComboType {
         ConfigType,
@@ -57,6 +60,9 @@ pub mod page {
         BlinkEffectEffectChanged(protocol::led::Effect),
 
         BuildControlUi(ControlUiType),
+
+        SetPreviewColor([u8; 3]),
+        PreviewTick,
     }
 
     pub struct Model {
@@ -65,6 +71,10 @@ pub mod page {
         current_controls: Option<(gtk::Box, Vec<(gtk::Widget, glib::SignalHandlerId)>)>,
 
         effect_config: protocol::led::Config,
+
+        org_pxb: gdk_pixbuf::Pixbuf,
+        preview_color: [u8; 3],
+        preview_start: std::time::Instant,
     }
 
     #[widget]
@@ -74,60 +84,87 @@ pub mod page {
             self.combo.append(Some("1"), "Breathe");
             self.combo.append(Some("2"), "Blink + Effect");
             self.combo.append(Some("3"), "Steady + Effect");
+
+            relm::interval(self.model.relm.stream(), PREVIEW_FRAME_MS, || {
+                Msg::PreviewTick
+            });
         }
 
         fn model(relm: &Relm<Self>, _: ()) -> Model {
+            let pxb = gdk_pixbuf::PixbufLoader::new();
+            pxb.write(include_bytes!("../../img/colors.png")).unwrap();
+            pxb.close().unwrap();
+            let pxb = pxb.get_pixbuf().unwrap();
+
             Model {
                 relm: relm.clone(),
                 current_controls: None,
                 current_config_combo_id: None,
 
                 effect_config: Default::default(),
+
+                org_pxb: pxb,
+                preview_color: [255, 255, 255],
+                preview_start: std::time::Instant::now(),
             }
         }
 
         fn update(&mut self, event: Msg) {
             use Msg::*;
             match event {
-                SetInitial(config) => match config {
-                    protocol::led::Config::Steady(b) => {
-                        self.combo.set_active_id(Some("0"));
-                        self.model.current_config_combo_id = Some(0);
+                SetInitial(config) => {
+                    self.steady_controls
+                        .emit(steady_controls::Msg::SetVisible(false));
+                    self.breathe_controls
+                        .emit(breathe_controls::Msg::SetVisible(false));
+                    self.blink_effect_controls
+                        .emit(blink_effect_controls::Msg::SetVisible(false));
 
-                        self.model.effect_config = protocol::led::Config::Steady(b.clone());
+                    match config {
+                        protocol::led::Config::Steady(b) => {
+                            self.combo.set_active_id(Some("0"));
+                            self.model.current_config_combo_id = Some(0);
 
-                        self.steady_controls
-                            .emit(steady_controls::Msg::SetInitial(b));
-                        self.steady_controls
-                            .emit(steady_controls::Msg::SetVisible(true));
-                    }
-                    protocol::led::Config::Breathe(s) => {
-                        self.combo.set_active_id(Some("1"));
-                        self.model.current_config_combo_id = Some(1);
+                            self.model.effect_config = protocol::led::Config::Steady(b.clone());
 
-                        self.model.effect_config = protocol::led::Config::Breathe(s.clone());
+                            self.steady_controls
+                                .emit(steady_controls::Msg::SetInitial(b));
+                            self.steady_controls
+                                .emit(steady_controls::Msg::SetVisible(true));
+                        }
+                        protocol::led::Config::Breathe(s) => {
+                            self.combo.set_active_id(Some("1"));
+                            self.model.current_config_combo_id = Some(1);
 
-                        self.breathe_controls
-                            .emit(breathe_controls::Msg::SetInitial(s));
-                        self.breathe_controls
-                            .emit(breathe_controls::Msg::SetVisible(true));
-                    }
-                    protocol::led::Config::BlinkEffect(f, t, e) => {
-                        self.combo.set_active_id(Some("2"));
-                        self.model.current_config_combo_id = Some(2);
+                            self.model.effect_config = protocol::led::Config::Breathe(s.clone());
 
-                        self.model.effect_config =
-                            protocol::led::Config::BlinkEffect(f.clone(), t.clone(), e.clone());
+                            self.breathe_controls
+                                .emit(breathe_controls::Msg::SetInitial(s));
+                            self.breathe_controls
+                                .emit(breathe_controls::Msg::SetVisible(true));
+                        }
+                        protocol::led::Config::BlinkEffect(f, t, e) => {
+                            self.combo.set_active_id(Some("2"));
+                            self.model.current_config_combo_id = Some(2);
 
-                        self.blink_effect_controls
-                            .emit(blink_effect_controls::Msg::SetInitial(f, t, e));
-                        self.blink_effect_controls
-                            .emit(blink_effect_controls::Msg::SetVisible(true));
-                    }
-                    protocol::led::Config::SteadyEffect(t, e) => {
-                        self.combo.set_active_id(Some("3"));
+                            self.model.effect_config =
+                                protocol::led::Config::BlinkEffect(f.clone(), t.clone(), e.clone());
+
+                            self.blink_effect_controls
+                                .emit(blink_effect_controls::Msg::SetInitial(f, t, e));
+                            self.blink_effect_controls
+                                .emit(blink_effect_controls::Msg::SetVisible(true));
+                        }
+                        protocol::led::Config::SteadyEffect(t, e) => {
+                            self.combo.set_active_id(Some("3"));
+                            self.model.current_config_combo_id = Some(3);
+
+                            self.model.effect_config = protocol::led::Config::SteadyEffect(t, e);
+                        }
                     }
-                },
+
+                    self.model.preview_start = std::time::Instant::now();
+                }
                 ConfigComboChanged(id) => {
                     let id = id.as_str().parse().unwrap();
 
@@ -271,12 +308,8 @@ pub mod page {
                                 .hexpand(true)
                                 .build();
 
-                            let labels = [
-                                "5%", "15%", "25%", "35%", "45%", "55%", "65%", "75%", "85%",
-                                "100%",
-                            ];
-                            for n in 1..11 {
-                                combo.append(Some(&n.to_string()), labels[n - 1]);
+                            for b in protocol::led::steady::Brightnes::iter() {
+                                combo.append(Some(&(b as u8).to_string()), &b.to_string());
                             }
 
                             combo.set_active_id(Some(&(b as i32).to_string()));
@@ -312,8 +345,8 @@ pub mod page {
                                 .hexpand(true)
                                 .build();
 
-                            for n in 1..6 {
-                                combo.append(Some(&n.to_string()), &format!("{}s", n + 3));
+                            for s in protocol::led::breathe::Speed::iter() {
+                                combo.append(Some(&(s as u8).to_string()), &s.to_string());
                             }
 
                             combo.set_active_id(Some(&(s as i32).to_string()));
@@ -350,8 +383,8 @@ pub mod page {
                                     .hexpand(true)
                                     .build();
 
-                                for n in 1..11 {
-                                    combo.append(Some(&n.to_string()), &format!("{}Hz", n));
+                                for f in protocol::led::blink::Frequency::iter() {
+                                    combo.append(Some(&(f as u8).to_string()), &f.to_string());
                                 }
 
                                 combo.set_active_id(Some(&(f as i32).to_string()));
@@ -382,8 +415,8 @@ pub mod page {
                                     .hexpand(true)
                                     .build();
 
-                                for n in 1..11 {
-                                    combo.append(Some(&n.to_string()), &format!("{}", n));
+                                for t in protocol::led::blink::Times::iter() {
+                                    combo.append(Some(&(t as u8).to_string()), &t.to_string());
                                 }
 
                                 combo.set_active_id(Some(&(f as i32).to_string()));
@@ -470,6 +503,22 @@ pub mod page {
                     self.controls.add(&ui);
                     self.model.current_controls = Some((ui, cbs));
                 }
+                ConfigChanged(_) => self.model.preview_start = std::time::Instant::now(),
+                SetPreviewColor(rgb) => self.model.preview_color = rgb,
+                PreviewTick => {
+                    if !self.image.get_mapped() {
+                        return;
+                    }
+
+                    let frame = gm4_core::simulation::frame_at(
+                        &self.model.effect_config,
+                        self.model.preview_color,
+                        self.model.preview_start.elapsed(),
+                    );
+                    let pxb = crate::recolor_pixbuf(&self.model.org_pxb, frame.color, frame.level);
+
+                    self.image.set_from_pixbuf(Some(&pxb));
+                }
                 _ => {}
             }
         }
@@ -484,6 +533,9 @@ pub mod page {
                 margin_start: 20,
                 margin_end: 20,
 
+                #[name="image"]
+                gtk::Image{},
+
                 #[name="combo"]
                 gtk::ComboBoxText{
                     changed(cb) => {
@@ -24,11 +24,9 @@ pub struct Model {
 #[widget]
 impl Widget for SteadyControls {
     fn init_view(&mut self) {
-        let labels = [
-            "5%", "15%", "25%", "35%", "45%", "55%", "65%", "75%", "85%", "100%",
-        ];
-        for n in 1..11 {
-            self.combo.append(Some(&n.to_string()), labels[n - 1]);
+        for b in protocol::led::steady::Brightnes::iter() {
+            self.combo
+                .append(Some(&(b as u8).to_string()), &b.to_string());
         }
 
         // self.combo.set_active_id(Some(&(b as i32).to_string()));
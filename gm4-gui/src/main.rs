@@ -22,3 +22,33 @@ pub fn rgba_to_arr(rgba: gdk::RGBA) -> [u8; 3] {
         (rgba.blue * 255.0).round() as u8,
     ]
 }
+
+/// Paints the LED area of `org` with `rgb`, dimmed to `level` (`0.0..=1.0`)
+pub fn recolor_pixbuf(org: &gdk_pixbuf::Pixbuf, rgb: [u8; 3], level: f64) -> gdk_pixbuf::Pixbuf {
+    let pxb = org.copy().unwrap();
+    let mut iter = unsafe { pxb.get_pixels() }.iter_mut();
+
+    let my_hsl = hsl::HSL::from_rgb(&rgb);
+
+    while let Some(r) = iter.next() {
+        let g = iter.next().unwrap();
+        let b = iter.next().unwrap();
+        let _ = iter.next().unwrap();
+
+        let mut hsl = hsl::HSL::from_rgb(&[*r, *g, *b]);
+
+        hsl.h = my_hsl.h;
+        hsl.s = my_hsl.s;
+        hsl.l *= level;
+
+        let rgb = hsl.to_rgb();
+
+        if *r > 90 && *g < 75 && *b < 75 {
+            *r = rgb.0;
+            *g = rgb.1;
+            *b = rgb.2;
+        }
+    }
+
+    pxb
+}
@@ -5,11 +5,6 @@ pub mod item {
     use relm::Widget;
     use relm_derive::{widget, Msg};
 
-    const DPI_LABEL_LIST: [&str; 16] = [
-        "800", "750", "1000", "1200", "1600", "2000", "2400", "3000", "3200", "3500", "4000",
-        "4500", "5000", "5500", "6000", "7200",
-    ];
-
     #[derive(Msg, Debug)]
     pub enum Msg {
         SetDPI(u8),
@@ -82,7 +77,7 @@ pub mod item {
                     digits: 0,
                     round_digits: 0,
                     value_pos: gtk::PositionType::Right,
-                    format_value(s, v) => return DPI_LABEL_LIST[v as usize].to_string(),
+                    format_value(s, v) => return gm4_core::config::DPI_LIST[v as usize].to_string(),
                     change_value(s,t,v) => (Msg::DPIChanged(v.round() as u8),Inhibit(false)),
 
                     // enter_notify_event(cb, _) => (Msg::MouseEnter,Inhibit(false)),
@@ -176,35 +171,8 @@ pub mod page {
                 DPIChanged(id, _) => self.update(UpdateImage(id)),
                 MouseEnter(id) => self.update(UpdateImage(id)),
                 UpdateImage(id) => {
-                    let pxb = self.model.org_pxb.copy().unwrap();
-                    let mut iter = unsafe { pxb.get_pixels() }.iter_mut();
-
-                    let rgba = self.model.colors_data[id];
-                    let rgb = [
-                        (rgba.red * 255.0).round() as u8,
-                        (rgba.green * 255.0).round() as u8,
-                        (rgba.blue * 255.0).round() as u8,
-                    ];
-                    let my_hsl = hsl::HSL::from_rgb(&rgb);
-
-                    while let Some(r) = iter.next() {
-                        let g = iter.next().unwrap();
-                        let b = iter.next().unwrap();
-                        let _ = iter.next().unwrap();
-
-                        let mut hsl = hsl::HSL::from_rgb(&[*r, *g, *b]);
-
-                        hsl.h = my_hsl.h;
-                        hsl.s = my_hsl.s;
-
-                        let rgb = hsl.to_rgb();
-
-                        if *r > 90 && *g < 75 && *b < 75 {
-                            *r = rgb.0;
-                            *g = rgb.1;
-                            *b = rgb.2;
-                        }
-                    }
+                    let rgb = crate::rgba_to_arr(self.model.colors_data[id]);
+                    let pxb = crate::recolor_pixbuf(&self.model.org_pxb, rgb, 1.0);
 
                     self.image.set_from_pixbuf(Some(&pxb));
                 }
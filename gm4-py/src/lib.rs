@@ -0,0 +1,378 @@
+//! Python bindings, `import gm4`.
+//!
+//! Build with `maturin develop`, tests are in `tests/` and run with pytest.
+
+use std::convert::TryFrom;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyIndexError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use gm4_core::config::{self, DPI_LIST};
+use gm4_core::device::{self, Device};
+use gm4_core::protocol::error::ProtocolError;
+use gm4_core::protocol::led::{self, blink, breathe, neon, steady};
+use gm4_core::protocol::ConfigData;
+
+create_exception!(gm4, DeviceError, PyOSError, "USB communication failed");
+
+fn device_err(err: device::DeviceError) -> PyErr {
+    DeviceError::new_err(err.to_string())
+}
+
+fn protocol_err(err: ProtocolError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn check_id(id: usize) -> PyResult<usize> {
+    if id < 5 {
+        Ok(id)
+    } else {
+        Err(PyIndexError::new_err(format!(
+            "profile {} out of range",
+            id
+        )))
+    }
+}
+
+/// Raw 154 byte report
+#[pyclass(name = "ConfigData")]
+#[derive(Clone)]
+struct PyConfigData(ConfigData);
+
+#[pymethods]
+impl PyConfigData {
+    /// Default report of a factory reset mouse, or a copy of `data`
+    #[new]
+    #[pyo3(signature = (data = None))]
+    fn new(data: Option<&[u8]>) -> PyResult<Self> {
+        match data {
+            None => Ok(Self(ConfigData::default())),
+            Some(data) => {
+                let bytes = <[u8; 154]>::try_from(data).map_err(|_| {
+                    PyValueError::new_err(format!("expected 154 bytes, got {}", data.len()))
+                })?;
+                Ok(Self(ConfigData::from_bytes(bytes)))
+            }
+        }
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.0.as_bytes())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0.as_bytes() == other.0.as_bytes()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pyclass(name = "Profile", frozen)]
+struct PyProfile(config::Profile);
+
+#[pymethods]
+impl PyProfile {
+    #[getter]
+    fn active(&self) -> bool {
+        self.0.active
+    }
+
+    /// Index into `DPI_LIST`
+    #[getter]
+    fn dpi(&self) -> u8 {
+        self.0.dpi
+    }
+
+    /// `None` for a step that is not in `DPI_LIST`
+    #[getter]
+    fn dpi_value(&self) -> Option<u16> {
+        self.0.dpi_value()
+    }
+
+    #[getter]
+    fn rgb(&self) -> (u8, u8, u8) {
+        let [r, g, b] = self.0.rgb;
+        (r, g, b)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+macro_rules! led_enum {
+    ($py:ident, $name:literal, $ty:ty, $unit:ident: $unit_ty:ty) => {
+        #[pyclass(name = $name, frozen)]
+        #[derive(Clone)]
+        struct $py($ty);
+
+        #[pymethods]
+        impl $py {
+            /// Parses `"2.5s"`, `"85%"`, `"3hz"` and the like, plain numbers work too
+            #[new]
+            fn new(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+                let value = value.str()?;
+                value.to_str()?.parse().map(Self).map_err(protocol_err)
+            }
+
+            /// Every value, in ascending order
+            #[staticmethod]
+            fn all() -> Vec<Self> {
+                <$ty>::iter().map(Self).collect()
+            }
+
+            #[getter]
+            fn $unit(&self) -> $unit_ty {
+                self.0.$unit()
+            }
+
+            fn __str__(&self) -> String {
+                self.0.to_string()
+            }
+
+            fn __repr__(&self) -> String {
+                format!("{}('{}')", $name, self.0)
+            }
+
+            fn __eq__(&self, other: &Self) -> bool {
+                self.0.to_raw() == other.0.to_raw()
+            }
+
+            fn __hash__(&self) -> u64 {
+                self.0.to_raw() as u64
+            }
+        }
+    };
+}
+
+led_enum!(PyBrightness, "Brightness", steady::Brightnes, percent: u8);
+led_enum!(PyEffectTime, "EffectTime", steady::EffectTime, seconds: f64);
+led_enum!(PyBreatheSpeed, "BreatheSpeed", breathe::Speed, seconds: f64);
+led_enum!(PyNeonSpeed, "NeonSpeed", neon::Speed, seconds: f64);
+led_enum!(PyBlinkFrequency, "BlinkFrequency", blink::Frequency, hz: u8);
+led_enum!(PyBlinkTimes, "BlinkTimes", blink::Times, count: u8);
+
+/// Effect that follows blinking or the steady phase
+#[pyclass(name = "Effect", frozen)]
+#[derive(Clone)]
+struct PyEffect(led::Effect);
+
+#[pymethods]
+impl PyEffect {
+    #[staticmethod]
+    fn respiration(speed: PyBreatheSpeed) -> Self {
+        Self(led::Effect::Respiration(speed.0))
+    }
+
+    #[staticmethod]
+    fn steady(brightness: PyBrightness) -> Self {
+        Self(led::Effect::Steady(brightness.0))
+    }
+
+    #[staticmethod]
+    fn neon(speed: PyNeonSpeed) -> Self {
+        Self(led::Effect::Neon(speed.0))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pyclass(name = "LedConfig", frozen)]
+#[derive(Clone)]
+struct PyLedConfig(led::Config);
+
+#[pymethods]
+impl PyLedConfig {
+    #[staticmethod]
+    fn steady(brightness: PyBrightness) -> Self {
+        Self(led::Config::Steady(brightness.0))
+    }
+
+    #[staticmethod]
+    fn breathe(speed: PyBreatheSpeed) -> Self {
+        Self(led::Config::Breathe(speed.0))
+    }
+
+    #[staticmethod]
+    fn blink(frequency: PyBlinkFrequency, times: PyBlinkTimes, effect: PyEffect) -> Self {
+        Self(led::Config::BlinkEffect(frequency.0, times.0, effect.0))
+    }
+
+    #[staticmethod]
+    fn steady_effect(time: PyEffectTime, effect: PyEffect) -> Self {
+        Self(led::Config::SteadyEffect(time.0, effect.0))
+    }
+
+    /// `(led_mode, led_arg1, led_arg2, led_arg3)` report bytes
+    fn to_raw(&self) -> (u8, u8, u8, u8) {
+        self.0.to_raw()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0.to_raw() == other.0.to_raw()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pyclass(name = "Config")]
+struct PyConfig(config::Config);
+
+#[pymethods]
+impl PyConfig {
+    #[new]
+    #[pyo3(signature = (data = None))]
+    fn new(data: Option<PyConfigData>) -> PyResult<Self> {
+        config::Config::try_new(data.map(|d| d.0).unwrap_or_default())
+            .map(Self)
+            .map_err(protocol_err)
+    }
+
+    #[getter]
+    fn profiles(&self) -> Vec<PyProfile> {
+        self.0.profiles().iter().map(|p| PyProfile(*p)).collect()
+    }
+
+    #[getter]
+    fn led_config(&self) -> PyLedConfig {
+        PyLedConfig(*self.0.led_config())
+    }
+
+    #[getter]
+    fn report_rate(&self) -> u8 {
+        self.0.report_rate()
+    }
+
+    /// `dpi` is an index into `DPI_LIST`
+    fn set_profile_dpi(&mut self, id: usize, dpi: u8) -> PyResult<()> {
+        if dpi as usize >= DPI_LIST.len() {
+            return Err(PyValueError::new_err(format!("no DPI step {}", dpi)));
+        }
+        self.0.set_profile_dpi(check_id(id)?, dpi);
+        Ok(())
+    }
+
+    fn set_profile_active(&mut self, id: usize, active: bool) -> PyResult<()> {
+        self.0.set_profile_active(check_id(id)?, active);
+        Ok(())
+    }
+
+    fn set_profile_rgb(&mut self, id: usize, rgb: [u8; 3]) -> PyResult<()> {
+        self.0.set_profile_rgb(check_id(id)?, rgb);
+        Ok(())
+    }
+
+    fn set_led_effect(&mut self, config: PyLedConfig) {
+        self.0.set_led_effect(config.0);
+    }
+
+    fn set_report_rate(&mut self, report_rate: u8) {
+        self.0.set_report_rate(report_rate);
+    }
+
+    /// Report to send to the mouse
+    fn config_data(&self) -> PyConfigData {
+        let raw: &ConfigData = (&self.0).into();
+        PyConfigData(raw.clone())
+    }
+}
+
+/// The mouse, over USB
+#[pyclass(name = "MouseDevice", unsendable)]
+struct PyMouseDevice(gm4_core::usb::MouseDevice);
+
+#[pymethods]
+impl PyMouseDevice {
+    #[staticmethod]
+    fn open() -> PyResult<Self> {
+        gm4_core::usb::MouseDevice::new()
+            .map(Self)
+            .map_err(|err| device_err(err.into()))
+    }
+
+    fn read(&mut self) -> PyResult<PyConfigData> {
+        Device::read(&mut self.0)
+            .map(PyConfigData)
+            .map_err(device_err)
+    }
+
+    fn send(&mut self, data: &PyConfigData) -> PyResult<()> {
+        Device::send(&mut self.0, &data.0).map_err(device_err)
+    }
+}
+
+/// In-memory stand-in for the mouse, for tests
+#[pyclass(name = "MockDevice")]
+struct PyMockDevice(device::MockDevice);
+
+#[pymethods]
+impl PyMockDevice {
+    #[new]
+    #[pyo3(signature = (data = None))]
+    fn new(data: Option<PyConfigData>) -> Self {
+        Self(device::MockDevice::new(
+            data.map(|d| d.0).unwrap_or_default(),
+        ))
+    }
+
+    fn read(&mut self) -> PyResult<PyConfigData> {
+        self.0.read().map(PyConfigData).map_err(device_err)
+    }
+
+    fn send(&mut self, data: &PyConfigData) -> PyResult<()> {
+        self.0.send(&data.0).map_err(device_err)
+    }
+
+    /// Config currently "stored" in the mouse
+    fn config_data(&self) -> PyConfigData {
+        PyConfigData(self.0.config_data())
+    }
+
+    /// Every report sent so far, oldest first
+    fn sent(&self) -> Vec<PyConfigData> {
+        self.0.sent().into_iter().map(PyConfigData).collect()
+    }
+
+    fn reads(&self) -> usize {
+        self.0.reads()
+    }
+}
+
+/// Index of `dpi` in `DPI_LIST`, None if the mouse can't do it
+#[pyfunction]
+fn dpi_id(dpi: u16) -> Option<u8> {
+    config::dpi_id(dpi)
+}
+
+#[pymodule]
+fn gm4(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("DeviceError", m.py().get_type::<DeviceError>())?;
+    m.add("DPI_LIST", DPI_LIST.to_vec())?;
+    m.add_function(wrap_pyfunction!(dpi_id, m)?)?;
+
+    m.add_class::<PyConfigData>()?;
+    m.add_class::<PyConfig>()?;
+    m.add_class::<PyProfile>()?;
+
+    m.add_class::<PyBrightness>()?;
+    m.add_class::<PyEffectTime>()?;
+    m.add_class::<PyBreatheSpeed>()?;
+    m.add_class::<PyNeonSpeed>()?;
+    m.add_class::<PyBlinkFrequency>()?;
+    m.add_class::<PyBlinkTimes>()?;
+    m.add_class::<PyEffect>()?;
+    m.add_class::<PyLedConfig>()?;
+
+    m.add_class::<PyMouseDevice>()?;
+    m.add_class::<PyMockDevice>()?;
+
+    Ok(())
+}
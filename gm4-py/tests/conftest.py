@@ -0,0 +1,25 @@
+import os
+
+import pytest
+
+import gm4
+
+
+@pytest.fixture
+def device():
+    """The real mouse when GM4_HARDWARE=1 is set, the mock device otherwise"""
+    if os.environ.get("GM4_HARDWARE") == "1":
+        return gm4.MouseDevice.open()
+    return gm4.MockDevice()
+
+
+@pytest.fixture
+def known_state(device):
+    """Puts the mouse in the default config for the test and restores its own afterwards"""
+    original = device.read()
+
+    config = gm4.Config()
+    device.send(config.config_data())
+    yield config
+
+    device.send(original)
@@ -0,0 +1,85 @@
+import pytest
+
+import gm4
+
+
+def test_config_data_bytes():
+    data = gm4.ConfigData()
+    raw = bytes(data)
+    assert len(raw) == 154
+    assert gm4.ConfigData(raw) == data
+
+    with pytest.raises(ValueError):
+        gm4.ConfigData(b"\x00" * 10)
+
+
+def test_config_malformed():
+    raw = bytearray(bytes(gm4.ConfigData()))
+    raw[93] = 0x99  # led_mode
+    with pytest.raises(ValueError):
+        gm4.Config(gm4.ConfigData(bytes(raw)))
+
+    raw = bytearray(bytes(gm4.ConfigData()))
+    raw[74] = 0x00  # profiles_dpi[0], below the first step
+    with pytest.raises(ValueError):
+        gm4.Config(gm4.ConfigData(bytes(raw)))
+
+
+def test_config_setters():
+    config = gm4.Config()
+
+    config.set_profile_rgb(1, (1, 2, 3))
+    config.set_profile_dpi(1, gm4.dpi_id(3200))
+    config.set_profile_active(4, False)
+    config.set_report_rate(7)
+
+    # Same values after a round trip through the raw report
+    config = gm4.Config(gm4.ConfigData(bytes(config.config_data())))
+    assert config.profiles[1].rgb == (1, 2, 3)
+    assert config.profiles[1].dpi_value == 3200
+    assert not config.profiles[4].active
+    assert config.report_rate == 7
+
+    with pytest.raises(IndexError):
+        config.set_profile_rgb(5, (0, 0, 0))
+    with pytest.raises(ValueError):
+        config.set_profile_dpi(0, len(gm4.DPI_LIST))
+
+
+def test_led_enums():
+    assert gm4.Brightness("85%").percent == 85
+    assert gm4.Brightness(85) == gm4.Brightness("85")
+    assert str(gm4.BlinkFrequency("3hz")) == "3Hz"
+    assert gm4.NeonSpeed("2.5s").seconds == 2.5
+    assert [t.count for t in gm4.BlinkTimes.all()] == list(range(1, 11))
+
+    with pytest.raises(ValueError):
+        gm4.BreatheSpeed("1s")
+
+
+def test_led_config():
+    config = gm4.Config()
+    effect = gm4.LedConfig.blink(
+        gm4.BlinkFrequency(2),
+        gm4.BlinkTimes(4),
+        gm4.Effect.neon(gm4.NeonSpeed("1s")),
+    )
+    config.set_led_effect(effect)
+
+    assert gm4.Config(config.config_data()).led_config == effect
+    assert effect.to_raw()[0] == 0x44
+
+
+def test_mock_device():
+    device = gm4.MockDevice()
+    config = gm4.Config(device.read())
+    config.set_profile_rgb(0, (9, 8, 7))
+    device.send(config.config_data())
+
+    assert device.reads() == 1
+    assert device.sent() == [config.config_data()]
+    assert gm4.Config(device.config_data()).profiles[0].rgb == (9, 8, 7)
+
+
+def test_known_state(device, known_state):
+    assert device.read() == known_state.config_data()